@@ -25,9 +25,76 @@ pub use nd::IxDyn;
 pub struct Histogram<A, D> {
     edges: Vec<Vec<BinEdges>>,
     pub counts: nd::Array<A, IxDyn>,
+    /// See `counts_with_flow()`
+    counts_with_flow: Option<nd::Array<A, IxDyn>>,
+    /// See `sumw2()`
+    sumw2: Option<nd::Array<A, IxDyn>>,
     dim: PhantomData<D>,
 }
 
+/// The number of dimensions of a `Histogram<A, [usize; N]>`
+pub trait NDim {
+    const N: usize;
+}
+
+impl<A, D> Histogram<A, D>
+where
+    A: Clone,
+    D: NDim,
+{
+    /// Create a histogram from the edges of the bins along each axis
+    /// and the counts of all bins, including an under- and an
+    /// overflow bin at both ends of each axis. This is the layout
+    /// used by other libraries such as ROOT. The sum of squared
+    /// weights, if given, must have the same shape as the counts.
+    /// Returns `None` if the number of axes or the shapes do not
+    /// match.
+    pub fn from_bins_with_flow(
+        edges: &[Vec<f64>],
+        counts_with_flow: nd::Array<A, IxDyn>,
+        sumw2: Option<nd::Array<A, IxDyn>>,
+    ) -> Option<Histogram<A, D>> {
+        let shape: Vec<_> = edges
+            .iter()
+            .map(|e| e.len().saturating_sub(1) + 2)
+            .collect();
+        if edges.len() != D::N
+            || edges.iter().any(|e| e.len() < 2)
+            || counts_with_flow.shape() != shape.as_slice()
+            || sumw2.iter().any(|s| s.shape() != shape.as_slice())
+        {
+            return None;
+        }
+        let mut counts = counts_with_flow.view();
+        for (axis, len) in shape.iter().enumerate() {
+            counts.slice_axis_inplace(Axis(axis), nd::Slice::from(1..len - 1));
+        }
+        Some(Histogram {
+            edges: edges.iter().map(|e| edges_to_bins(e)).collect(),
+            counts: counts.to_owned(),
+            counts_with_flow: Some(counts_with_flow),
+            sumw2,
+            dim: PhantomData,
+        })
+    }
+
+    /// Counts of all bins including the under- and overflow bin at
+    /// both ends of each axis. Only known for histograms created with
+    /// `from_bins_with_flow`. The `fill` methods and `add` keep them
+    /// up to date, but changes made to `counts` directly are not
+    /// reflected.
+    pub fn counts_with_flow(&self) -> Option<&nd::Array<A, IxDyn>> {
+        self.counts_with_flow.as_ref()
+    }
+
+    /// Sum of the squared weights of each bin, including the under-
+    /// and overflow bins as in `counts_with_flow`, if known. Each
+    /// filled entry has a weight of one.
+    pub fn sumw2(&self) -> Option<&nd::Array<A, IxDyn>> {
+        self.sumw2.as_ref()
+    }
+}
+
 pub trait Centers {
     fn centers(&self, axis: usize) -> Vec<f64>;
 }
@@ -52,6 +119,10 @@ impl<A, D> Widths for Histogram<A, D> {
 
 macro_rules! impl_histogram {
     ($N:expr, $($idx:expr)*) => {
+        impl NDim for [usize; $N] {
+            const N: usize = $N;
+        }
+
         impl<A> Histogram<A, [usize; $N]>
         where
             A: Copy + libnum::Zero + Add + AddAssign + libnum::One + PartialOrd,
@@ -75,20 +146,66 @@ macro_rules! impl_histogram {
                 Some(idxs)
             }
 
+            /// Find indices of bins along each axis, where 0 is the
+            /// underflow bin and the number of bins plus one is the
+            /// overflow bin of an axis
+            fn find_bin_indices_with_flow(&self, values: &[f64; $N]) -> [usize; $N] {
+                let mut idxs = [0; $N];
+                for dim in 0..$N {
+                    idxs[dim] = match self.find_bin_index_axis(dim, values[dim]) {
+                        Some(idx) => idx + 1,
+                        None if values[dim] < self.edges[dim][0].lower => 0,
+                        None => self.edges[dim].len() + 1,
+                    };
+                }
+                idxs
+            }
+
+            /// Count one entry in the bin with the given indices as
+            /// returned by `find_bin_indices_with_flow`
+            fn count_with_flow(&mut self, idxs: [usize; $N]) {
+                if (0..$N).all(|dim| idxs[dim] >= 1 && idxs[dim] <= self.edges[dim].len()) {
+                    let mut inner = idxs;
+                    for idx in inner.iter_mut() {
+                        *idx -= 1;
+                    }
+                    self.counts[inner.as_ref()] += A::one();
+                }
+                if let Some(counts) = self.counts_with_flow.as_mut() {
+                    counts[idxs.as_ref()] += A::one();
+                }
+                if let Some(sumw2) = self.sumw2.as_mut() {
+                    sumw2[idxs.as_ref()] += A::one();
+                }
+            }
+
+            /// Count one entry in the bin with the given indices
+            fn count(&mut self, idxs: [usize; $N]) {
+                let mut with_flow = idxs;
+                for idx in with_flow.iter_mut() {
+                    *idx += 1;
+                }
+                self.count_with_flow(with_flow);
+            }
+
             pub fn fill(&mut self, values: &[f64; $N]) {
-                if let Some(idxs) = self.find_bin_indices(values) {
-                    self.counts[idxs.as_ref()] += A::one();
+                match self.find_bin_indices(values) {
+                    Some(idxs) => self.count(idxs),
+                    None => {
+                        let idxs = self.find_bin_indices_with_flow(values);
+                        self.count_with_flow(idxs);
+                    }
                 }
             }
             pub fn fill_by_index<I>(&mut self, indices: [usize; $N]) {
-                self.counts[indices.as_ref()] += A::one();
+                self.count(indices);
             }
             pub fn fill_by_index_bulk<T>(&mut self, indices_slice: T)
             where
                 T: IntoIterator<Item = [usize; $N]>,
             {
                 for idxs in indices_slice {
-                    self.counts[idxs.as_ref()] += A::one();
+                    self.count(idxs);
                 }
             }
             pub fn fill_bulk<T>(&mut self, values: T, npairs: usize)
@@ -96,15 +213,31 @@ macro_rules! impl_histogram {
                 T: IntoIterator<Item = [f64; $N]>,
             {
                 let mut indices = Vec::<[usize; $N]>::with_capacity(npairs);
-                indices.extend(values.into_iter().filter_map(|v| self.find_bin_indices(&v)));
+                indices.extend(
+                    values
+                        .into_iter()
+                        .map(|v| self.find_bin_indices_with_flow(&v)),
+                );
                 for idxs in indices {
-                    self.counts[idxs.as_ref()] += A::one();
+                    self.count_with_flow(idxs);
                 }
             }
 
             pub fn add(&mut self, other: &Histogram<A, [usize; $N]>) {
                 // assert_eq!(self.edges.as_slice(), other.edges.as_slice());
                 self.counts += &other.counts;
+                match (
+                    self.counts_with_flow.as_mut(),
+                    other.counts_with_flow.as_ref(),
+                ) {
+                    (Some(counts), Some(other)) => *counts += other,
+                    // Unknown for one of the histograms
+                    _ => self.counts_with_flow = None,
+                }
+                match (self.sumw2.as_mut(), other.sumw2.as_ref()) {
+                    (Some(sumw2), Some(other)) => *sumw2 += other,
+                    _ => self.sumw2 = None,
+                }
             }
 
             /// Dump histogram (without edges) to a file of `name`.
@@ -131,10 +264,10 @@ macro_rules! impl_histogram {
             {
                 let indices: Vec<_> = values
                     .into_iter()
-                    .filter_map(|v| self.find_bin_indices(&v))
+                    .map(|v| self.find_bin_indices_with_flow(&v))
                     .collect();
                 for idxs in indices {
-                    self.counts[idxs.as_ref()] += A::one();
+                    self.count_with_flow(idxs);
                 }
             }
         }
@@ -186,6 +319,8 @@ macro_rules! impl_histogram_builder {
                 let counts = nd::ArrayD::<A>::zeros(IxDyn(shape.as_ref()));
                 Some(Histogram::<A, [usize; $N]> {
                     counts: counts,
+                    counts_with_flow: None,
+                    sumw2: None,
                     edges: edges,
                     dim: PhantomData,
                 })
@@ -353,6 +488,56 @@ mod tests {
         assert_eq!(h.centers(0), &[-0.5, 0.5]);
     }
 
+    #[test]
+    fn from_bins_with_flow() {
+        let edges = vec![vec![0., 1., 3.], vec![-1., 1.]];
+        let counts =
+            nd::Array::from_shape_vec(IxDyn(&[4, 3]), (0..12).map(f64::from).collect()).unwrap();
+        let h = Histogram::<f64, [usize; 2]>::from_bins_with_flow(&edges, counts.clone(), None)
+            .unwrap();
+        assert_eq!(h.counts, nd::arr2(&[[4.], [7.]]).into_dyn());
+        assert_eq!(h.counts_with_flow(), Some(&counts));
+        assert_eq!(h.widths(0), &[1., 2.]);
+        assert_eq!(h.find_bin_index_axis(0, 2.), Some(1));
+
+        // Wrong number of axes or shapes
+        assert!(
+            Histogram::<f64, [usize; 1]>::from_bins_with_flow(&edges, counts.clone(), None)
+                .is_none()
+        );
+        assert!(Histogram::<f64, [usize; 2]>::from_bins_with_flow(
+            &edges,
+            counts.clone(),
+            Some(nd::ArrayD::zeros(IxDyn(&[2, 1])))
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn filling_with_flow() {
+        let edges = vec![vec![0., 1., 3.]];
+        let zeros = nd::ArrayD::<f64>::zeros(IxDyn(&[4]));
+        let mut h =
+            Histogram::<f64, [usize; 1]>::from_bins_with_flow(&edges, zeros.clone(), Some(zeros))
+                .unwrap();
+        h.fill(&[-1.]);
+        h.fill(&[2.]);
+        h.fill(&[5.]);
+        h.fill_by_index::<()>([0]);
+        assert_eq!(h.counts, nd::arr1(&[1., 1.]).into_dyn());
+        let with_flow = nd::arr1(&[1., 1., 1., 1.]).into_dyn();
+        assert_eq!(h.counts_with_flow(), Some(&with_flow));
+        assert_eq!(h.sumw2(), Some(&with_flow));
+
+        let other = HistogramBuilder::<[usize; 1]>::new()
+            .add_variable_width_axis(&edges[0])
+            .build::<f64>()
+            .unwrap();
+        h.add(&other);
+        assert_eq!(h.counts_with_flow(), None);
+        assert_eq!(h.sumw2(), None);
+    }
+
     #[test]
     fn bin_edges() {
         let be = BinEdges {
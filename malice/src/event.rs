@@ -9,8 +9,7 @@ use nom::number::complete::*;
 use nom::sequence::tuple;
 
 use root_io::core::parsers::{parse_custom_mantissa, parse_tobjarray_of_tnameds};
-use root_io::stream_zip;
-use root_io::tree_reader::Tree;
+use root_io::tree_reader::{FromTree, Tree};
 
 use crate::primary_vertex::PrimaryVertex;
 use crate::track::{Flags, ItsClusters, Track, TrackParameters};
@@ -26,21 +25,49 @@ bitflags! {
 }
 
 /// A model for a subset of an event as stored in the published data
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, FromTree)]
 pub struct Event {
+    #[branch("PrimaryVertex.AliVertex.fPosition[3]")]
     primaryvertex_alivertex_fposition: (f32, f32, f32),
+    #[branch("PrimaryVertex.AliVertex.fNContributors")]
     primaryvertex_alivertex_fncontributors: i32,
+    #[branch("AliESDRun.fRunNumber")]
     aliesdrun_frunnumber: i32,
+    #[branch("AliESDRun.fTriggerClasses", parser = parse_tobjarray_of_tnameds)]
     aliesdrun_ftriggerclasses: Vec<String>,
+    #[branch("AliESDHeader.fTriggerMask")]
     aliesdheader_ftriggermask: u64,
+    #[branch("Tracks.fX", counter = "Tracks")]
     tracks_fx: Vec<f32>,
+    #[branch(
+        "Tracks.fP[5]",
+        counter = "Tracks",
+        parser = |i| map(tuple((be_f32, be_f32, be_f32, be_f32, be_f32)), |p| {
+            TrackParameters::new(&p)
+        })(i)
+    )]
     tracks_fp: Vec<TrackParameters>,
+    #[branch("Tracks.fAlpha", counter = "Tracks")]
     tracks_falpha: Vec<f32>,
+    #[branch(
+        "Tracks.fFlags",
+        counter = "Tracks",
+        parser = |i| map(be_u64, |uint| Flags::from_bits(uint).unwrap())(i)
+    )]
     tracks_fflags: Vec<Flags>,
+    #[branch("Tracks.fITSchi2", counter = "Tracks", parser = |i| parse_custom_mantissa(i, 8))]
     tracks_fitschi2: Vec<f32>,
+    #[branch("Tracks.fITSncls", counter = "Tracks")]
     tracks_fitsncls: Vec<i8>,
+    #[branch(
+        "Tracks.fITSClusterMap",
+        counter = "Tracks",
+        parser = |i| map(be_u8, |uint| ItsClusters::from_bits(uint).unwrap())(i)
+    )]
     tracks_fitsclustermap: Vec<ItsClusters>,
+    #[branch("Tracks.fTPCchi2", counter = "Tracks", parser = |i| parse_custom_mantissa(i, 8))]
     tracks_ftpcchi2: Vec<f32>,
+    #[branch("Tracks.fTPCncls", counter = "Tracks")]
     tracks_ftpcncls: Vec<u16>,
 }
 
@@ -123,87 +150,13 @@ impl Event {
     }
 }
 
-pub async fn event_stream_from_tree(t: &Tree) -> Result<impl Stream<Item = Event>, Error> {
-    let track_counter: Vec<_> = t
-        .branch_by_name("Tracks")?
-        .as_fixed_size_iterator(|i| be_u32(i))
-        .collect::<Vec<_>>()
-        .await;
-    let s = stream_zip!(
-        t.branch_by_name("AliESDRun.fRunNumber")?
-            .as_fixed_size_iterator(|i| be_i32(i)),
-        t.branch_by_name("AliESDRun.fTriggerClasses")?
-            .as_fixed_size_iterator(parse_tobjarray_of_tnameds),
-        t.branch_by_name("AliESDHeader.fTriggerMask")?
-            .as_fixed_size_iterator(|i| be_u64(i)),
-        t.branch_by_name("PrimaryVertex.AliVertex.fPosition[3]")?
-            .as_fixed_size_iterator(|i| tuple((be_f32, be_f32, be_f32))(i)),
-        t.branch_by_name("PrimaryVertex.AliVertex.fNContributors")?
-            .as_fixed_size_iterator(|i| be_i32(i)),
-        t.branch_by_name("Tracks.fX")?
-            .as_var_size_iterator(|i| be_f32(i), &track_counter),
-        t.branch_by_name("Tracks.fP[5]")?.as_var_size_iterator(
-            |i| map(tuple((be_f32, be_f32, be_f32, be_f32, be_f32)), |p| {
-                TrackParameters::new(&p)
-            })(i),
-            &track_counter
-        ),
-        t.branch_by_name("Tracks.fAlpha")?
-            .as_var_size_iterator(|i| be_f32(i), &track_counter),
-        t.branch_by_name("Tracks.fFlags")?.as_var_size_iterator(
-            |i| map(be_u64, |uint| Flags::from_bits(uint).unwrap())(i),
-            &track_counter
-        ),
-        t.branch_by_name("Tracks.fITSchi2")?
-            .as_var_size_iterator(|i| parse_custom_mantissa(i, 8), &track_counter),
-        t.branch_by_name("Tracks.fITSncls")?
-            .as_var_size_iterator(|i| be_i8(i), &track_counter),
-        t.branch_by_name("Tracks.fITSClusterMap")?
-            .as_var_size_iterator(
-                |i| map(be_u8, |uint| ItsClusters::from_bits(uint).unwrap())(i),
-                &track_counter
-            ),
-        t.branch_by_name("Tracks.fTPCncls")?
-            .as_var_size_iterator(|i| be_u16(i), &track_counter),
-        t.branch_by_name("Tracks.fTPCchi2")?
-            .as_var_size_iterator(|i| parse_custom_mantissa(i, 8), &track_counter),
-    )
-    .map(
-        |(
-            aliesdrun_frunnumber,
-            aliesdrun_ftriggerclasses,
-            aliesdheader_ftriggermask,
-            primaryvertex_alivertex_fposition,
-            primaryvertex_alivertex_fncontributors,
-            tracks_fx,
-            tracks_fp,
-            tracks_falpha,
-            tracks_fflags,
-            tracks_fitschi2,
-            tracks_fitsncls,
-            tracks_fitsclustermap,
-            tracks_ftpcncls,
-            tracks_ftpcchi2,
-        )| {
-            Event {
-                aliesdrun_frunnumber,
-                aliesdrun_ftriggerclasses,
-                aliesdheader_ftriggermask,
-                primaryvertex_alivertex_fposition,
-                primaryvertex_alivertex_fncontributors,
-                tracks_fx,
-                tracks_fp,
-                tracks_falpha,
-                tracks_fflags,
-                tracks_fitschi2,
-                tracks_fitsncls,
-                tracks_fitsclustermap,
-                tracks_ftpcchi2,
-                tracks_ftpcncls,
-            }
-        },
-    );
-    Ok(s)
+/// Stream over all events of the given ESD tree. An error is
+/// yielded for each event which cannot be read, e.g. because its
+/// basket is corrupt; the following events are still read.
+pub async fn event_stream_from_tree(
+    t: &Tree,
+) -> Result<impl Stream<Item = Result<Event, Error>>, Error> {
+    Ok(Event::stream_from_tree(t).await?.map_err(Error::from))
 }
 
 /// Convert a given trigger description to a `TriggerMask`. This
@@ -52,9 +52,9 @@ pub use crate::utils::{default_event_filter, default_track_filter, is_hybrid_tra
 
 use failure::Error;
 use futures::prelude::*;
-use futures::stream::{iter, StreamExt};
+use futures::stream::StreamExt;
 
-use root_io::{RootFile, Source};
+use root_io::{Chain, RootFile, Source};
 
 use std::pin::Pin;
 use std::sync::mpsc::sync_channel;
@@ -77,7 +77,7 @@ where
     }();
     // Turn Result<Stream> into a Stream of Results
     match tmp.await {
-        Ok(s) => s.map(Ok).boxed(),
+        Ok(s) => s.boxed(),
         Err(err) => stream::iter(vec![Err(err)]).boxed(),
     }
 }
@@ -86,10 +86,12 @@ where
 /// data. Produces an iterator over events from an iterator over files
 /// (either local or remote).
 ///
-/// The necessary IO is done on a separate thread such that IO bound
+/// The files are read as a `root_io::Chain` of their `esdTree`s. The
+/// necessary IO is done on a separate thread such that IO bound
 /// tasks do not interfere with the CPU bound tasks of the analysis
-/// itself. If an IO error is encountered the respective file will be
-/// skipped.
+/// itself. Events which cannot be read, e.g. because their file
+/// cannot be opened or their basket is corrupt, are skipped with a
+/// message on stderr.
 ///
 /// This function is not available on the wasm32 target and must not
 /// be called from an asynchronous context itself.
@@ -104,16 +106,21 @@ where
     spawn(|| {
         let mut rt = tokio::runtime::Runtime::new().expect("Failed to start IO runtime");
         rt.block_on(async move {
-            iter(sources)
-                .then(event_stream_from_esd_file)
-                .flatten()
-                .try_for_each(|event| async {
-                    // Errors if the receiving end has hung up
-                    sender.send(event).map_err(Into::into)
-                })
-                .await
-        })
-        .expect("Failed to start IO processing.");
+            let chain = Chain::new("esdTree", sources);
+            let events = chain.stream_from_tree::<Event>();
+            futures::pin_mut!(events);
+            while let Some(event) = events.next().await {
+                match event {
+                    Ok(event) => {
+                        // Errors if the receiving end has hung up
+                        if sender.send(event).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => eprintln!("Skipping event: {}", e),
+                }
+            }
+        });
     });
     receiver.into_iter()
 }
@@ -147,6 +154,7 @@ mod tests {
         let mut cnt_tracks = 0;
         let mut cnt_tracks_valid = 0;
         events
+            .map(|ev| ev.unwrap())
             .filter(|ev| future::ready(default_event_filter(ev)))
             .for_each(|ev| {
                 cnt_evts += 1;
@@ -0,0 +1,228 @@
+//! Derive macros for the `root-io` crate. Use them through the
+//! re-exports in `root_io::tree_reader`.
+extern crate proc_macro;
+
+use proc_macro2::TokenStream;
+use quote::{quote, quote_spanned};
+use syn::parse::ParseStream;
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input, Data, DeriveInput, Expr, Fields, GenericArgument, Ident, LitStr,
+    PathArguments, Token, Type,
+};
+
+/// Derive `root_io::tree_reader::FromTree` for a struct with named
+/// fields. Each field is read from the branch given in its
+/// `#[branch("...")]` attribute, or from the branch with the same
+/// name as the field if the attribute is missing. The attribute
+/// takes the following optional arguments:
+///
+/// - `counter = "name"`: The field is a `Vec<T>` whose number of
+///   elements in each entry is given by the branch `name`
+/// - `jagged`: The field is a `Vec<T>` whose entries are split using
+///   the entry offsets stored in the baskets
+/// - `parser = expr`: Parser used instead of `FromBranch::parse`;
+///   for `Vec` fields with a `counter` or `jagged`, this parses a
+///   single element
+///
+/// ```ignore
+/// #[derive(FromTree)]
+/// struct Event {
+///     #[branch("AliESDRun.fRunNumber")]
+///     run_number: i32,
+///     #[branch("Tracks.fP[5]", counter = "Tracks")]
+///     tracks_fp: Vec<[f32; 5]>,
+///     #[branch("Tracks.fITSchi2", counter = "Tracks", parser = |i| parse_custom_mantissa(i, 8))]
+///     tracks_fitschi2: Vec<f32>,
+/// }
+/// ```
+#[proc_macro_derive(FromTree, attributes(branch))]
+pub fn derive_from_tree(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    from_tree(&input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+/// How a field is read from its branch
+enum Column {
+    /// One element per entry
+    Fixed,
+    /// Number of elements per entry given by another branch
+    Counted(LitStr),
+    /// Entries split by the offsets stored in the baskets
+    Jagged,
+}
+
+/// Content of the `#[branch(...)]` attribute of a field
+struct BranchAttr {
+    name: LitStr,
+    column: Column,
+    parser: Option<Expr>,
+}
+
+impl BranchAttr {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        let mut column = Column::Fixed;
+        let mut parser = None;
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            let key: Ident = input.parse()?;
+            match key.to_string().as_str() {
+                "counter" => {
+                    input.parse::<Token![=]>()?;
+                    column = Column::Counted(input.parse()?);
+                }
+                "jagged" => column = Column::Jagged,
+                "parser" => {
+                    input.parse::<Token![=]>()?;
+                    parser = Some(input.parse()?);
+                }
+                _ => {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        "Expected `counter = \"...\"`, `jagged`, or `parser = ...`",
+                    ))
+                }
+            }
+        }
+        Ok(BranchAttr {
+            name,
+            column,
+            parser,
+        })
+    }
+}
+
+/// The element type `T` of a `Vec<T>`
+fn vec_element(ty: &Type) -> Option<&Type> {
+    let seg = match ty {
+        Type::Path(p) => p.path.segments.last()?,
+        _ => return None,
+    };
+    match &seg.arguments {
+        PathArguments::AngleBracketed(args) if seg.ident == "Vec" && args.args.len() == 1 => {
+            match args.args.first()? {
+                GenericArgument::Type(t) => Some(t),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn from_tree(input: &DeriveInput) -> syn::Result<TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(s) => match &s.fields {
+            Fields::Named(f) => &f.named,
+            _ => {
+                return Err(syn::Error::new(
+                    input.span(),
+                    "`FromTree` requires a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new(
+                input.span(),
+                "`FromTree` can only be derived for structs",
+            ))
+        }
+    };
+    if fields.is_empty() {
+        return Err(syn::Error::new(
+            input.span(),
+            "`FromTree` requires at least one field",
+        ));
+    }
+    let rt = quote!(::root_io::tree_reader::__private);
+
+    // Each counter branch is only read once
+    let mut counters: Vec<String> = vec![];
+    let mut read_counters = vec![];
+    let mut streams = vec![];
+    let mut names = vec![];
+    for field in fields {
+        let ident = field.ident.as_ref().expect("Named field");
+        let attr = match field.attrs.iter().find(|a| a.path.is_ident("branch")) {
+            Some(a) => a.parse_args_with(BranchAttr::parse)?,
+            None => BranchAttr {
+                name: LitStr::new(&ident.to_string(), ident.span()),
+                column: Column::Fixed,
+                parser: None,
+            },
+        };
+        let ty = &field.ty;
+        let element = || {
+            vec_element(ty).ok_or_else(|| {
+                syn::Error::new(ty.span(), "Jagged columns must be of type `Vec<_>`")
+            })
+        };
+        let parser = |t: &Type| match &attr.parser {
+            Some(p) => quote!(#p),
+            None => quote_spanned!(t.span()=> <#t as ::root_io::tree_reader::FromBranch>::parse),
+        };
+        let name = &attr.name;
+        let stream = match &attr.column {
+            Column::Fixed => {
+                let p = parser(ty);
+                quote!(#rt::fixed(tree, #name, #p)?)
+            }
+            Column::Jagged => {
+                let p = parser(element()?);
+                quote!(#rt::jagged(tree, #name, #p)?)
+            }
+            Column::Counted(counter) => {
+                let p = parser(element()?);
+                let idx = match counters.iter().position(|c| *c == counter.value()) {
+                    Some(idx) => idx,
+                    None => {
+                        counters.push(counter.value());
+                        counters.len() - 1
+                    }
+                };
+                let var = Ident::new(&format!("__counter_{}", idx), counter.span());
+                if idx == read_counters.len() {
+                    read_counters.push(quote!(let #var = #rt::counter(tree, #counter).await?;));
+                }
+                quote!(#rt::counted(tree, #name, #p, &#var)?)
+            }
+        };
+        streams.push(stream);
+        names.push(ident);
+    }
+
+    // Zip all columns into nested tuples and destructure them again
+    let mut zipped = streams[0].clone();
+    let mut pattern = {
+        let n = names[0];
+        quote!(#n)
+    };
+    for (s, n) in streams.iter().zip(names.iter()).skip(1) {
+        zipped = quote!(#rt::zip(#zipped, #s));
+        pattern = quote!((#pattern, #n));
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::root_io::tree_reader::FromTree for #name #ty_generics #where_clause {
+            fn stream_from_tree(
+                tree: &::root_io::tree_reader::Tree,
+            ) -> #rt::SourceFuture<
+                '_,
+                ::std::result::Result<#rt::EntryStream<'static, Self>, ::root_io::Error>,
+            > {
+                ::std::boxed::Box::pin(async move {
+                    #(#read_counters)*
+                    let zipped = #zipped;
+                    ::std::result::Result::Ok(#rt::map(zipped, |#pattern| Self { #(#names),* }))
+                })
+            }
+        }
+    })
+}
@@ -6,7 +6,7 @@ extern crate nom;
 use nom::number::complete::{be_i32, be_u32, be_f32};
 
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use futures::StreamExt;
+use futures::{StreamExt, TryStreamExt};
 use tokio::runtime::Runtime;
 
 use root_io::RootFile;
@@ -22,7 +22,7 @@ fn fixed_size_branch() {
             .as_fixed_size_iterator(|i| be_i32(i));
         iter.for_each(|el| async move {black_box(el);}).await
     };
-    let rt = Runtime::new().unwrap();
+    let mut rt = Runtime::new().unwrap();
     rt.block_on(fut);
 }
 
@@ -41,13 +41,44 @@ fn var_size_branch() {
             .as_var_size_iterator(|i| be_f32(i), &track_counter);
         iter.for_each(|el| async {black_box(el);}).await
     };
-    let rt = Runtime::new().unwrap();
+    let mut rt = Runtime::new().unwrap();
+    rt.block_on(fut);
+}
+
+fn fixed_size_branch_columns() {
+    let path = alice_open_data::test_file().unwrap();
+
+    let fut = async {
+        let f = RootFile::new(path.as_path()).await.expect("Failed to open file");
+        let t = f.items()[0].as_tree().await.unwrap();
+        let iter = t
+            .branch_by_name("PrimaryVertex.AliVertex.fNContributors").unwrap()
+            .read_basket_columns::<i32>();
+        iter.try_for_each(|basket| async move {black_box(basket); Ok(())}).await.unwrap()
+    };
+    let mut rt = Runtime::new().unwrap();
+    rt.block_on(fut);
+}
+
+fn var_size_branch_columns() {
+    let fut = async {
+        let path = alice_open_data::test_file().unwrap();
+        let f = RootFile::new(path.as_path()).await.expect("Failed to open file");
+        let t = f.items()[0].as_tree().await.unwrap();
+        let iter = t
+            .branch_by_name("Tracks.fFlags").unwrap()
+            .read_basket_columns::<u64>();
+        iter.try_for_each(|basket| async move {black_box(basket); Ok(())}).await.unwrap()
+    };
+    let mut rt = Runtime::new().unwrap();
     rt.block_on(fut);
 }
 
 pub fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("fixed_size_branch", |b| b.iter(|| fixed_size_branch));
     c.bench_function("var_size_branch", |b| b.iter(|| var_size_branch));
+    c.bench_function("fixed_size_branch_columns", |b| b.iter(fixed_size_branch_columns));
+    c.bench_function("var_size_branch_columns", |b| b.iter(var_size_branch_columns));
 }
 
 criterion_group!(benches, criterion_benchmark);
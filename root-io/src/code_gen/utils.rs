@@ -8,16 +8,12 @@ pub(crate) fn type_is_core(name: &str) -> bool {
     }
 }
 
-/// If necessary, annotate the given type name with a life time or replace with an alias
-pub(crate) fn alias_or_lifetime(t: &str) -> String {
-    // Most core types do not need a life time specifier
-    if type_is_core(t) && t != "TObjArray" {
-        return t.to_string();
-    }
-    // All non-core types get a life time
-    // This is over zealous, but currently, I don't have a proper way
-    // to check if a type has a member with a lifetime
-    return format!("{}<'s>", t);
+/// The name of the generated struct of a class; ROOT class names may
+/// contain namespaces or template arguments
+pub(crate) fn class_ident(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
 }
 
 pub(crate) fn sanitize(n: &str) -> String {
@@ -27,11 +23,12 @@ pub(crate) fn sanitize(n: &str) -> String {
         "return", "Self", "self", "static", "struct", "super", "trait", "true", "type", "unsafe",
         "use", "where", "while", "abstract", "alignof", "become", "box", "do", "final", "macro",
         "offsetof", "override", "priv", "proc", "pure", "sizeof", "typeof", "unsized", "virtual",
-        "yield",
+        "yield", "async", "await", "dyn", "try",
     ];
+    let n = class_ident(n);
     if keywords.into_iter().any(|w| w == n) {
         format!("{}_", n)
     } else {
-        n.to_string()
+        n
     }
 }
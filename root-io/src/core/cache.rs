@@ -0,0 +1,483 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use futures::lock::Mutex as AsyncMutex;
+use once_cell::sync::Lazy;
+use xxhash_rust::xxh64::xxh64;
+
+use crate::core::{boxed, DataSource, Source, SourceFuture, SourceVersion};
+use crate::Error;
+
+/// Sources are cached in blocks of this many bytes
+const BLOCK_SIZE: u64 = 256 * 1024;
+
+/// Default limit of the total size of all cached blocks
+const DEFAULT_MAX_SIZE: u64 = 4 * 1024 * 1024 * 1024;
+
+/// The index of a cache directory; read from the directory when it is
+/// first needed
+type SharedIndex = Arc<Mutex<Option<BlockIndex>>>;
+
+/// The index of each cache directory used by this process
+static INDICES: Lazy<Mutex<HashMap<PathBuf, SharedIndex>>> = Lazy::new(Default::default);
+
+/// A source whose content is cached on disk, e.g. a remote
+/// file. Fetched bytes are stored in blocks, keyed by the name of the
+/// source (see `DataSource::name`) and the position of the block, so
+/// that later runs only fetch what was not read before. Once the
+/// cache grows beyond its size limit, the least recently used blocks
+/// are removed.
+///
+/// The cached blocks of a source are dropped if its version changed
+/// (see `DataSource::version`); for remote files, that is their
+/// `ETag` or `Last-Modified` header. If the version cannot be
+/// determined, e.g. because the server cannot be reached, the cached
+/// blocks are used as they are, so files which were read before are
+/// available offline.
+///
+/// # Example
+/// ```no_run
+/// use reqwest::Url;
+/// use root_io::{CachedSource, Error, HttpOptions, RootFile, Source};
+///
+/// # async fn f() -> Result<(), Error> {
+/// let url = Url::parse("http://opendata.cern.ch/eos/opendata/alice/2010/LHC10h/000139038/ESD/0001/AliESDs.root").unwrap();
+/// let remote = Source::remote(url, HttpOptions::default())?;
+/// let f = RootFile::new(CachedSource::new(remote)?).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct CachedSource {
+    source: Source,
+    /// The name of `source`
+    name: String,
+    /// Directory holding the cached blocks of all sources
+    dir: PathBuf,
+    max_size: u64,
+    /// The validated metadata of this source; `None` until the first
+    /// fetch. It is locked while the cached blocks are validated, so
+    /// that they are not removed while they are read.
+    meta: AsyncMutex<Option<Meta>>,
+    /// The index of `dir`, shared by all sources cached there
+    index: SharedIndex,
+}
+
+/// What is known about a cached source
+#[derive(Debug, Clone, PartialEq)]
+struct Meta {
+    name: String,
+    version: SourceVersion,
+}
+
+impl Meta {
+    fn read(path: &Path) -> Option<Self> {
+        let content = fs::read_to_string(path).ok()?;
+        let mut lines = content.lines();
+        let mut field = || {
+            let value = lines.next()?;
+            Some(Some(value.to_string()).filter(|v| !v.is_empty()))
+        };
+        Some(Meta {
+            name: field()??,
+            version: SourceVersion {
+                tag: field()?,
+                len: field()?.and_then(|len| len.parse().ok()),
+            },
+        })
+    }
+
+    fn write(&self, path: &Path) -> io::Result<()> {
+        let content = format!(
+            "{}\n{}\n{}\n",
+            self.name,
+            self.version.tag.as_deref().unwrap_or(""),
+            self.version
+                .len
+                .map(|len| len.to_string())
+                .unwrap_or_default()
+        );
+        write_atomically(path, content.as_bytes())
+    }
+
+    /// True if the source has not changed since it was
+    /// cached. Sources without a tag can't be validated.
+    fn is_valid(&self, current: &SourceVersion) -> bool {
+        self.version.tag.is_some() && self.version == *current
+    }
+}
+
+impl CachedSource {
+    /// Cache `source` in the `root-io-cache` folder in
+    /// `alice_open_data::data_dir()`. The source must have a name
+    /// (see `DataSource::name`), as remote files do.
+    pub fn new<S: Into<Source>>(source: S) -> Result<Self, Error> {
+        let dir = alice_open_data::data_dir()
+            .map_err(|e| Error::NotFound(format!("Cache directory: {}", e)))?;
+        Self::with_dir(source, dir.join("root-io-cache"))
+    }
+
+    /// Cache `source` in the given directory
+    pub fn with_dir<S, P>(source: S, dir: P) -> Result<Self, Error>
+    where
+        S: Into<Source>,
+        P: Into<PathBuf>,
+    {
+        let source = source.into();
+        let name = source.name().ok_or_else(|| {
+            Error::Unsupported(format!("Caching {:?}, which has no name", source))
+        })?;
+        let dir = dir.into();
+        let index = INDICES
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(dir.clone())
+            .or_default()
+            .clone();
+        Ok(Self {
+            source,
+            name,
+            dir,
+            max_size: DEFAULT_MAX_SIZE,
+            meta: AsyncMutex::new(None),
+            index,
+        })
+    }
+
+    /// Limit the total size of the cache directory to `bytes`. The
+    /// default limit is 4 GiB.
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = bytes;
+        self
+    }
+
+    /// The directory holding the blocks of this source
+    fn file_dir(&self) -> PathBuf {
+        self.dir
+            .join(format!("{:016x}", xxh64(self.name.as_bytes(), 0)))
+    }
+
+    /// Apply `f` to the index of the cache directory, which is read
+    /// from the directory first if needed
+    fn with_index<T, F>(&self, f: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut BlockIndex) -> io::Result<T>,
+    {
+        let mut index = self.index.lock().unwrap_or_else(|e| e.into_inner());
+        if index.is_none() {
+            *index = Some(BlockIndex::scan(&self.dir)?);
+        }
+        f(index.as_mut().expect("The index was read"))
+    }
+
+    /// Validate the cached blocks of this source (once) and return
+    /// its metadata
+    async fn meta(&self) -> Result<Meta, Error> {
+        let mut validated = self.meta.lock().await;
+        if let Some(meta) = validated.as_ref() {
+            return Ok(meta.clone());
+        }
+        let dir = self.file_dir();
+        let meta_path = dir.join("meta");
+        let cached = Meta::read(&meta_path).filter(|m| m.name == self.name);
+        let meta = match (self.source.version().await, cached) {
+            (Ok(version), Some(cached)) if cached.is_valid(&version) => cached,
+            (Ok(version), _) => {
+                self.with_index(|index| {
+                    index.remove_dir(&dir);
+                    Ok(())
+                })?;
+                if dir.exists() {
+                    fs::remove_dir_all(&dir)?;
+                }
+                fs::create_dir_all(&dir)?;
+                let current = Meta {
+                    name: self.name.clone(),
+                    version,
+                };
+                current.write(&meta_path)?;
+                current
+            }
+            // Offline; use what is there
+            (Err(_), Some(cached)) => cached,
+            (Err(e), None) => return Err(e),
+        };
+        *validated = Some(meta.clone());
+        Ok(meta)
+    }
+
+    /// Return the given blocks of a source of `len` bytes, reading
+    /// them from the cache or from the source
+    async fn blocks(&self, indices: &[u64], len: u64) -> Result<HashMap<u64, Vec<u8>>, Error> {
+        let dir = self.file_dir();
+        let block_len = |i: u64| BLOCK_SIZE.min(len - i * BLOCK_SIZE);
+        let block_path = |i: u64| dir.join(format!("{}.block", i));
+        let used = |path: PathBuf, block: &[u8]| {
+            self.with_index(|index| {
+                index.insert(path, SystemTime::now(), block.len() as u64);
+                Ok(())
+            })
+        };
+        let mut blocks = HashMap::new();
+        let mut missing = vec![];
+        for &i in indices {
+            match read_block(&block_path(i), block_len(i)) {
+                Some(block) => {
+                    used(block_path(i), &block)?;
+                    blocks.insert(i, block);
+                }
+                None => missing.push(i),
+            }
+        }
+        if missing.is_empty() {
+            return Ok(blocks);
+        }
+        let ranges: Vec<_> = missing
+            .iter()
+            .map(|i| (i * BLOCK_SIZE, block_len(*i)))
+            .collect();
+        let fetched = self.source.fetch_many(&ranges).await?;
+        fs::create_dir_all(&dir)?;
+        for (i, block) in missing.into_iter().zip(fetched) {
+            write_atomically(&block_path(i), &block)?;
+            used(block_path(i), &block)?;
+            blocks.insert(i, block);
+        }
+        self.with_index(|index| index.evict(self.max_size))?;
+        Ok(blocks)
+    }
+}
+
+impl DataSource for CachedSource {
+    fn fetch(&self, start: u64, len: u64) -> SourceFuture<'_, Result<Vec<u8>, Error>> {
+        boxed(async move {
+            let mut bufs = self.fetch_many(&[(start, len)]).await?;
+            Ok(bufs.remove(0))
+        })
+    }
+
+    fn fetch_many<'a>(
+        &'a self,
+        ranges: &'a [(u64, u64)],
+    ) -> SourceFuture<'a, Result<Vec<Vec<u8>>, Error>> {
+        boxed(async move {
+            let file_len = match self.meta().await?.version.len {
+                Some(len) => len,
+                // Without knowing the size of the source, blocks
+                // can't be requested; don't cache anything
+                None => return self.source.fetch_many(ranges).await,
+            };
+            if let Some((start, len)) = ranges.iter().find(|(s, l)| s + l > file_len) {
+                return Err(Error::Io(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!(
+                        "Bytes {}..{} of a file of {} bytes",
+                        start,
+                        start + len,
+                        file_len
+                    ),
+                )));
+            }
+            let mut indices: Vec<_> = ranges
+                .iter()
+                .filter(|(_, len)| *len > 0)
+                .flat_map(|(start, len)| start / BLOCK_SIZE..=(start + len - 1) / BLOCK_SIZE)
+                .collect();
+            indices.sort_unstable();
+            indices.dedup();
+            let blocks = self.blocks(&indices, file_len).await?;
+            Ok(ranges
+                .iter()
+                .map(|(start, len)| {
+                    let mut buf = Vec::with_capacity(*len as usize);
+                    let mut pos = *start;
+                    while pos < start + len {
+                        let block = &blocks[&(pos / BLOCK_SIZE)];
+                        let begin = (pos % BLOCK_SIZE) as usize;
+                        let end = block.len().min(begin + (start + len - pos) as usize);
+                        buf.extend_from_slice(&block[begin..end]);
+                        pos += (end - begin) as u64;
+                    }
+                    buf
+                })
+                .collect())
+        })
+    }
+
+    fn sibling(&self, name: &str) -> Option<Source> {
+        let source = self.source.sibling(name).ok()?;
+        let cached = CachedSource::with_dir(source, self.dir.clone()).ok()?;
+        Some(cached.max_size(self.max_size).into())
+    }
+
+    fn len(&self) -> Option<u64> {
+        self.meta
+            .try_lock()
+            .and_then(|meta| meta.as_ref().and_then(|meta| meta.version.len))
+            .or_else(|| self.source.len())
+    }
+
+    fn name(&self) -> Option<String> {
+        Some(self.name.clone())
+    }
+
+    fn version(&self) -> SourceFuture<'_, Result<SourceVersion, Error>> {
+        boxed(self.source.version())
+    }
+}
+
+/// Read a cached block, marking it as recently used. Returns `None`
+/// if the block is not cached or does not have the expected length.
+fn read_block(path: &Path, len: u64) -> Option<Vec<u8>> {
+    let mut file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .ok()?;
+    let mut block = vec![];
+    file.read_to_end(&mut block).ok()?;
+    if block.len() as u64 != len {
+        return None;
+    }
+    // The modification time tracks the last use; see `evict`
+    let _ = file.set_modified(SystemTime::now());
+    Some(block)
+}
+
+/// Write `data` to a temporary file and move it to `path`, so that
+/// other processes never see partially written files
+fn write_atomically(path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp = path.with_extension(format!("tmp{}", std::process::id()));
+    File::create(&tmp)?.write_all(data)?;
+    fs::rename(&tmp, path)
+}
+
+/// The blocks of all sources in a cache directory, with their last
+/// use and size. The directory is read once per process; blocks added
+/// by other processes afterwards are not evicted until the next run.
+#[derive(Debug, Default)]
+struct BlockIndex {
+    blocks: HashMap<PathBuf, (SystemTime, u64)>,
+    /// Total size of all blocks
+    total: u64,
+}
+
+impl BlockIndex {
+    /// Read the index of the blocks of all sources cached in `dir`
+    fn scan(dir: &Path) -> io::Result<Self> {
+        let mut index = Self::default();
+        let file_dirs = match fs::read_dir(dir) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(index),
+            file_dirs => file_dirs?,
+        };
+        for file_dir in file_dirs {
+            let file_dir = file_dir?.path();
+            if !file_dir.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(&file_dir)? {
+                let path = entry?.path();
+                if path.extension() == Some(OsStr::new("block")) {
+                    let metadata = fs::metadata(&path)?;
+                    index.insert(path, metadata.modified()?, metadata.len());
+                }
+            }
+        }
+        Ok(index)
+    }
+
+    /// Record that the block at `path` of `len` bytes was used at `time`
+    fn insert(&mut self, path: PathBuf, time: SystemTime, len: u64) {
+        if let Some((_, old)) = self.blocks.insert(path, (time, len)) {
+            self.total -= old;
+        }
+        self.total += len;
+    }
+
+    /// Forget the blocks in `file_dir`, which is about to be removed
+    fn remove_dir(&mut self, file_dir: &Path) {
+        let total = &mut self.total;
+        self.blocks.retain(|path, (_, len)| {
+            let keep = !path.starts_with(file_dir);
+            if !keep {
+                *total -= *len;
+            }
+            keep
+        });
+    }
+
+    /// Remove the least recently used blocks until their total size
+    /// is at most `max_size` bytes
+    fn evict(&mut self, max_size: u64) -> io::Result<()> {
+        if self.total <= max_size {
+            return Ok(());
+        }
+        let mut blocks: Vec<_> = self
+            .blocks
+            .iter()
+            .map(|(path, (time, len))| (*time, *len, path.clone()))
+            .collect();
+        blocks.sort();
+        for (_, len, path) in blocks {
+            if self.total <= max_size {
+                break;
+            }
+            match fs::remove_file(&path) {
+                // Already removed, e.g. by another process
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+            self.blocks.remove(&path);
+            self.total -= len;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn evict_least_recently_used() {
+        let dir = std::env::temp_dir().join(format!("root-io-evict-{}", std::process::id()));
+        let file_dir = dir.join("0123456789abcdef");
+        fs::create_dir_all(&file_dir).unwrap();
+        let now = SystemTime::now();
+        for (i, age) in [3, 1, 2].iter().enumerate() {
+            let path = file_dir.join(format!("{}.block", i));
+            File::create(&path).unwrap().write_all(&[0; 10]).unwrap();
+            let file = fs::OpenOptions::new().write(true).open(&path).unwrap();
+            file.set_modified(now - Duration::from_secs(*age)).unwrap();
+        }
+        Meta {
+            name: "http://example.com/a.root".to_string(),
+            version: SourceVersion {
+                tag: None,
+                len: Some(30),
+            },
+        }
+        .write(&file_dir.join("meta"))
+        .unwrap();
+
+        let mut index = BlockIndex::scan(&dir).unwrap();
+        assert_eq!(index.total, 30);
+        index.evict(15).unwrap();
+        assert_eq!(index.total, 10);
+        let exists = |i| file_dir.join(format!("{}.block", i)).exists();
+        // Block 0 was used longest ago, block 2 before block 1
+        assert_eq!((exists(0), exists(1), exists(2)), (false, true, false));
+        let meta = Meta::read(&file_dir.join("meta")).unwrap();
+        assert_eq!(meta.version.len, Some(30));
+        assert_eq!(meta.version.tag, None);
+        index.remove_dir(&file_dir);
+        assert_eq!(index.total, 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,69 +1,894 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
 use std::fs::File;
-use std::io::{Read, Seek, SeekFrom};
-#[cfg(not(target_arch = "wasm32"))]
-use std::path::Path;
-use std::path::PathBuf;
+use std::io;
+use std::ops::{Deref, Range};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use failure::Error;
+use bytes::Bytes;
+use futures::future;
+use futures::prelude::*;
+use once_cell::sync::OnceCell;
 use reqwest::{
-    header::{RANGE, USER_AGENT},
-    Client, Url,
+    header::{
+        HeaderName, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, LAST_MODIFIED, RANGE,
+        USER_AGENT,
+    },
+    Client, Response, StatusCode, Url,
 };
 
+#[cfg(all(unix, not(target_arch = "wasm32")))]
+use crate::core::mmap::MappedFile;
+use crate::Error;
+
+/// The future returned by the methods of a `DataSource`. It is
+/// `Send`, so that files can be read from any executor, e.g. in a
+/// task started with `tokio::spawn`. On `wasm32`, where the futures
+/// of the HTTP client are not `Send`, it is a `LocalBoxFuture`.
+#[cfg(not(target_arch = "wasm32"))]
+pub type SourceFuture<'a, T> = future::BoxFuture<'a, T>;
+/// The future returned by the methods of a `DataSource`
+#[cfg(target_arch = "wasm32")]
+pub type SourceFuture<'a, T> = future::LocalBoxFuture<'a, T>;
+
+/// Box a future as a `SourceFuture`
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn boxed<'a, F>(f: F) -> SourceFuture<'a, F::Output>
+where
+    F: Future + Send + 'a,
+{
+    f.boxed()
+}
+
+/// Box a future as a `SourceFuture`
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn boxed<'a, F>(f: F) -> SourceFuture<'a, F::Output>
+where
+    F: Future + 'a,
+{
+    f.boxed_local()
+}
+
+/// Anything a ROOT file can be read from, e.g. a local file, a
+/// remote file, or a buffer in memory. Implement this trait to read
+/// files from other places; any implementor can be converted into a
+/// `Source` and passed to `RootFile::new`.
+#[allow(clippy::len_without_is_empty)]
+pub trait DataSource: fmt::Debug + Send + Sync {
+    /// Fetch `len` bytes starting at byte `start`
+    fn fetch(&self, start: u64, len: u64) -> SourceFuture<'_, Result<Vec<u8>, Error>>;
+
+    /// Fetch several ranges, given as `(start, len)`, at once. The
+    /// returned buffers are in the same order as `ranges`. The
+    /// default implementation calls `fetch` for each range.
+    fn fetch_many<'a>(
+        &'a self,
+        ranges: &'a [(u64, u64)],
+    ) -> SourceFuture<'a, Result<Vec<Vec<u8>>, Error>> {
+        boxed(future::try_join_all(
+            ranges.iter().map(|(start, len)| self.fetch(*start, *len)),
+        ))
+    }
+
+    /// The total size in bytes, if known
+    fn len(&self) -> Option<u64> {
+        None
+    }
+
+    /// The whole content, if it is kept in memory. Uncompressed
+    /// baskets of such sources are read without copying them.
+    fn as_slice(&self) -> Option<&[u8]> {
+        None
+    }
+
+    /// Another file at the same location, i.e. in the same directory
+    /// or on the same server. `name` is relative to this file. Used
+    /// for baskets stored in other files; `None` if not supported.
+    fn sibling(&self, _name: &str) -> Option<Source> {
+        None
+    }
+
+    /// A name identifying this source across runs, e.g. its url. A
+    /// `CachedSource` stores the blocks of a source under its name;
+    /// sources without a name cannot be cached.
+    fn name(&self) -> Option<String> {
+        None
+    }
+
+    /// The current version of this source. A `CachedSource` drops
+    /// the cached blocks of a source once its version changed. The
+    /// default implementation reports `len` without a tag.
+    fn version(&self) -> SourceFuture<'_, Result<SourceVersion, Error>> {
+        boxed(future::ready(Ok(SourceVersion {
+            tag: None,
+            len: self.len(),
+        })))
+    }
+}
+
+/// The state of the content of a source; see `DataSource::version`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceVersion {
+    /// Changes whenever the content changes, e.g. the `ETag` or
+    /// `Last-Modified` header of a remote file. Content without a tag
+    /// can't be validated.
+    pub tag: Option<String>,
+    /// The size in bytes, if known
+    pub len: Option<u64>,
+}
+
 /// The source from where the Root file is read. Construct it using
-/// `.into()` on a `Url` or `Path`. The latter is not availible for
-/// the `wasm32` target.
-#[derive(Debug, Clone)]
-pub struct Source(SourceInner);
+/// `.into()` on a `Url`, a `Path`, a buffer in memory (`Vec<u8>`,
+/// `Bytes`, or `&'static [u8]`), or any other `DataSource`; or map a
+/// local file into memory with `Source::mmap`. Local files are not
+/// availible for the `wasm32` target. Cloning a `Source` is
+/// cheap; all clones read from the same underlying `DataSource`.
+#[derive(Clone)]
+pub struct Source {
+    inner: Arc<dyn DataSource>,
+    /// The files next to this one which were opened so far, by name;
+    /// shared by all clones, so that the baskets of a file referenced
+    /// by many baskets are all read from the same `Source`
+    siblings: Arc<Mutex<HashMap<String, Source>>>,
+}
 
-// This inner enum hides the differentiation between the local and
-// remote files from the public API
-#[derive(Debug, Clone)]
-enum SourceInner {
-    /// A local source, i.e. a file on disc.
-    Local(PathBuf),
-    Remote {
-	client: Client,
-	url: Url,
+impl fmt::Debug for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Source").field(&self.inner).finish()
     }
 }
 
 impl Source {
-    pub fn new<T: Into::<Self>>(thing: T) -> Self {
-	thing.into()
+    pub fn new<T: Into<Self>>(thing: T) -> Self {
+        thing.into()
     }
-    
+
     pub async fn fetch(&self, start: u64, len: u64) -> Result<Vec<u8>, Error> {
-	match &self.0 {
-	    SourceInner::Local(path) => {
-		let mut f = File::open(&path)?;
-		f.seek(SeekFrom::Start(start))?;
-		let mut buf = vec![0; len as usize];
-		f.read_exact(&mut buf)?;
-		Ok(buf)
-	    },
-	    SourceInner::Remote{client, url} => {
-		let rsp = client
-		    .get(url.clone())
-		    .header(USER_AGENT, "alice-rs")
-		    .header(RANGE, format!("bytes={}-{}", start, start + len - 1))
-		    .send()
-		    .await?;
-		let bytes = rsp.bytes().await?;
-		Ok(bytes.as_ref().to_vec())
-	    }
-	}
+        self.inner.fetch(start, len).await
+    }
+
+    /// Fetch several ranges, given as `(start, len)`, at once. The
+    /// returned buffers are in the same order as `ranges`. For local
+    /// and remote files, adjacent and overlapping ranges are
+    /// merged. Remote files request all ranges with a single
+    /// multi-range request if the server supports it.
+    pub async fn fetch_many(&self, ranges: &[(u64, u64)]) -> Result<Vec<Vec<u8>>, Error> {
+        self.inner.fetch_many(ranges).await
+    }
+
+    /// A remote file read with the given options. Using `.into()`
+    /// on a `Url` uses the default options.
+    pub fn remote(url: Url, options: HttpOptions) -> Result<Self, Error> {
+        Ok(RemoteFile::new(url, options)?.into())
+    }
+
+    /// A local file mapped into memory. Uncompressed baskets are
+    /// then read without copying them. On platforms other than Unix,
+    /// the file is read into memory instead.
+    ///
+    /// # Safety
+    ///
+    /// The buffers handed out by this source borrow the mapping. If
+    /// the file is truncated while it is mapped, reading them may
+    /// raise `SIGBUS`; if it is modified, their contents may change
+    /// underneath the reader. The caller must ensure that the file is
+    /// left untouched for as long as the source, or any buffer
+    /// borrowed from it, is alive. The same applies to the files of
+    /// friend trees, which are mapped from the same directory.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub unsafe fn mmap<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        #[cfg(unix)]
+        let source = MappedFile::open(path.as_ref().to_path_buf())?.into();
+        #[cfg(not(unix))]
+        let source = std::fs::read(path)?.into();
+        Ok(source)
+    }
+
+    /// The total size of this source in bytes, if known
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> Option<u64> {
+        self.inner.len()
+    }
+
+    /// Fetch `len` bytes starting at `start`, borrowing them if the
+    /// source is kept in memory
+    pub(crate) async fn fetch_buffer(&self, start: u64, len: u64) -> Result<Buffer, Error> {
+        match self.inner.as_slice() {
+            Some(all) => {
+                let range = start as usize..(start + len) as usize;
+                if range.end > all.len() {
+                    // Let the source report the error
+                    return self.fetch(start, len).await.map(Buffer::Owned);
+                }
+                Ok(Buffer::Borrowed(self.clone(), range))
+            }
+            None => self.fetch(start, len).await.map(Buffer::Owned),
+        }
+    }
+
+    /// True if the whole content of this source is kept in memory
+    pub(crate) fn in_memory(&self) -> bool {
+        self.inner.as_slice().is_some()
+    }
+
+    /// True if both handles refer to the same source
+    pub(crate) fn same_as(&self, other: &Source) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    /// The name of this source; see `DataSource::name`
+    pub(crate) fn name(&self) -> Option<String> {
+        self.inner.name()
+    }
+
+    /// The current version of this source; see `DataSource::version`
+    pub(crate) async fn version(&self) -> Result<SourceVersion, Error> {
+        self.inner.version().await
+    }
+
+    /// The file `name` at the same location as this one; see
+    /// `DataSource::sibling`. Each sibling is only opened once.
+    pub(crate) fn sibling(&self, name: &str) -> Result<Source, Error> {
+        let mut siblings = self.siblings.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(sibling) = siblings.get(name) {
+            return Ok(sibling.clone());
+        }
+        let sibling = self.inner.sibling(name).ok_or_else(|| {
+            Error::Unsupported(format!(
+                "Opening the file `{}` relative to {:?}",
+                name, self.inner
+            ))
+        })?;
+        siblings.insert(name.to_string(), sibling.clone());
+        Ok(sibling)
+    }
+}
+
+/// Bytes read from a `Source`; either a copy or a range of a source
+/// kept in memory
+#[derive(Clone)]
+pub(crate) enum Buffer {
+    Owned(Vec<u8>),
+    Borrowed(Source, Range<usize>),
+}
+
+impl Buffer {
+    /// The given range of this buffer; borrowed if possible
+    pub(crate) fn slice(&self, range: Range<usize>) -> Self {
+        match self {
+            Buffer::Owned(buf) => Buffer::Owned(buf[range].to_vec()),
+            Buffer::Borrowed(source, outer) => {
+                assert!(range.end <= outer.len(), "Range out of bounds");
+                Buffer::Borrowed(
+                    source.clone(),
+                    outer.start + range.start..outer.start + range.end,
+                )
+            }
+        }
+    }
+}
+
+impl Deref for Buffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Buffer::Owned(buf) => buf,
+            Buffer::Borrowed(source, range) => {
+                &source.inner.as_slice().expect("Source is kept in memory")[range.clone()]
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Buffer of {} bytes", self.len())
+    }
+}
+
+impl PartialEq for Buffer {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: DataSource + 'static> From<T> for Source {
+    fn from(source: T) -> Self {
+        Self {
+            inner: Arc::new(source),
+            siblings: Default::default(),
+        }
+    }
+}
+
+impl<T: DataSource + ?Sized> DataSource for Arc<T> {
+    fn fetch(&self, start: u64, len: u64) -> SourceFuture<'_, Result<Vec<u8>, Error>> {
+        (**self).fetch(start, len)
+    }
+
+    fn fetch_many<'a>(
+        &'a self,
+        ranges: &'a [(u64, u64)],
+    ) -> SourceFuture<'a, Result<Vec<Vec<u8>>, Error>> {
+        (**self).fetch_many(ranges)
+    }
+
+    fn len(&self) -> Option<u64> {
+        (**self).len()
+    }
+
+    fn as_slice(&self) -> Option<&[u8]> {
+        (**self).as_slice()
+    }
+
+    fn sibling(&self, name: &str) -> Option<Source> {
+        (**self).sibling(name)
+    }
+
+    fn name(&self) -> Option<String> {
+        (**self).name()
+    }
+
+    fn version(&self) -> SourceFuture<'_, Result<SourceVersion, Error>> {
+        (**self).version()
+    }
+}
+
+/// A file on disc
+#[derive(Debug)]
+struct LocalFile {
+    path: PathBuf,
+    /// Opened on first use and kept open afterwards
+    file: OnceCell<File>,
+    /// Serializes seeking and reading on platforms without
+    /// positional reads
+    #[cfg(not(unix))]
+    cursor: Mutex<()>,
+}
+
+impl LocalFile {
+    /// Read the given `(start, end)` spans. On Unix, the file is read
+    /// with positional reads, so that concurrent reads do not wait
+    /// for each other.
+    fn read(&self, spans: &[(u64, u64)]) -> Result<Vec<Vec<u8>>, Error> {
+        let file = self.file.get_or_try_init(|| File::open(&self.path))?;
+        #[cfg(not(unix))]
+        let _cursor = self.cursor.lock().unwrap_or_else(|e| e.into_inner());
+        spans
+            .iter()
+            .map(|(start, end)| {
+                let mut buf = vec![0; (end - start) as usize];
+                read_exact_at(file, &mut buf, *start)?;
+                Ok(buf)
+            })
+            .collect()
+    }
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    use std::os::unix::fs::FileExt;
+    file.read_exact_at(buf, offset)
+}
+
+#[cfg(not(unix))]
+fn read_exact_at(mut file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    use std::io::{Read, Seek, SeekFrom};
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(buf)
+}
+
+impl DataSource for LocalFile {
+    fn fetch(&self, start: u64, len: u64) -> SourceFuture<'_, Result<Vec<u8>, Error>> {
+        boxed(async move {
+            let mut bufs = self.read(&[(start, start + len)])?;
+            Ok(bufs.remove(0))
+        })
+    }
+
+    fn fetch_many<'a>(
+        &'a self,
+        ranges: &'a [(u64, u64)],
+    ) -> SourceFuture<'a, Result<Vec<Vec<u8>>, Error>> {
+        boxed(async move {
+            let spans = merge_ranges(ranges);
+            let bufs = self.read(&spans)?;
+            Ok(split_spans(ranges, &spans, &bufs))
+        })
+    }
+
+    fn len(&self) -> Option<u64> {
+        std::fs::metadata(&self.path).ok().map(|m| m.len())
+    }
+
+    fn sibling(&self, name: &str) -> Option<Source> {
+        Some(self.path.with_file_name(name).into())
+    }
+}
+
+/// Options for reading remote files; see `Source::remote`
+#[derive(Debug, Clone)]
+pub struct HttpOptions {
+    /// Timeout of each request, including reading the response. Not
+    /// supported on `wasm32`.
+    pub timeout: Option<Duration>,
+    /// Timeout for establishing a connection. Not supported on
+    /// `wasm32`.
+    pub connect_timeout: Option<Duration>,
+    /// How often a request is repeated after a transient failure,
+    /// e.g. a timeout, a lost connection, or a `5xx` status
+    pub retries: u32,
+    /// Delay before the first retry; doubled for each further retry
+    pub backoff: Duration,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Some(Duration::from_secs(60)),
+            connect_timeout: Some(Duration::from_secs(10)),
+            retries: 3,
+            backoff: Duration::from_millis(250),
+        }
+    }
+}
+
+/// A file on a web server
+#[derive(Debug)]
+struct RemoteFile {
+    client: Client,
+    url: Url,
+    options: HttpOptions,
+    /// Cleared once the server failed to answer a request for
+    /// several ranges
+    multi_range: AtomicBool,
+}
+
+/// A failed request to a server
+struct Failure {
+    error: Error,
+    /// Whether repeating the request may succeed
+    transient: bool,
+}
+
+impl RemoteFile {
+    fn new(url: Url, options: HttpOptions) -> Result<Self, Error> {
+        #[allow(unused_mut)]
+        let mut builder = Client::builder();
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if let Some(timeout) = options.timeout {
+                builder = builder.timeout(timeout);
+            }
+            if let Some(timeout) = options.connect_timeout {
+                builder = builder.connect_timeout(timeout);
+            }
+        }
+        Ok(Self {
+            client: builder.build()?,
+            url,
+            options,
+            multi_range: AtomicBool::new(true),
+        })
+    }
+
+    /// Fetch the `(start, end)` spans. Several spans are requested at
+    /// once if the server supports it; if that fails, one request is
+    /// sent per span.
+    async fn fetch_spans(&self, spans: &[(u64, u64)]) -> Result<Vec<Vec<u8>>, Error> {
+        if spans.len() > 1 && self.multi_range.load(Ordering::Relaxed) {
+            let ranges: Vec<_> = spans
+                .iter()
+                .map(|(start, end)| format!("{}-{}", start, end - 1))
+                .collect();
+            let parts = match self.request(&ranges.join(",")).await {
+                Ok(parts) => parts,
+                Err(Failure {
+                    error,
+                    transient: true,
+                }) => return Err(error),
+                Err(_) => vec![],
+            };
+            let bufs: Option<Vec<_>> = spans
+                .iter()
+                .map(|(start, end)| {
+                    parts.iter().find_map(|(part_start, data)| {
+                        let begin = start.checked_sub(*part_start)? as usize;
+                        data.get(begin..begin + (end - start) as usize)
+                            .map(|d| d.to_vec())
+                    })
+                })
+                .collect();
+            match bufs {
+                Some(bufs) => return Ok(bufs),
+                None => self.multi_range.store(false, Ordering::Relaxed),
+            }
+        }
+        future::try_join_all(spans.iter().map(|(start, end)| async move {
+            let range = format!("{}-{}", start, end - 1);
+            let mut parts = self.request(&range).await.map_err(|f| f.error)?;
+            match parts.pop() {
+                Some((part_start, data))
+                    if parts.is_empty()
+                        && part_start == *start
+                        && data.len() as u64 == end - start =>
+                {
+                    Ok(data)
+                }
+                _ => Err(self.invalid(&range, "Response does not match the range")),
+            }
+        }))
+        .await
+    }
+
+    /// Send a request with `send`, repeating it after transient
+    /// failures with an exponentially increasing delay
+    async fn retry<T, F, R>(&self, send: F) -> Result<T, Failure>
+    where
+        F: Fn() -> R,
+        R: Future<Output = Result<T, Failure>>,
+    {
+        let mut delay = self.options.backoff;
+        let mut attempt = 0;
+        loop {
+            match send().await {
+                Err(failure) if failure.transient && attempt < self.options.retries => {
+                    sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Request the given ranges (e.g. `0-9,20-29`), retrying after
+    /// transient failures
+    async fn request(&self, ranges: &str) -> Result<Vec<(u64, Vec<u8>)>, Failure> {
+        self.retry(|| self.request_once(ranges)).await
+    }
+
+    /// Request the given ranges (e.g. `0-9,20-29`). The response is
+    /// split into its parts, each given as its first byte and its
+    /// content.
+    async fn request_once(&self, ranges: &str) -> Result<Vec<(u64, Vec<u8>)>, Failure> {
+        let rsp = self
+            .client
+            .get(self.url.clone())
+            .header(USER_AGENT, "alice-rs")
+            .header(RANGE, format!("bytes={}", ranges))
+            .send()
+            .await
+            .map_err(http_failure)?;
+        let status = rsp.status();
+        if status != StatusCode::PARTIAL_CONTENT {
+            let msg = if status.is_success() {
+                format!("Range request not supported (status {})", status)
+            } else {
+                format!("Status {}", status)
+            };
+            return Err(Failure {
+                error: self.invalid(ranges, &msg),
+                transient: is_transient(status),
+            });
+        }
+        let header = |name| header(&rsp, name);
+        let content_type = header(CONTENT_TYPE).unwrap_or_default();
+        let content_range = header(CONTENT_RANGE);
+        let body = rsp.bytes().await.map_err(http_failure)?.to_vec();
+        let boundary = content_type
+            .split(';')
+            .map(str::trim)
+            .find_map(|p| p.strip_prefix("boundary="))
+            .map(|b| b.trim_matches('"'));
+        let (parts, msg) = match (boundary, content_range) {
+            (Some(boundary), _) if content_type.starts_with("multipart/byteranges") => (
+                multipart_parts(&body, boundary),
+                "Invalid multipart response",
+            ),
+            (_, Some(range)) => match parse_content_range(&range) {
+                // An incomplete body is retried
+                Some((start, end)) if body.len() as u64 != end - start => {
+                    return Err(Failure {
+                        error: self.invalid(
+                            ranges,
+                            &format!("Received {} of {} bytes", body.len(), end - start),
+                        ),
+                        transient: true,
+                    })
+                }
+                Some((start, _)) => (Some(vec![(start, body)]), ""),
+                None => (None, "Invalid Content-Range"),
+            },
+            _ => (None, "Missing Content-Range"),
+        };
+        parts.ok_or_else(|| Failure {
+            error: self.invalid(ranges, msg),
+            transient: false,
+        })
+    }
+
+    /// Request the headers of the file, retrying after transient
+    /// failures
+    async fn head(&self) -> Result<SourceVersion, Error> {
+        self.retry(|| self.head_once())
+            .await
+            .map_err(|failure| failure.error)
+    }
+
+    /// Request the headers of the file
+    async fn head_once(&self) -> Result<SourceVersion, Failure> {
+        let rsp = self
+            .client
+            .head(self.url.clone())
+            .header(USER_AGENT, "alice-rs")
+            .send()
+            .await
+            .map_err(http_failure)?;
+        let status = rsp.status();
+        let rsp = rsp.error_for_status().map_err(|e| Failure {
+            error: Error::Http(e),
+            transient: is_transient(status),
+        })?;
+        let header = |name| header(&rsp, name);
+        let tag = match (header(ETAG), header(LAST_MODIFIED)) {
+            (None, None) => None,
+            (etag, modified) => Some(format!(
+                "{} {}",
+                etag.unwrap_or_default(),
+                modified.unwrap_or_default()
+            )),
+        };
+        Ok(SourceVersion {
+            tag,
+            len: header(CONTENT_LENGTH).and_then(|len| len.parse().ok()),
+        })
+    }
+
+    fn invalid(&self, range: &str, msg: &str) -> Error {
+        Error::Response {
+            url: self.url.to_string(),
+            range: range.to_string(),
+            msg: msg.to_string(),
+        }
+    }
+}
+
+/// A failed request which did not receive a response
+fn http_failure(e: reqwest::Error) -> Failure {
+    Failure {
+        transient: e.is_timeout() || e.is_connect() || e.is_request() || e.is_body(),
+        error: Error::Http(e),
+    }
+}
+
+/// Whether a request answered with `status` may succeed if repeated
+fn is_transient(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+/// The value of a header of `rsp`, if it is valid text
+fn header(rsp: &Response, name: HeaderName) -> Option<String> {
+    rsp.headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string())
+}
+
+/// Wait before retrying a request. The timer is a thread of its own,
+/// so that this works with any executor and not only within a tokio
+/// runtime.
+#[cfg(not(target_arch = "wasm32"))]
+async fn sleep(duration: Duration) {
+    let (done, wait) = futures::channel::oneshot::channel();
+    std::thread::spawn(move || {
+        std::thread::sleep(duration);
+        let _ = done.send(());
+    });
+    // An error means that the thread is gone; there is nothing to
+    // wait for then
+    let _ = wait.await;
+}
+
+/// Wait before retrying a request; there is no timer on `wasm32`,
+/// so requests are repeated right away
+#[cfg(target_arch = "wasm32")]
+async fn sleep(_duration: Duration) {}
+
+impl DataSource for RemoteFile {
+    fn fetch(&self, start: u64, len: u64) -> SourceFuture<'_, Result<Vec<u8>, Error>> {
+        boxed(async move {
+            let mut bufs = self.fetch_many(&[(start, len)]).await?;
+            Ok(bufs.remove(0))
+        })
+    }
+
+    fn fetch_many<'a>(
+        &'a self,
+        ranges: &'a [(u64, u64)],
+    ) -> SourceFuture<'a, Result<Vec<Vec<u8>>, Error>> {
+        boxed(async move {
+            let spans = merge_ranges(ranges);
+            let bufs = self.fetch_spans(&spans).await?;
+            Ok(split_spans(ranges, &spans, &bufs))
+        })
+    }
+
+    fn sibling(&self, name: &str) -> Option<Source> {
+        let url = self.url.join(name).ok()?;
+        Source::remote(url, self.options.clone()).ok()
+    }
+
+    fn name(&self) -> Option<String> {
+        Some(self.url.to_string())
+    }
+
+    fn version(&self) -> SourceFuture<'_, Result<SourceVersion, Error>> {
+        boxed(self.head())
+    }
+}
+
+/// Read from a buffer in memory
+pub(crate) fn fetch_from_memory(
+    buf: &[u8],
+    start: u64,
+    len: u64,
+) -> SourceFuture<'_, Result<Vec<u8>, Error>> {
+    let data = usize::try_from(start)
+        .ok()
+        .zip(usize::try_from(start + len).ok())
+        .and_then(|(start, end)| buf.get(start..end))
+        .map(|data| data.to_vec())
+        .ok_or_else(|| {
+            Error::Io(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!(
+                    "Bytes {}..{} of a buffer of {} bytes",
+                    start,
+                    start + len,
+                    buf.len()
+                ),
+            ))
+        });
+    boxed(future::ready(data))
+}
+
+impl DataSource for Vec<u8> {
+    fn fetch(&self, start: u64, len: u64) -> SourceFuture<'_, Result<Vec<u8>, Error>> {
+        fetch_from_memory(self, start, len)
+    }
+
+    fn len(&self) -> Option<u64> {
+        Some(self.as_slice().len() as u64)
+    }
+
+    fn as_slice(&self) -> Option<&[u8]> {
+        Some(self.as_slice())
+    }
+}
+
+impl DataSource for Bytes {
+    fn fetch(&self, start: u64, len: u64) -> SourceFuture<'_, Result<Vec<u8>, Error>> {
+        fetch_from_memory(self, start, len)
+    }
+
+    fn len(&self) -> Option<u64> {
+        Some(self.as_ref().len() as u64)
+    }
+
+    fn as_slice(&self) -> Option<&[u8]> {
+        Some(self.as_ref())
+    }
+}
+
+impl DataSource for &'static [u8] {
+    fn fetch(&self, start: u64, len: u64) -> SourceFuture<'_, Result<Vec<u8>, Error>> {
+        fetch_from_memory(self, start, len)
+    }
+
+    fn len(&self) -> Option<u64> {
+        Some((*self).len() as u64)
+    }
+
+    fn as_slice(&self) -> Option<&[u8]> {
+        Some(*self)
+    }
+}
+
+/// Cut the requested `(start, len)` ranges out of the buffers of the
+/// `(start, end)` spans containing them (see `merge_ranges`)
+fn split_spans(ranges: &[(u64, u64)], spans: &[(u64, u64)], bufs: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    ranges
+        .iter()
+        .map(|(start, len)| {
+            // The last span starting before this range contains it
+            let k = spans.partition_point(|(s, _)| s <= start);
+            match k.checked_sub(1) {
+                Some(k) if *len > 0 => {
+                    let begin = (start - spans[k].0) as usize;
+                    bufs[k][begin..begin + *len as usize].to_vec()
+                }
+                _ => vec![],
+            }
+        })
+        .collect()
+}
+
+/// Sort the non-empty `(start, len)` ranges and merge those which
+/// overlap or touch. The result are `(start, end)` pairs.
+fn merge_ranges(ranges: &[(u64, u64)]) -> Vec<(u64, u64)> {
+    let mut sorted: Vec<_> = ranges
+        .iter()
+        .filter(|(_, len)| *len > 0)
+        .map(|(start, len)| (*start, start + len))
+        .collect();
+    sorted.sort_unstable();
+    let mut spans: Vec<(u64, u64)> = vec![];
+    for (start, end) in sorted {
+        match spans.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => spans.push((start, end)),
+        }
+    }
+    spans
+}
+
+/// Parse the value of a `Content-Range` header, e.g. `bytes
+/// 0-9/100`, into the first byte and the end of the range
+fn parse_content_range(value: &str) -> Option<(u64, u64)> {
+    let range = value.trim().strip_prefix("bytes ")?.split('/').next()?;
+    let mut bounds = range.splitn(2, '-').map(|b| b.trim().parse::<u64>());
+    let start = bounds.next()?.ok()?;
+    let last = bounds.next()?.ok()?;
+    Some((start, last + 1))
+}
+
+/// Split the body of a `multipart/byteranges` response into the
+/// first byte and the content of each part
+fn multipart_parts(body: &[u8], boundary: &str) -> Option<Vec<(u64, Vec<u8>)>> {
+    let find = |haystack: &[u8], needle: &[u8]| {
+        haystack
+            .windows(needle.len())
+            .position(|window| window == needle)
+    };
+    let delimiter = format!("--{}", boundary);
+    let mut parts = vec![];
+    let mut rest = body;
+    loop {
+        let pos = find(rest, delimiter.as_bytes())?;
+        rest = &rest[pos + delimiter.len()..];
+        if rest.starts_with(b"--") {
+            return Some(parts);
+        }
+        // The headers of each part end with an empty line
+        let end = find(rest, b"\r\n\r\n")?;
+        let headers = std::str::from_utf8(&rest[..end]).ok()?;
+        let (start, stop) = headers.lines().find_map(|line| {
+            let mut kv = line.splitn(2, ':');
+            match (kv.next(), kv.next()) {
+                (Some(k), Some(v)) if k.trim().eq_ignore_ascii_case("content-range") => {
+                    parse_content_range(v)
+                }
+                _ => None,
+            }
+        })?;
+        rest = &rest[end + 4..];
+        let len = stop.checked_sub(start)? as usize;
+        parts.push((start, rest.get(..len)?.to_vec()));
+        rest = &rest[len..];
     }
 }
 
 impl From<Url> for Source {
     fn from(url: Url) -> Self {
-	Self(
-	    SourceInner::Remote {
-		client: Client::new(),
-		url,
-	    }
-	)
+        Source::remote(url, HttpOptions::default()).expect("Failed to create HTTP client")
     }
 }
 
@@ -72,13 +897,103 @@ impl From<Url> for Source {
 #[cfg(not(target_arch = "wasm32"))]
 impl From<&Path> for Source {
     fn from(path: &Path) -> Self {
-	path.to_path_buf().into()
+        path.to_path_buf().into()
     }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
 impl From<PathBuf> for Source {
     fn from(path_buf: PathBuf) -> Self {
-	Self(SourceInner::Local(path_buf))
+        LocalFile {
+            path: path_buf,
+            file: OnceCell::new(),
+            #[cfg(not(unix))]
+            cursor: Mutex::new(()),
+        }
+        .into()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_adjacent_ranges() {
+        assert_eq!(
+            merge_ranges(&[(10, 5), (0, 10), (30, 0), (12, 1), (20, 5)]),
+            vec![(0, 15), (20, 25)]
+        );
+    }
+
+    #[test]
+    fn multipart_response() {
+        let body = b"\r\n--XYZ\r\nContent-Type: application/octet-stream\r\n\
+                     Content-Range: bytes 2-4/100\r\n\r\nabc\r\n\
+                     --XYZ\r\nContent-Range: bytes 10-10/100\r\n\r\nd\r\n--XYZ--\r\n";
+        assert_eq!(
+            multipart_parts(body, "XYZ"),
+            Some(vec![(2, b"abc".to_vec()), (10, b"d".to_vec())])
+        );
+        assert_eq!(parse_content_range("bytes 0-9/*"), Some((0, 10)));
+        assert_eq!(parse_content_range("items 0-9/*"), None);
+    }
+
+    #[test]
+    fn sleep_without_tokio() {
+        let start = std::time::Instant::now();
+        futures::executor::block_on(sleep(Duration::from_millis(20)));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[tokio::test]
+    async fn fetch_many_local() {
+        let path = Path::new("./src/test_data/simple.root");
+        let source = Source::new(path);
+        let bufs = source
+            .fetch_many(&[(100, 10), (0, 4), (104, 2), (50, 0)])
+            .await
+            .unwrap();
+        let all = std::fs::read(path).unwrap();
+        assert_eq!(bufs[0], &all[100..110]);
+        assert_eq!(bufs[1], b"root");
+        assert_eq!(bufs[2], &all[104..106]);
+        assert!(bufs[3].is_empty());
+    }
+
+    #[test]
+    fn fetch_local_concurrently() {
+        let path = Path::new("./src/test_data/simple.root");
+        let source = Source::new(path);
+        let all = std::fs::read(path).unwrap();
+        let len = all.len() as u64;
+        let threads: Vec<_> = (0..8)
+            .map(|i| {
+                let source = source.clone();
+                std::thread::spawn(move || {
+                    let start = i * (len / 8);
+                    futures::executor::block_on(source.fetch(start, len / 8)).unwrap()
+                })
+            })
+            .collect();
+        let bufs: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+        assert_eq!(bufs.concat(), &all[..(len / 8 * 8) as usize]);
+        // Reading past the end is only reported when the future is polled
+        let past_end = source.fetch(len, 1);
+        assert!(futures::executor::block_on(past_end).is_err());
+    }
+
+    #[tokio::test]
+    async fn fetch_from_memory() {
+        let all = std::fs::read("./src/test_data/simple.root").unwrap();
+        let source = Source::new(all.clone());
+        assert_eq!(source.len(), Some(all.len() as u64));
+        assert_eq!(source.fetch(0, 4).await.unwrap(), b"root");
+        let bufs = source.fetch_many(&[(100, 10), (50, 0)]).await.unwrap();
+        assert_eq!(bufs, vec![all[100..110].to_vec(), vec![]]);
+        assert!(source.fetch(all.len() as u64 - 2, 4).await.is_err());
+
+        let source = Source::new(Bytes::from(all.clone()));
+        assert_eq!(source.fetch(100, 10).await.unwrap(), &all[100..110]);
     }
 }
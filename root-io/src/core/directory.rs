@@ -0,0 +1,171 @@
+use crate::core::*;
+use crate::Error;
+
+/// Size of serialized TDirectory. Depending on the ROOT version this
+/// may use 32 or 64 bit pointers. This is the maximal (64 bit size).
+pub(crate) const TDIRECTORY_MAX_SIZE: u64 = 42;
+
+/// A (sub-)directory within a ROOT file. The items of a directory
+/// are read when the directory is opened, but its subdirectories are
+/// only parsed when they are requested.
+#[derive(Debug, Clone)]
+pub struct Directory {
+    name: String,
+    items: Vec<FileItem>,
+}
+
+impl Directory {
+    /// Read the list of keys of the directory described by the given header
+    pub(crate) async fn read(
+        source: Source,
+        name: &str,
+        hdr: &DirectoryHeader,
+    ) -> Result<Self, Error> {
+        let tkey_of_keys = source
+            .fetch(hdr.seek_keys, hdr.n_bytes_keys as u64)
+            .await
+            .and_then(|buf| {
+                tkey(&buf)
+                    .map_err(|e| Error::parse("TKey", hdr.seek_keys, &buf, e))
+                    .map(|(_i, o)| o)
+            })?;
+        let keys = tkey_headers(&tkey_of_keys.obj)
+            .map_err(|e| Error::parse("TKeyHeaders", hdr.seek_keys, &tkey_of_keys.obj, e))
+            .map(|(_i, hdrs)| hdrs)?;
+        let items = keys
+            .iter()
+            .map(|k_hdr| FileItem::new(k_hdr, source.clone()))
+            .collect();
+        Ok(Directory {
+            name: name.to_string(),
+            items,
+        })
+    }
+
+    /// The name of this directory; empty for the top level directory of a file
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Slice of the items contained in this directory (including
+    /// the keys of subdirectories)
+    pub fn items(&self) -> &[FileItem] {
+        &self.items
+    }
+
+    /// The item with the given name. If there are several cycles of
+    /// that item, the one with the highest cycle is returned.
+    pub fn item_by_name(&self, name: &str) -> Result<&FileItem, Error> {
+        self.items
+            .iter()
+            .filter(|i| i.object_name() == name)
+            .max_by_key(|i| i.cycle())
+            .ok_or_else(|| {
+                Error::NotFound(format!("No item `{}` in directory `{}`", name, self.name))
+            })
+    }
+
+    /// The item with the given name and cycle
+    pub fn item_by_name_and_cycle(&self, name: &str, cycle: i16) -> Result<&FileItem, Error> {
+        self.items
+            .iter()
+            .find(|i| i.object_name() == name && i.cycle() == cycle)
+            .ok_or_else(|| {
+                Error::NotFound(format!(
+                    "No item `{};{}` in directory `{}`",
+                    name, cycle, self.name
+                ))
+            })
+    }
+
+    /// Parse all the direct subdirectories of this directory
+    pub async fn subdirectories(&self) -> Result<Vec<Directory>, Error> {
+        let mut dirs = vec![];
+        for item in self.items.iter().filter(|i| i.is_directory()) {
+            dirs.push(item.as_directory().await?);
+        }
+        Ok(dirs)
+    }
+
+    /// Find the item at the given `/`-separated path relative to this
+    /// directory, e.g. `"a/b/tree"`. Intermediate directories are
+    /// parsed on the way. The highest cycle is used for each component.
+    pub async fn get(&self, path: &str) -> Result<FileItem, Error> {
+        let components: Vec<_> = path.split('/').filter(|c| !c.is_empty()).collect();
+        let mut current = self.clone();
+        for (i, name) in components.iter().enumerate() {
+            let item = current.item_by_name(name)?;
+            if i + 1 == components.len() {
+                return Ok(item.to_owned());
+            }
+            if !item.is_directory() {
+                return Err(Error::NotFound(format!(
+                    "`{}` in path `{}` is not a directory",
+                    name, path
+                )));
+            }
+            current = item.as_directory().await?;
+        }
+        Err(Error::NotFound("Empty path".to_string()))
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::path::Path;
+
+    use super::Directory;
+    use crate::core::RootFile;
+
+    #[tokio::test]
+    async fn nested_dirs() {
+        let path = Path::new("./src/test_data/nesteddirs.root");
+        let f = RootFile::new(path).await.expect("Failed to open file");
+        let names = |items: &[crate::core::FileItem]| {
+            items
+                .iter()
+                .map(|i| i.object_name().to_owned())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(f.items()), ["one", "three"]);
+
+        let subdirs = f.root_directory().subdirectories().await.unwrap();
+        assert_eq!(subdirs.len(), 2);
+        assert_eq!(subdirs[0].name(), "one");
+        assert_eq!(names(subdirs[0].items()), ["two", "tree"]);
+        assert_eq!(names(subdirs[1].items()), ["tree"]);
+
+        let paths = [
+            ("one/tree", "three"),
+            ("one/two/tree", "Int32"),
+            ("/three/tree", "StlVecStr"),
+        ];
+        for (p, branch) in paths.iter() {
+            let tree = f.get(p).await.unwrap().as_tree().await.unwrap();
+            assert!(tree.branch_by_name(branch).is_ok());
+        }
+        assert!(f.get("one/four").await.is_err());
+        assert!(f.get("one/tree/two").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn highest_cycle() {
+        let path = Path::new("./src/test_data/simple.root");
+        let f = RootFile::new(path).await.expect("Failed to open file");
+        let mut items = vec![];
+        for cycle in &[2, 5, 1] {
+            let mut item = f.items()[0].clone();
+            item.tkey_hdr.cycle = *cycle;
+            items.push(item);
+        }
+        let dir = Directory {
+            name: "".to_string(),
+            items,
+        };
+        assert_eq!(dir.item_by_name("tree").unwrap().cycle(), 5);
+        assert_eq!(dir.item_by_name_and_cycle("tree", 2).unwrap().cycle(), 2);
+        assert!(dir.item_by_name_and_cycle("tree", 3).is_err());
+        assert!(dir.item_by_name("no_tree").is_err());
+        assert_eq!(dir.get("tree").await.unwrap().cycle(), 5);
+    }
+}
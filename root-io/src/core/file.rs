@@ -1,8 +1,8 @@
+use std::collections::HashSet;
 use std::fmt;
 
 
 
-use failure::Error;
 use nom::{
     self,
     number::complete::{be_i16, be_i32, be_i64, be_u32, be_u64, be_u8},
@@ -12,22 +12,18 @@ use nom::{
 use crate::{
     code_gen::rust::{ToNamedRustParser, ToRustStruct},
     core::*,
-    MAP_OFFSET,
+    Error, MAP_OFFSET,
 };
 
 /// Size of serialized `FileHeader` in bytes
 const FILE_HEADER_SIZE: u64 = 53;
 
-/// Size of serialized TDirectory. Depending on the ROOT version this
-/// may use 32 or 64 bit pointers. This is the maximal (64 bit size).
-const TDIRECTORY_MAX_SIZE: u64 = 42;
-
 /// `RootFile` wraps the most basic information of a ROOT file.
 #[derive(Debug)]
 pub struct RootFile {
     source: Source,
     hdr: FileHeader,
-    items: Vec<FileItem>,
+    root: Directory,
 }
 
 #[derive(Debug, PartialEq)]
@@ -47,16 +43,17 @@ struct FileHeader {
     seek_dir: SeekPointer,
 }
 
+/// The serialized header of a `TDirectory`
 #[derive(Debug, PartialEq)]
-pub struct Directory {
+pub(crate) struct DirectoryHeader {
     version: i16,
     c_time: u32,
     m_time: u32,
-    n_bytes_keys: i32,
+    pub(crate) n_bytes_keys: i32,
     n_bytes_name: i32,
     seek_dir: SeekPointer,
     seek_parent: SeekPointer,
-    seek_keys: SeekPointer,
+    pub(crate) seek_keys: SeekPointer,
 }
 
 named!(
@@ -98,7 +95,7 @@ fn versioned_pointer(input: &[u8], version: i16) -> nom::IResult<&[u8], u64> {
 
 named!(
     #[doc="Directory within a root file; exists on ever file"],
-    directory<&[u8], Directory>,
+    pub(crate) directory<&[u8], DirectoryHeader>,
     do_parse!(
         version: be_i16 >>
             c_time: be_u32 >>
@@ -109,20 +106,23 @@ named!(
             seek_parent: call!(versioned_pointer, version) >>
             seek_keys: call!(versioned_pointer, version) >>
             ({
-                Directory {version, c_time, m_time, n_bytes_keys,
+                DirectoryHeader {version, c_time, m_time, n_bytes_keys,
                            n_bytes_name, seek_dir, seek_parent, seek_keys,
                 }})
     )
 );
 
 impl RootFile {
-    /// Open a new ROOT file either from a `Url`, or from a `Path`
-    /// (not available on `wasm32`).
+    /// Open a new ROOT file either from a `Url`, from a `Path` (not
+    /// available on `wasm32`), from a buffer in memory, or from any
+    /// other `DataSource`. Local files can also be mapped into memory
+    /// with the `unsafe` `Source::mmap`, which avoids copying
+    /// uncompressed data: `RootFile::new(unsafe { Source::mmap(path)? })`.
     pub async fn new<S: Into::<Source>>(source: S) -> Result<Self, Error> {
 	let source = source.into();
         let hdr = source.fetch(0, FILE_HEADER_SIZE).await.and_then(|buf| {
             file_header(&buf)
-                .map_err(|_| format_err!("Failed to parse file header"))
+                .map_err(|e| Error::parse("TFile", 0, &buf, e))
                 .map(|(_i, o)| o)
         })?;
 
@@ -132,27 +132,12 @@ impl RootFile {
             .await
             .and_then(|buf| {
                 directory(&buf)
-                    .map_err(|_| format_err!("Failed to parse TDirectory"))
-                    .map(|(_i, o)| o)
-            })?;
-        let tkey_of_keys = source
-            .fetch(dir.seek_keys, dir.n_bytes_keys as u64)
-            .await
-            .and_then(|buf| {
-                tkey(&buf)
-                    .map_err(|_| format_err!("Failed to parse TKeys"))
+                    .map_err(|e| Error::parse("TDirectory", hdr.seek_dir, &buf, e))
                     .map(|(_i, o)| o)
             })?;
-        let keys = match tkey_headers(&tkey_of_keys.obj) {
-            Ok((_, hdrs)) => Ok(hdrs),
-            _ => Err(format_err!("Expected TKeyHeaders")),
-        }?;
-        let items = keys
-            .iter()
-            .map(|k_hdr| FileItem::new(k_hdr, source.clone()))
-            .collect();
+        let root = Directory::read(source.clone(), "", &dir).await?;
 
-        Ok(RootFile { source, hdr, items })
+        Ok(RootFile { source, hdr, root })
     }
 
     /// Return all `TSreamerInfo` for the data in this file
@@ -163,7 +148,11 @@ impl RootFile {
             .source
             .fetch(self.hdr.seek_info, seek_info_len)
             .await
-            .and_then(|buf| Ok(tkey(&buf).unwrap().1))?;
+            .and_then(|buf| {
+                tkey(&buf)
+                    .map_err(|e| Error::parse("TKey", self.hdr.seek_info, &buf, e))
+                    .map(|(_i, o)| o)
+            })?;
 
         let key_len = info_key.hdr.key_len;
         let context = Context {
@@ -171,45 +160,65 @@ impl RootFile {
             offset: key_len as u64 + MAP_OFFSET,
             s: info_key.obj.as_slice(),
         };
+        let parse_err =
+            |e| Error::parse("TList", self.hdr.seek_info, info_key.obj.as_slice(), e);
         // This TList in the payload has a bytecount in front...
         let wrapped_tlist = |i| tlist(i, &context);
-        let tlist_objs =
-            match length_value!(info_key.obj.as_slice(), checked_byte_count, wrapped_tlist) {
-                Ok((_, l)) => Ok(l.objs),
-                _ => Err(format_err!("Expected TStreamerInfo's TList")),
-            }?;
+        let tlist_objs = length_value!(info_key.obj.as_slice(), checked_byte_count, wrapped_tlist)
+            .map(|(_, l)| l.objs)
+            .map_err(parse_err)?;
         // Mainly this is a TList of `TStreamerInfo`s, but there might
         // be some "rules" in the end
-        let streamers = Ok(tlist_objs
+        let streamers = tlist_objs
             .iter()
             .filter_map(|raw| match raw.classinfo.as_str() {
                 "TStreamerInfo" => Some(raw.obj),
                 _ => None,
             })
-            .map(|i| tstreamerinfo(i, &context).unwrap().1)
-            .collect());
+            .map(|i| tstreamerinfo(i, &context, self.hdr.seek_info))
+            .collect::<Result<Vec<_>, _>>()?;
         // Parse the "rules", if any, from the same tlist
-        let _rules: Vec<_> = tlist_objs
-            .iter()
-            .filter_map(|raw| match raw.classinfo.as_str() {
-                "TList" => Some(raw.obj),
-                _ => None,
-            })
-            .map(|i| {
-                let tl = tlist(i, &context).unwrap().1;
-                // Each `Rule` is a TList of `TObjString`s
-                tl.objs
-                    .iter()
-                    .map(|el| tobjstring(el.obj).unwrap().1)
-                    .collect::<Vec<_>>()
-            })
-            .collect();
-        streamers
+        for i in tlist_objs.iter().filter_map(|raw| match raw.classinfo.as_str() {
+            "TList" => Some(raw.obj),
+            _ => None,
+        }) {
+            let tl = tlist(i, &context).map_err(parse_err)?.1;
+            // Each `Rule` is a TList of `TObjString`s
+            let _rules = tl
+                .objs
+                .iter()
+                .map(|el| tobjstring(el.obj).map(|(_, o)| o).map_err(parse_err))
+                .collect::<Result<Vec<_>, _>>()?;
+        }
+        Ok(streamers)
     }
 
-    /// Slice of the items contained in this file
+    /// Slice of the items contained in the top level directory of this file
     pub fn items(&self) -> &[FileItem] {
-        &self.items
+        self.root.items()
+    }
+
+    /// The item with the given name in the top level directory. If
+    /// there are several cycles of that item, the one with the
+    /// highest cycle is returned.
+    pub fn item_by_name(&self, name: &str) -> Result<&FileItem, Error> {
+        self.root.item_by_name(name)
+    }
+
+    /// The item with the given name and cycle in the top level directory
+    pub fn item_by_name_and_cycle(&self, name: &str, cycle: i16) -> Result<&FileItem, Error> {
+        self.root.item_by_name_and_cycle(name, cycle)
+    }
+
+    /// The top level directory of this file
+    pub fn root_directory(&self) -> &Directory {
+        &self.root
+    }
+
+    /// Find the item at the given `/`-separated path, e.g. `"a/b/tree"`.
+    /// Subdirectories are only parsed if they are on the path.
+    pub async fn get(&self, path: &str) -> Result<FileItem, Error> {
+        self.root.get(path).await
     }
 
     /// Translate the streamer info of this file to a YAML file
@@ -220,39 +229,49 @@ impl RootFile {
         Ok(())
     }
 
-    /// Generate Rust code from the streamer info of this file
+    /// Generate Rust code from the streamer info of this file. The
+    /// output is a module with a struct and a parser for each class;
+    /// it depends on `root_io` and `nom`. The parsers read an object
+    /// starting with its version, as in `Raw::data`.
     pub async fn streamer_info_as_rust<W: fmt::Write>(&self, s: &mut W) -> Result<(), Error> {
         // Add necessary imports at the top of the file
-        writeln!(
-            s,
-            "{}",
-            quote! {
-                use std::marker::PhantomData;
-                use nom::*;
-                use parsers::*;
-                use parsers::utils::*;
-                use core_types::*;
-            }
-            .to_string()
+        s.write_str(
+            r#"#![allow(dead_code, non_camel_case_types, unused_imports, unused_variables)]
+use std::marker::PhantomData;
+
+use nom::bytes::complete::take;
+use nom::combinator::map;
+use nom::multi::{count, length_value};
+use nom::number::complete::*;
+use nom::sequence::preceded;
+use nom::IResult;
+
+use root_io::core::parsers::*;
+use root_io::core::types::*;
+
+"#,
         )?;
 
+        // A class may be described more than once
+        let mut streamers = self.streamers().await?;
+        let mut seen = HashSet::new();
+        streamers.retain(|el| seen.insert(el.named.name.clone()));
+
         // generate structs
-        for el in &self.streamers().await? {
+        for el in &streamers {
             // The structs contain comments which introduce line breaks; i.e. readable
             writeln!(s, "{}", el.to_struct().to_string())?;
         }
 
         // generate parsers
-        for el in &self.streamers().await? {
+        for el in &streamers {
             // The parsers have no comments, but are ugly; We introduce some
             // Linebreaks here to not have rustfmt choke later (doing it later
             // is inconvinient since the comments in the structs might contain
             // the patterns
             let parsers = el.to_named_parser().to_string();
             let parsers = parsers.replace(",", ",\n");
-            let parsers = parsers.replace(">>", ">>\n");
-            // macro names are generated as my_macro ! (...) by `quote`
-            let parsers = parsers.replace(" ! (", "!(");
+            let parsers = parsers.replace(";", ";\n");
             writeln!(s, "{}", parsers)?;
         }
         Ok(())
@@ -284,7 +303,7 @@ mod test {
                 .await
                 .and_then(|buf| {
                     file_header(&buf)
-                        .map_err(|_| format_err!("Failed to parse file header"))
+                        .map_err(|e| Error::parse("TFile", 0, &buf, e))
                         .map(|(_i, o)| o)
                 })
                 .unwrap();
@@ -320,7 +339,7 @@ mod test {
                 .await
                 .and_then(|buf| {
                     file_header(&buf)
-                        .map_err(|_| format_err!("Failed to parse file header"))
+                        .map_err(|e| Error::parse("TFile", 0, &buf, e))
                         .map(|(_i, o)| o)
                 })
                 .unwrap();
@@ -330,13 +349,13 @@ mod test {
                 .await
                 .and_then(|buf| {
                     directory(&buf)
-                        .map_err(|_| format_err!("Failed to parse file header"))
+                        .map_err(|e| Error::parse("TDirectory", hdr.seek_dir, &buf, e))
                         .map(|(_i, o)| o)
                 })
                 .unwrap();
             assert_eq!(
                 dir,
-                Directory {
+                DirectoryHeader {
                     version: 5,
                     c_time: 1418768412,
                     m_time: 1418768412,
@@ -365,7 +384,7 @@ mod test {
                 .await
                 .and_then(|buf| {
                     tkey(&buf)
-                        .map_err(|_| format_err!("Failed to parse file header"))
+                        .map_err(|e| Error::parse("TKey", 1117, &buf, e))
                         .map(|(_i, o)| o)
                 })
                 .unwrap();
@@ -1,16 +1,21 @@
 
 
-use failure::Error;
+use histogram::{Histogram, NDim};
 use nom::*;
 
-use crate::core::{checked_byte_count, decompress, Context, Source, TKeyHeader};
-use crate::tree_reader::{ttree, Tree};
+use crate::core::{
+    checked_byte_count, decompress, directory, Context, Datime, Directory, Interpreter, Source,
+    TKeyHeader, TStreamerInfo, TDIRECTORY_MAX_SIZE,
+};
+use crate::histograms::{graph, hist, hist_class, profile, Graph};
+use crate::tree_reader::{ttree, Tree, Value};
+use crate::Error;
 
 /// Describes a single item within this file (e.g. a `Tree`)
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct FileItem {
     source: Source,
-    tkey_hdr: TKeyHeader,
+    pub(crate) tkey_hdr: TKeyHeader,
 }
 
 impl FileItem {
@@ -22,10 +27,115 @@ impl FileItem {
         }
     }
 
-    /// Parse this item as a `Tree`. Instead one could also call
-    /// `parse_with` with the `ttree` parser
+    /// Parse this item as a `Tree`. The trees of its friends are
+    /// opened once they are needed; see `Tree::open_branch`.
     pub async fn as_tree(&self) -> Result<Tree, Error> {
-        self.parse_with(ttree).await
+        let tree = self.parse_with(ttree).await??;
+        Ok(tree.at_key(self.tkey_hdr.seek_key))
+    }
+
+    /// Parse this item as a histogram with `D` dimensions, e.g.
+    /// `[usize; 1]`. `TH1`, `TH2` and `TH3` histograms of any type of
+    /// bin content are supported, as well as `TProfile`s. For the
+    /// latter, the mean of each bin is returned as its count and the
+    /// squared error of the mean as its sum of squared weights.
+    pub async fn as_histogram<D: NDim>(&self) -> Result<Histogram<f64, D>, Error> {
+        let class = self.class_name();
+        if class == "TProfile" {
+            self.parse_with(|i: &[u8], _: &Context| profile(i))
+                .await?
+                .to_histogram()
+        } else if hist_class(class).is_some() {
+            self.parse_with(|i: &[u8], _: &Context| hist(i, class))
+                .await?
+                .to_histogram()
+        } else {
+            Err(Error::Unsupported(format!(
+                "Reading `{}` as a histogram",
+                class
+            )))
+        }
+    }
+
+    /// Parse this item as a `TGraph` or `TGraphErrors`
+    pub async fn as_graph(&self) -> Result<Graph, Error> {
+        let class = self.class_name();
+        if class != "TGraph" && class != "TGraphErrors" {
+            return Err(Error::Unsupported(format!(
+                "Reading `{}` as a graph",
+                class
+            )));
+        }
+        self.parse_with(|i: &[u8], _: &Context| graph(i, class))
+            .await
+    }
+
+    /// Decode the object of this item, whatever its class, into a
+    /// `Value::Struct` by following the streamer info of its class
+    /// (see `RootFile::streamers`)
+    pub async fn as_value(&self, streamers: &[TStreamerInfo]) -> Result<Value, Error> {
+        let class = self.class_name();
+        let interpreter = Interpreter::new(streamers, class)?;
+        self.parse_with(|i, context| interpreter.with_context(context).versioned(i, class))
+            .await
+            .map_err(|e| interpreter.annotate(e))
+    }
+
+    /// The name of the object stored in this item
+    pub fn object_name(&self) -> &str {
+        &self.tkey_hdr.obj_name
+    }
+
+    /// The title of the object stored in this item
+    pub fn title(&self) -> &str {
+        &self.tkey_hdr.obj_title
+    }
+
+    /// The ROOT class name of the object stored in this item (e.g. `TTree`)
+    pub fn class_name(&self) -> &str {
+        &self.tkey_hdr.class_name
+    }
+
+    /// The cycle of this item. ROOT keeps several versions
+    /// ("cycles") of an object with the same name; higher is newer.
+    pub fn cycle(&self) -> i16 {
+        self.tkey_hdr.cycle
+    }
+
+    /// The time at which this item was written
+    pub fn datime(&self) -> Datime {
+        Datime::from_packed(self.tkey_hdr.datime)
+    }
+
+    /// Is this item a (sub-)directory?
+    pub fn is_directory(&self) -> bool {
+        matches!(
+            self.tkey_hdr.class_name.as_str(),
+            "TDirectory" | "TDirectoryFile"
+        )
+    }
+
+    /// Open this item as a subdirectory. This reads the list of keys
+    /// of that directory, but not the content of its items
+    pub async fn as_directory(&self) -> Result<Directory, Error> {
+        if !self.is_directory() {
+            return Err(Error::NotFound(format!(
+                "`{}` is not a directory",
+                self.tkey_hdr.obj_name
+            )));
+        }
+        let start = self.tkey_hdr.seek_key + self.tkey_hdr.key_len as u64;
+        let len = (self.tkey_hdr.total_size as u64).saturating_sub(self.tkey_hdr.key_len as u64);
+        let hdr = self
+            .source
+            .fetch(start, len.min(TDIRECTORY_MAX_SIZE))
+            .await
+            .and_then(|buf| {
+                directory(&buf)
+                    .map_err(|e| Error::parse(self.class_name(), self.tkey_hdr.seek_key, &buf, e))
+                    .map(|(_i, o)| o)
+            })?;
+        Directory::read(self.source.clone(), &self.tkey_hdr.obj_name, &hdr).await
     }
 
     /// Information about this file item in Human readable form
@@ -40,22 +150,25 @@ impl FileItem {
     }
 
     /// Read (and possibly decompress) data from disk and parse it as
-    /// the appropriate type using the TStreamerInfo types.
+    /// the appropriate type using the TStreamerInfo types, e.g. with
+    /// a parser generated by `RootFile::streamer_info_as_rust`.
     /// The return type of the parser function must not contain a
     /// reference to the parsed buffer
-    pub(crate) async fn parse_with<O, F>(&self, parser: F) -> Result<O, Error>
+    pub async fn parse_with<O, F>(&self, parser: F) -> Result<O, Error>
     where
         F: for<'s> Fn(&'s [u8], &'s Context<'s>) -> IResult<&'s [u8], O>,
     {
         let start = self.tkey_hdr.seek_key + self.tkey_hdr.key_len as u64;
-        let len = self.tkey_hdr.total_size - self.tkey_hdr.key_len as u32;
+        let len = self
+            .tkey_hdr
+            .total_size
+            .saturating_sub(self.tkey_hdr.key_len as u32);
         let comp_buf = self.source.fetch(start, len as u64).await?;
 
         let buf = {
             if self.tkey_hdr.total_size < self.tkey_hdr.uncomp_len {
                 // Decompress the read buffer; buf is Vec<u8>
-                let (_, buf) = decompress(comp_buf.as_slice()).unwrap();
-                buf
+                decompress(comp_buf.as_slice(), self.tkey_hdr.uncomp_len as usize)?
             } else {
                 comp_buf
             }
@@ -69,10 +182,8 @@ impl FileItem {
         };
         // wrap parser in a byte count
         let res = length_value!(s, checked_byte_count, call!(&parser, &context));
-        match res {
-            Ok((_, obj)) => Ok(obj),
-            _ => Err(format_err!("Supplied parser failed!")),
-        }
+        res.map(|(_, obj)| obj)
+            .map_err(|e| Error::parse(self.class_name(), self.tkey_hdr.seek_key, s, e))
     }
 }
 
@@ -93,6 +204,59 @@ mod tests {
         assert_eq!(f.streamers().await.unwrap().len(), 18);
     }
 
+    #[tokio::test]
+    async fn key_accessors() {
+        use crate::core::Datime;
+        let path = Path::new("./src/test_data/simple.root");
+        let f = RootFile::new(path).await.expect("Failed to open file");
+        let item = f.item_by_name("tree").unwrap();
+        assert_eq!(item.object_name(), "tree");
+        assert_eq!(item.class_name(), "TTree");
+        assert_eq!(item.title(), "fake data");
+        assert_eq!(item.cycle(), 1);
+        assert_eq!(
+            item.datime(),
+            Datime {
+                year: 2016,
+                month: 2,
+                day: 8,
+                hour: 11,
+                minute: 0,
+                second: 28
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn tree_as_value() {
+        use crate::tree_reader::Value;
+        let path = Path::new("./src/test_data/simple.root");
+        let f = RootFile::new(path).await.expect("Failed to open file");
+        let streamers = f.streamers().await.unwrap();
+        let value = f.items()[0].as_value(&streamers).await.unwrap();
+        assert_eq!(
+            value.member("fName"),
+            Some(&Value::String("tree".to_string()))
+        );
+        assert_eq!(value.member("fEntries"), Some(&Value::I64(4)));
+        let branch_names: Vec<_> = match value.member("fBranches") {
+            Some(Value::Vec(branches)) => branches.iter().map(|b| b.member("fName")).collect(),
+            other => panic!("Unexpected branches {:?}", other),
+        };
+        let name = |n: &str| Some(Value::String(n.to_string()));
+        assert_eq!(
+            branch_names,
+            vec![
+                name("one").as_ref(),
+                name("two").as_ref(),
+                name("three").as_ref()
+            ]
+        );
+        // Objects of other classes do not have a streamer info
+        let streamers = &streamers[..0];
+        assert!(f.items()[0].as_value(streamers).await.is_err());
+    }
+
     // Skip this test on MacOs since the downloaded file is not working on Travis
     #[tokio::test]
     #[cfg(all(not(target_os = "macos"), not(target_arch = "wasm32")))]
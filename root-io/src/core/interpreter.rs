@@ -0,0 +1,660 @@
+//! Decode objects of arbitrary classes at runtime by walking the
+//! data members listed in the `TStreamerInfo` of their class. This
+//! is the dynamic counterpart of the parsers generated by `code_gen`.
+
+use std::sync::{Arc, Mutex};
+
+use nom::combinator::{cond, map};
+use nom::error::ErrorKind;
+use nom::multi::{count, length_data};
+use nom::number::complete::*;
+use nom::sequence::tuple;
+use nom::IResult;
+
+use crate::core::*;
+use crate::tree_reader::{Double32, Scalar, Value};
+use crate::Error;
+
+/// Version bit of STL containers streamed member-wise
+const MEMBERWISE: u16 = 0x4000;
+
+/// Why reading an object failed even though the layout of its class
+/// was checked, e.g. because a `std::vector` was streamed
+/// member-wise. Nom errors can't carry this; see `Interpreter::annotate`.
+#[derive(Debug)]
+struct Failure {
+    class: String,
+    member: String,
+    reason: String,
+}
+
+/// Decodes objects into `Value::Struct`s following the
+/// `TStreamerInfo`s of their classes
+pub(crate) struct Interpreter<'a> {
+    streamers: &'a [TStreamerInfo],
+    /// Needed to look up the class of objects behind pointers which
+    /// refer to a class defined earlier in the buffer. Without it,
+    /// only pointers which define the class of their object can be
+    /// read.
+    context: Option<&'a Context<'a>>,
+    /// The last failure which is not an ordinary parse error; shared
+    /// with the copies made by `with_context`
+    failure: Arc<Mutex<Option<Failure>>>,
+}
+
+impl<'a> Interpreter<'a> {
+    /// Create an interpreter for objects of `class`. Fails if the
+    /// streamer info of `class`, or of a class it contains, is
+    /// missing or uses a layout which is not supported. Classes of
+    /// objects behind pointers are only known while reading; they
+    /// cannot be checked here.
+    pub(crate) fn new(streamers: &'a [TStreamerInfo], class: &str) -> Result<Self, Error> {
+        let interpreter = Interpreter {
+            streamers,
+            context: None,
+            failure: Default::default(),
+        };
+        interpreter.check(class, &mut vec![])?;
+        Ok(interpreter)
+    }
+
+    /// The same interpreter, following references to classes within
+    /// the buffer of `context`
+    pub(crate) fn with_context<'b>(&self, context: &'b Context<'b>) -> Interpreter<'b>
+    where
+        'a: 'b,
+    {
+        Interpreter {
+            streamers: self.streamers,
+            context: Some(context),
+            failure: self.failure.clone(),
+        }
+    }
+
+    /// Add the class and data member at which this interpreter
+    /// failed, and why, to `err`, the error made from that failure.
+    /// Ordinary parse errors, e.g. of truncated buffers, are returned
+    /// as they are.
+    pub(crate) fn annotate(&self, err: Error) -> Error {
+        let failure = self
+            .failure
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take();
+        match (err, failure) {
+            (Error::Parse { class, offset, msg }, Some(f)) => Error::Parse {
+                class,
+                offset,
+                msg: format!(
+                    "{} (member `{}` of class `{}`: {})",
+                    msg, f.member, f.class, f.reason
+                ),
+            },
+            (err, _) => err,
+        }
+    }
+
+    /// Record why reading the data member `member` of `class` failed
+    /// at `input`, and return the error to abort parsing with
+    fn fail<'s>(
+        &self,
+        input: &'s [u8],
+        class: &str,
+        member: &str,
+        reason: String,
+    ) -> nom::Err<(&'s [u8], ErrorKind)> {
+        *self.failure.lock().unwrap_or_else(|e| e.into_inner()) = Some(Failure {
+            class: class.to_string(),
+            member: member.to_string(),
+            reason,
+        });
+        nom::Err::Failure((input, ErrorKind::Switch))
+    }
+
+    fn info(&self, class: &str) -> Option<&'a TStreamerInfo> {
+        self.streamers.iter().find(|info| info.named.name == class)
+    }
+
+    /// Verify recursively that all members of `class` can be read
+    fn check(&self, class: &str, seen: &mut Vec<String>) -> Result<(), Error> {
+        if is_builtin(class) || seen.iter().any(|c| c == class) {
+            return Ok(());
+        }
+        seen.push(class.to_string());
+        let info = self
+            .info(class)
+            .ok_or_else(|| Error::NotFound(format!("Streamer info of class `{}`", class)))?;
+        for member in &info.data_members {
+            let el = member.elem();
+            let unsupported = || {
+                Error::Unsupported(format!(
+                    "Member `{}` of type `{}` ({:?}) in class `{}`",
+                    el.name.name, el.type_name, el.el_type, class
+                ))
+            };
+            match member {
+                TStreamer::Base { .. } => match el.el_type {
+                    TypeID::TObject | TypeID::InvalidOrCounter(-1) => {}
+                    TypeID::Base | TypeID::Object | TypeID::Named => {
+                        self.check(&el.name.name, seen)?
+                    }
+                    _ => return Err(unsupported()),
+                },
+                TStreamer::BasicType { .. } => {
+                    match el.el_type {
+                        TypeID::Primitive(ref id) | TypeID::Offset(ref id) => scalar(id, el),
+                        _ => None,
+                    }
+                    .ok_or_else(unsupported)?;
+                }
+                TStreamer::BasicPointer { .. } => {
+                    match el.el_type {
+                        TypeID::Array(ref id) => scalar(id, el),
+                        _ => None,
+                    }
+                    .ok_or_else(unsupported)?;
+                }
+                TStreamer::Loop { .. } => self.check(pointee(&el.type_name), seen)?,
+                TStreamer::Object { .. } | TStreamer::ObjectAny { .. } => {
+                    self.check(&el.type_name, seen)?
+                }
+                TStreamer::ObjectPointer { .. } | TStreamer::ObjectAnyPointer { .. } => {
+                    if !is_pointer(el) {
+                        self.check(pointee(&el.type_name), seen)?
+                    }
+                }
+                TStreamer::String { .. } | TStreamer::StlString { .. } => {}
+                TStreamer::Stl { vtype, ctype, .. } => match (vtype, ctype) {
+                    (StlTypeID::Vector, TypeID::Primitive(id)) => {
+                        scalar(id, el).ok_or_else(unsupported)?;
+                    }
+                    (StlTypeID::Vector, _) => {
+                        let inner = vector_element(&el.type_name).ok_or_else(unsupported)?;
+                        if inner != "string" && !inner.ends_with('*') {
+                            self.check(inner, seen)?
+                        }
+                    }
+                    (StlTypeID::Bitset, _) => return Err(unsupported()),
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Read an object of `class` which is preceded by its byte
+    /// count and version, as stored in baskets of object branches
+    /// and as data members
+    pub(crate) fn object<'s>(&self, input: &'s [u8], class: &str) -> IResult<&'s [u8], Value> {
+        match class {
+            // `TObject` has no byte count
+            "TObject" => self.versioned(input, class),
+            "TString" => map(string, Value::String)(input),
+            _ if class.starts_with("TArray") => tarray(input, class),
+            _ => {
+                let (input, buf) = length_data(checked_byte_count)(input)?;
+                let (_, value) = self.versioned(buf, class)?;
+                Ok((input, value))
+            }
+        }
+    }
+
+    /// Read an object of `class` which starts with its version, as
+    /// the payload of a `FileItem`
+    pub(crate) fn versioned<'s>(&self, input: &'s [u8], class: &str) -> IResult<&'s [u8], Value> {
+        match class {
+            "TObject" => {
+                let (input, tobj) = tobject(input)?;
+                Ok((input, Value::Struct(tobject_members(&tobj))))
+            }
+            // Not always part of the streamer infos of a file
+            "TNamed" if self.info(class).is_none() => {
+                let (input, (_ver, tobj, name, title)) =
+                    tuple((be_u16, tobject, string, string))(input)?;
+                let mut members = tobject_members(&tobj);
+                members.push(("fName".to_string(), Value::String(name)));
+                members.push(("fTitle".to_string(), Value::String(title)));
+                Ok((input, Value::Struct(members)))
+            }
+            "TList" | "THashList" | "TObjArray" => self.collection(input, class),
+            _ => {
+                let info = self
+                    .info(class)
+                    .ok_or(nom::Err::Failure((input, ErrorKind::Verify)))?;
+                let (input, _ver) = be_u16(input)?;
+                let mut members = vec![];
+                let input = self.members(input, info, &mut members)?;
+                Ok((input, Value::Struct(members)))
+            }
+        }
+    }
+
+    /// Read a collection of objects of any class, i.e. a `TList` or
+    /// a `TObjArray`, into a `Value::Vec`
+    fn collection<'s>(&self, input: &'s [u8], class: &str) -> IResult<&'s [u8], Value> {
+        let is_list = class != "TObjArray";
+        let (input, (_ver, _tobj, _name, n)) = tuple((be_u16, tobject, string, be_i32))(input)?;
+        let (input, _lower_bound) = cond(!is_list, be_i32)(input)?;
+        let element = move |i| {
+            let (i, value) = self.pointer(i)?;
+            // Lists store an option with each element
+            let (i, _option) = cond(is_list, length_data(be_u8))(i)?;
+            Ok((i, value))
+        };
+        map(count(element, n.max(0) as usize), Value::Vec)(input)
+    }
+
+    /// Read a pointer to an object, which defines or refers to the
+    /// class of the object, or is null
+    fn pointer<'s>(&self, input: &'s [u8]) -> IResult<&'s [u8], Value> {
+        let (rest, class) = classinfo(input)?;
+        match class {
+            ClassInfo::References(0) => Ok((rest, Value::Null)),
+            // Objects are only read once, even if they are pointed to
+            // several times
+            ClassInfo::References(_) => Ok((rest, Value::Reference)),
+            ClassInfo::New(class) => {
+                let (rest, buf) = length_data(checked_byte_count)(rest)?;
+                let (_, value) = self.versioned(buf, &class)?;
+                Ok((rest, value))
+            }
+            ClassInfo::Exists(_) => {
+                let context = self
+                    .context
+                    .ok_or(nom::Err::Failure((input, ErrorKind::Verify)))?;
+                let (rest, raw) = raw(input, context)?;
+                let (_, value) = self
+                    .versioned(raw.obj, &raw.classinfo)
+                    .map_err(|e| reborrow_err(input, e))?;
+                Ok((rest, value))
+            }
+        }
+    }
+
+    /// Read the data members of `info` into `out`
+    fn members<'s>(
+        &self,
+        mut input: &'s [u8],
+        info: &TStreamerInfo,
+        out: &mut Vec<(String, Value)>,
+    ) -> Result<&'s [u8], nom::Err<(&'s [u8], ErrorKind)>> {
+        let class = &info.named.name;
+        for member in &info.data_members {
+            let el = member.elem();
+            let fail = |i, reason| self.fail(i, class, &el.name.name, reason);
+            let unsupported = |i| fail(i, format!("type `{}` is not supported", el.type_name));
+            let no_counter = |i, cname| fail(i, format!("its counter `{}` was not read", cname));
+            let (rest, value) = match member {
+                TStreamer::Base { .. } => {
+                    let (rest, base) = match el.el_type {
+                        TypeID::TObject => self.versioned(input, "TObject")?,
+                        TypeID::InvalidOrCounter(-1) => {
+                            let (rest, bytes) =
+                                nom::bytes::complete::take(el.size as usize)(input)?;
+                            let bytes = bytes.iter().cloned().map(Value::U8).collect();
+                            (rest, Value::Vec(bytes))
+                        }
+                        _ => self.object(input, &el.name.name)?,
+                    };
+                    // Members of base classes are part of the derived class
+                    match base {
+                        Value::Struct(members) => out.extend(members),
+                        base => out.push((el.name.name.clone(), base)),
+                    }
+                    input = rest;
+                    continue;
+                }
+                TStreamer::BasicType { .. } => match el.el_type {
+                    TypeID::Primitive(ref id) => {
+                        let s = scalar(id, el).ok_or_else(|| unsupported(input))?;
+                        s.parse(input)?
+                    }
+                    TypeID::Offset(ref id) => {
+                        let s = scalar(id, el).ok_or_else(|| unsupported(input))?;
+                        let n = el.array_len.max(0) as usize;
+                        map(count(move |i| s.parse(i), n), Value::Array)(input)?
+                    }
+                    _ => return Err(unsupported(input)),
+                },
+                TStreamer::BasicPointer { cname, .. } => {
+                    let s = match el.el_type {
+                        TypeID::Array(ref id) => scalar(id, el),
+                        _ => None,
+                    }
+                    .ok_or_else(|| unsupported(input))?;
+                    let n = counter(out, cname).ok_or_else(|| no_counter(input, cname))?;
+                    let (rest, is_array) = be_u8(input)?;
+                    if is_array == 0 {
+                        (rest, Value::Vec(vec![]))
+                    } else {
+                        map(count(move |i| s.parse(i), n), Value::Vec)(rest)?
+                    }
+                }
+                TStreamer::Loop { cname, .. } => {
+                    let n = counter(out, cname).ok_or_else(|| no_counter(input, cname))?;
+                    let class = pointee(&el.type_name);
+                    // Skip the byte count and version of the loop
+                    let (rest, _) = nom::bytes::complete::take(6usize)(input)?;
+                    map(count(move |i| self.object(i, class), n), Value::Vec)(rest)?
+                }
+                TStreamer::Object { .. } | TStreamer::ObjectAny { .. } => {
+                    self.object(input, &el.type_name)?
+                }
+                TStreamer::ObjectPointer { .. } | TStreamer::ObjectAnyPointer { .. } => {
+                    if is_pointer(el) {
+                        self.pointer(input)?
+                    } else {
+                        // Pointers which are never null are stored as objects
+                        self.object(input, pointee(&el.type_name))?
+                    }
+                }
+                TStreamer::String { .. } => map(string, Value::String)(input)?,
+                TStreamer::StlString { .. } => {
+                    let (rest, buf) = length_data(checked_byte_count)(input)?;
+                    let (_, s) = nom::sequence::preceded(be_u16, string)(buf)?;
+                    (rest, Value::String(s))
+                }
+                TStreamer::Stl { vtype, ctype, .. } => match vtype {
+                    StlTypeID::Vector => self.vector(input, class, el, ctype)?,
+                    StlTypeID::Bitset => return Err(unsupported(input)),
+                },
+            };
+            out.push((el.name.name.clone(), value));
+            input = rest;
+        }
+        Ok(input)
+    }
+
+    /// Read a `std::vector` data member of `class`
+    fn vector<'s>(
+        &self,
+        input: &'s [u8],
+        class: &str,
+        el: &TStreamerElement,
+        ctype: &TypeID,
+    ) -> IResult<&'s [u8], Value> {
+        let fail = |reason: &str| self.fail(input, class, &el.name.name, reason.to_string());
+        let unsupported = || fail(&format!("type `{}` is not supported", el.type_name));
+        let (rest, buf) = length_data(checked_byte_count)(input)?;
+        let (buf, ver) = be_u16(buf)?;
+        if ver & MEMBERWISE != 0 {
+            return Err(fail("vectors streamed member-wise are not supported"));
+        }
+        let (buf, n) = be_i32(buf)?;
+        let n = n.max(0) as usize;
+        let (_, elements) = match ctype {
+            TypeID::Primitive(id) => {
+                let s = scalar(id, el).ok_or_else(unsupported)?;
+                count(move |i| s.parse(i), n)(buf)?
+            }
+            _ => match vector_element(&el.type_name) {
+                Some("string") => count(map(string, Value::String), n)(buf)?,
+                Some(class) if class.ends_with('*') => count(move |i| self.pointer(i), n)(buf)?,
+                Some(class) => count(move |i| self.object(i, class), n)(buf)?,
+                None => return Err(unsupported()),
+            },
+        };
+        Ok((rest, Value::Vec(elements)))
+    }
+}
+
+/// Classes with a custom streamer which are read without a
+/// `TStreamerInfo`
+fn is_builtin(class: &str) -> bool {
+    matches!(
+        class,
+        "TObject" | "TNamed" | "TString" | "TList" | "THashList" | "TObjArray"
+    ) || class.starts_with("TArray")
+}
+
+/// The data members of a `TObject`
+fn tobject_members(tobj: &TObject) -> Vec<(String, Value)> {
+    vec![
+        ("fUniqueID".to_string(), Value::U32(tobj.id)),
+        ("fBits".to_string(), Value::U32(tobj.bits.bits())),
+    ]
+}
+
+/// Is this member stored as a pointer which may be null, rather than
+/// as an object (ROOT's `kObjectP` and `kAnyP` vs `kObjectp` and `kAnyp`)
+pub(crate) fn is_pointer(el: &TStreamerElement) -> bool {
+    matches!(el.el_type, TypeID::ObjectP | TypeID::AnyP)
+}
+
+/// The class pointed to by a type such as `TObject*`
+pub(crate) fn pointee(type_name: &str) -> &str {
+    type_name.trim_end_matches('*').trim()
+}
+
+/// The type of the elements of a type such as `vector<int>`
+pub(crate) fn vector_element(type_name: &str) -> Option<&str> {
+    let inner = type_name
+        .trim()
+        .strip_prefix("vector<")?
+        .strip_suffix('>')?;
+    Some(inner.trim())
+}
+
+/// The scalar of a primitive member; `None` for types which are not
+/// supported, such as `char*`, and for `Double32_t` and `Float16_t`
+/// members whose range cannot be parsed
+fn scalar(id: &PrimitiveID, el: &TStreamerElement) -> Option<Scalar> {
+    Some(match id.0 {
+        1 => Scalar::I8,
+        2 => Scalar::I16,
+        3 | 6 => Scalar::I32,
+        4 | 16 => Scalar::I64,
+        5 => Scalar::F32,
+        8 => Scalar::F64,
+        9 => Scalar::Double32(Double32::from_title(&el.name.title).ok()?),
+        11 => Scalar::U8,
+        12 => Scalar::U16,
+        13 | 15 => Scalar::U32,
+        14 | 17 => Scalar::U64,
+        18 => Scalar::Bool,
+        19 => Scalar::Double32(Double32::float16_from_title(&el.name.title).ok()?),
+        _ => return None,
+    })
+}
+
+/// The value of the counter `name` which was read before the array it
+/// counts
+fn counter(members: &[(String, Value)], name: &str) -> Option<usize> {
+    let n = match members.iter().rev().find(|(n, _)| n == name)?.1 {
+        Value::I8(n) => i64::from(n),
+        Value::I16(n) => i64::from(n),
+        Value::I32(n) => i64::from(n),
+        Value::I64(n) => n,
+        Value::U8(n) => i64::from(n),
+        Value::U16(n) => i64::from(n),
+        Value::U32(n) => i64::from(n),
+        Value::U64(n) => n as i64,
+        _ => return None,
+    };
+    Some(n.max(0) as usize)
+}
+
+/// Read a `TArray` of the type given by the last letter of `class`
+/// (e.g. `D` for `TArrayD`); `TArray`s have no header
+fn tarray<'s>(input: &'s [u8], class: &str) -> IResult<&'s [u8], Value> {
+    let (input, n) = be_i32(input)?;
+    let n = n.max(0) as usize;
+    let (input, values) = match class {
+        "TArrayC" => count(map(be_i8, Value::I8), n)(input)?,
+        "TArrayS" => count(map(be_i16, Value::I16), n)(input)?,
+        "TArrayI" => count(map(be_i32, Value::I32), n)(input)?,
+        "TArrayL" | "TArrayL64" => count(map(be_i64, Value::I64), n)(input)?,
+        "TArrayF" => count(map(be_f32, Value::F32), n)(input)?,
+        "TArrayD" => count(map(be_f64, Value::F64), n)(input)?,
+        _ => return Err(nom::Err::Failure((input, ErrorKind::Switch))),
+    };
+    Ok((input, Value::Vec(values)))
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::core::RootFile;
+    use crate::test_utils::{tnamed, tstring, with_byte_count};
+
+    /// A serialized `std::vector` with `n` elements
+    fn vector(n: i32, elements: Vec<u8>) -> Vec<u8> {
+        let mut out = vec![0, 9];
+        out.extend(&n.to_be_bytes());
+        out.extend(elements);
+        with_byte_count(out)
+    }
+
+    /// A serialized `Event` of `small-evnt-tree-fullsplit.root`
+    fn event() -> Vec<u8> {
+        let mut buf = vec![0, 1];
+        buf.extend(tstring("beg"));
+        buf.extend(&(-16i16).to_be_bytes());
+        buf.extend(&(-32i32).to_be_bytes());
+        buf.extend(&(-64i64).to_be_bytes());
+        buf.extend(&16u16.to_be_bytes());
+        buf.extend(&32u32.to_be_bytes());
+        buf.extend(&64u64.to_be_bytes());
+        buf.extend(&1.5f32.to_be_bytes());
+        buf.extend(&2.5f64.to_be_bytes());
+        buf.extend(tstring("evt-0"));
+        let mut p3 = vec![0, 1];
+        p3.extend(&1i32.to_be_bytes());
+        p3.extend(&2f64.to_be_bytes());
+        p3.extend(&3i32.to_be_bytes());
+        buf.extend(with_byte_count(p3));
+        // Fixed size arrays of 10 elements each
+        for size in &[2, 4, 8, 2, 4, 8, 4, 8] {
+            buf.extend(vec![0; 10 * size]);
+        }
+        buf.extend(&2i32.to_be_bytes());
+        // Arrays of `N` elements behind pointers
+        buf.push(1);
+        buf.extend(&[0, 1, 0, 2]);
+        for size in &[4, 8, 2, 4, 8, 4, 8] {
+            buf.push(1);
+            buf.extend(vec![0; 2 * size]);
+        }
+        // `std::string` with its own byte count and version
+        let mut std_str = vec![0, 1];
+        std_str.extend(tstring("std"));
+        buf.extend(with_byte_count(std_str));
+        buf.extend(vector(0, vec![]));
+        buf.extend(vector(
+            3,
+            [3i32, 4, 5]
+                .iter()
+                .flat_map(|v| v.to_be_bytes().to_vec())
+                .collect(),
+        ));
+        for _ in 0..6 {
+            buf.extend(vector(0, vec![]));
+        }
+        let mut strings = tstring("a");
+        strings.extend(tstring("bc"));
+        buf.extend(vector(2, strings));
+        buf.extend(tstring("end"));
+        with_byte_count(buf)
+    }
+
+    #[tokio::test]
+    async fn event_object() {
+        let path = Path::new("./src/test_data/small-evnt-tree-fullsplit.root");
+        let f = RootFile::new(path).await.expect("Failed to open file");
+        let streamers = f.streamers().await.unwrap();
+        let interpreter = Interpreter::new(&streamers, "Event").unwrap();
+        let buf = event();
+        let (rest, event) = interpreter.object(&buf, "Event").unwrap();
+        assert!(rest.is_empty());
+        let member = |name| event.member(name).unwrap().clone();
+        assert_eq!(member("Beg"), Value::String("beg".to_string()));
+        assert_eq!(member("I64"), Value::I64(-64));
+        assert_eq!(member("F32"), Value::F32(1.5));
+        assert_eq!(member("Str"), Value::String("evt-0".to_string()));
+        let p3 = member("P3");
+        assert_eq!(p3.member("Px"), Some(&Value::I32(1)));
+        assert_eq!(p3.member("Py"), Some(&Value::F64(2.)));
+        assert_eq!(member("ArrayF64"), Value::Array(vec![Value::F64(0.); 10]));
+        assert_eq!(member("N"), Value::I32(2));
+        assert_eq!(
+            member("SliceI16"),
+            Value::Vec(vec![Value::I16(1), Value::I16(2)])
+        );
+        assert_eq!(member("SliceF64"), Value::Vec(vec![Value::F64(0.); 2]));
+        assert_eq!(member("StdStr"), Value::String("std".to_string()));
+        assert_eq!(member("StlVecI16"), Value::Vec(vec![]));
+        assert_eq!(
+            member("StlVecI32"),
+            Value::Vec(vec![Value::I32(3), Value::I32(4), Value::I32(5)])
+        );
+        assert_eq!(
+            member("StlVecStr"),
+            Value::Vec(vec![
+                Value::String("a".to_string()),
+                Value::String("bc".to_string())
+            ])
+        );
+        assert_eq!(member("End"), Value::String("end".to_string()));
+        assert!(Interpreter::new(&streamers, "NoSuchClass").is_err());
+    }
+
+    #[tokio::test]
+    async fn memberwise_vector() {
+        let path = Path::new("./src/test_data/small-evnt-tree-fullsplit.root");
+        let f = RootFile::new(path).await.expect("Failed to open file");
+        let streamers = f.streamers().await.unwrap();
+        let interpreter = Interpreter::new(&streamers, "Event").unwrap();
+        let mut buf = event();
+        // Mark the (empty) `StlVecI16` as streamed member-wise
+        let pos = buf
+            .windows(6)
+            .position(|w| w == [0, 9, 0, 0, 0, 0])
+            .unwrap();
+        buf[pos] = 0x40;
+        let err = interpreter.object(&buf, "Event").unwrap_err();
+        let err = interpreter.annotate(Error::parse("Event", 0, &buf, err));
+        let msg = err.to_string();
+        assert!(
+            msg.contains("member `StlVecI16` of class `Event`"),
+            "{}",
+            msg
+        );
+        assert!(msg.contains("member-wise"), "{}", msg);
+        // Truncated buffers are ordinary parse errors
+        let err = interpreter.object(&buf[..10], "Event").unwrap_err();
+        let err = interpreter.annotate(Error::parse("Event", 0, &buf, err));
+        assert!(!err.to_string().contains("member"), "{}", err);
+    }
+
+    #[test]
+    fn pointers_in_collections() {
+        // A `TObjArray` holding a new `TNamed`, a null pointer and a
+        // reference to the first object
+        let mut buf = vec![0, 3, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0];
+        buf.extend(tstring(""));
+        buf.extend(&3i32.to_be_bytes());
+        buf.extend(&0i32.to_be_bytes());
+        let mut pointer = 0xffff_ffffu32.to_be_bytes().to_vec();
+        pointer.extend(b"TNamed\0");
+        pointer.extend(tnamed("name", "title"));
+        buf.extend(with_byte_count(pointer));
+        buf.extend(&[0; 4]);
+        buf.extend(&42u32.to_be_bytes());
+        // `TNamed` is read without a streamer info
+        let streamers = [];
+        let interpreter = Interpreter::new(&streamers, "TObjArray").unwrap();
+        let (rest, array) = interpreter.versioned(&buf, "TObjArray").unwrap();
+        assert!(rest.is_empty());
+        let named = Value::Struct(vec![
+            ("fUniqueID".to_string(), Value::U32(0)),
+            ("fBits".to_string(), Value::U32(0x0100_0000)),
+            ("fName".to_string(), Value::String("name".to_string())),
+            ("fTitle".to_string(), Value::String("title".to_string())),
+        ]);
+        assert_eq!(
+            array,
+            Value::Vec(vec![named, Value::Null, Value::Reference])
+        );
+    }
+}
@@ -0,0 +1,112 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::{ptr, slice};
+
+use crate::core::data_source::{fetch_from_memory, SourceFuture};
+use crate::core::{DataSource, Source};
+use crate::Error;
+
+/// A local file mapped into memory; see `Source::mmap`
+pub(crate) struct MappedFile {
+    path: PathBuf,
+    ptr: *const u8,
+    len: usize,
+}
+
+// The mapping is read-only and owned by this struct
+unsafe impl Send for MappedFile {}
+unsafe impl Sync for MappedFile {}
+
+impl MappedFile {
+    /// Map the file at `path` into memory
+    ///
+    /// # Safety
+    ///
+    /// The file must not be truncated or modified while the mapping
+    /// is alive; see `Source::mmap`.
+    pub(crate) unsafe fn open(path: PathBuf) -> Result<Self, Error> {
+        let file = File::open(&path)?;
+        let len = usize::try_from(file.metadata()?.len()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "File is too large to be mapped",
+            )
+        })?;
+        if len == 0 {
+            return Ok(Self {
+                path,
+                ptr: ptr::null(),
+                len,
+            });
+        }
+        // A private, read-only mapping of a file opened for reading;
+        // it is unmapped when this struct is dropped
+        let ptr = libc::mmap(
+            ptr::null_mut(),
+            len,
+            libc::PROT_READ,
+            libc::MAP_PRIVATE,
+            file.as_raw_fd(),
+            0,
+        );
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(Self {
+            path,
+            ptr: ptr as *const u8,
+            len,
+        })
+    }
+
+    fn bytes(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        // SAFETY: `ptr` points to a mapping of `len` bytes which lives
+        // as long as `self`
+        unsafe { slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Drop for MappedFile {
+    fn drop(&mut self) {
+        if self.len > 0 {
+            // SAFETY: `ptr` and `len` describe a mapping created in `open`
+            unsafe {
+                libc::munmap(self.ptr as *mut libc::c_void, self.len);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for MappedFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Mapped file {:?} of {} bytes", self.path, self.len)
+    }
+}
+
+impl DataSource for MappedFile {
+    fn fetch(&self, start: u64, len: u64) -> SourceFuture<'_, Result<Vec<u8>, Error>> {
+        fetch_from_memory(self.bytes(), start, len)
+    }
+
+    fn len(&self) -> Option<u64> {
+        Some(self.len as u64)
+    }
+
+    fn as_slice(&self) -> Option<&[u8]> {
+        Some(self.bytes())
+    }
+
+    fn sibling(&self, name: &str) -> Option<Source> {
+        let path = self.path.with_file_name(name);
+        // SAFETY: Covered by the contract of `Source::mmap`, which
+        // extends to the files of friend trees
+        Some(unsafe { MappedFile::open(path) }.ok()?.into())
+    }
+}
@@ -2,9 +2,15 @@
 //! the self-description of a root file. These parsers can be used to
 //! build new parsers using the [root-ls](https://github.com/cbourjau/alice-rs) cli.
 
+#[cfg(not(target_arch = "wasm32"))]
+mod cache;
 mod data_source;
+mod directory;
 mod file;
 mod file_item;
+mod interpreter;
+#[cfg(all(unix, not(target_arch = "wasm32")))]
+mod mmap;
 pub mod parsers;
 mod tkey;
 mod tstreamer;
@@ -12,13 +18,20 @@ mod tstreamerinfo;
 mod typeid;
 pub mod types;
 
+pub(crate) use self::data_source::{boxed, Buffer};
+pub(crate) use self::directory::TDIRECTORY_MAX_SIZE;
+pub(crate) use self::file::{directory, DirectoryHeader};
+pub(crate) use self::interpreter::Interpreter;
 pub(crate) use self::parsers::*;
 pub(crate) use self::tkey::*;
-pub(crate) use self::tstreamer::{tstreamer, TStreamer};
+pub(crate) use self::tstreamer::{tstreamer, TStreamer, TStreamerElement};
 pub(crate) use self::tstreamerinfo::{tstreamerinfo, TStreamerInfo};
 pub(crate) use self::typeid::*;
 pub(crate) use self::types::*;
 
-pub use self::data_source::Source;
+#[cfg(not(target_arch = "wasm32"))]
+pub use self::cache::CachedSource;
+pub use self::data_source::{DataSource, HttpOptions, Source, SourceFuture, SourceVersion};
+pub use self::directory::Directory;
 pub use self::file::RootFile;
 pub use self::file_item::FileItem;
@@ -1,4 +1,3 @@
-use std::convert::TryFrom;
 use std::io::Read;
 /// Parsers of the ROOT core types. Note that objects in ROOT files
 /// are often, but not always, preceeded by their size. The parsers in
@@ -11,19 +10,23 @@ use std::io::Read;
 // code for (old) layout
 use std::str;
 
-use failure::Error;
-use flate2::bufread::ZlibDecoder;
+use flate2::bufread::{DeflateDecoder, ZlibDecoder};
+use lz4_flex::block::decompress as lz4_decompress;
 use lzma_rs::xz_decompress;
+use ruzstd::StreamingDecoder;
 use nom::{
     self,
     bytes::complete::{take, take_until},
-    combinator::{map_res, rest},
+    combinator::{map, map_res, rest, verify},
+    error::ErrorKind,
     multi::{count, length_data, length_value},
     number::complete::{be_f64, be_i32, be_u16, be_u32, be_u8},
-    sequence::{pair, tuple},
+    sequence::{pair, preceded, tuple},
 };
+use xxhash_rust::xxh64::xxh64;
 
 use crate::core::*;
+use crate::Error;
 
 #[allow(clippy::trivially_copy_pass_by_ref)]
 fn is_byte_count(v: &u32) -> bool {
@@ -201,28 +204,117 @@ named!(
     length_count!(be_i32, be_f64)
 );
 
-fn decode_reader(bytes: &[u8], magic: &str) -> Result<Vec<u8>, Error> {
+/// Size of the checksum preceeding the payload of LZ4 compressed blocks
+const LZ4_CHECKSUM_SIZE: usize = 8;
+
+/// Size of the header preceeding each compressed block
+const COMPRESSION_HEADER_SIZE: usize = 9;
+
+fn decode_reader(bytes: &[u8], magic: &str, uncomp_len: usize) -> Result<Vec<u8>, Error> {
+    let decomp_err = |e: &dyn std::fmt::Display| Error::Decompression(format!("{}: {}", magic, e));
     let mut ret = vec![];
     match magic {
         "ZL" => {
             let mut decoder = ZlibDecoder::new(&bytes[..]);
-            decoder.read_to_end(&mut ret)?;
+            decoder.read_to_end(&mut ret).map_err(|e| decomp_err(&e))?;
         }
         "XZ" => {
             let mut reader = std::io::BufReader::new(bytes);
-            xz_decompress(&mut reader, &mut ret).unwrap();
+            xz_decompress(&mut reader, &mut ret).map_err(|e| decomp_err(&format!("{:?}", e)))?;
+        }
+        "L4" => {
+            // LZ4 payloads are preceeded by the big endian xxhash64 of the compressed data
+            if bytes.len() < LZ4_CHECKSUM_SIZE {
+                return Err(decomp_err(&"Block is too short to contain a checksum"));
+            }
+            let (checksum, payload) = bytes.split_at(LZ4_CHECKSUM_SIZE);
+            let mut expected = [0u8; LZ4_CHECKSUM_SIZE];
+            expected.copy_from_slice(checksum);
+            if u64::from_be_bytes(expected) != xxh64(payload, 0) {
+                return Err(decomp_err(&"Checksum mismatch"));
+            }
+            ret = lz4_decompress(payload, uncomp_len).map_err(|e| decomp_err(&e))?;
+        }
+        "ZS" => {
+            let mut reader = bytes;
+            let mut decoder = StreamingDecoder::new(&mut reader).map_err(|e| decomp_err(&e))?;
+            decoder.read_to_end(&mut ret).map_err(|e| decomp_err(&e))?;
+        }
+        "CS" => {
+            // ROOT's legacy algorithm is a raw deflate stream without the zlib header
+            let mut decoder = DeflateDecoder::new(bytes);
+            decoder.read_to_end(&mut ret).map_err(|e| decomp_err(&e))?;
+        }
+        m => {
+            return Err(Error::Unsupported(format!(
+                "Compression algorithm `{}`",
+                m.escape_debug()
+            )))
         }
-        m => return Err(format_err!("Unsupported compression format `{}`", m)),
     };
     Ok(ret)
 }
 
-/// Decompress the given buffer. Figures out the compression algorithm from the preceeding \"magic\" bytes
-pub fn decompress(input: &[u8]) -> nom::IResult<&[u8], Vec<u8>> {
-    map_res(
-        tuple((|i| take_str!(i, 2usize), take(7usize), rest)),
-        |(magic, _header, comp_buf)| decode_reader(comp_buf, magic),
-    )(input)
+/// Parse a three byte little endian integer as used in the headers of compressed blocks
+fn le_u24(input: &[u8]) -> nom::IResult<&[u8], u32> {
+    map(take(3usize), |b: &[u8]| {
+        u32::from(b[0]) | u32::from(b[1]) << 8 | u32::from(b[2]) << 16
+    })(input)
+}
+
+/// Decompress a single compressed block including its 9 byte
+/// header. Figures out the compression algorithm from the preceeding
+/// \"magic\" bytes. Returns the remaining input and the decompressed block.
+fn compressed_block(input: &[u8]) -> Result<(&[u8], Vec<u8>), Error> {
+    if input.len() < COMPRESSION_HEADER_SIZE {
+        return Err(Error::Decompression(
+            "Buffer is too short to contain a compression header".to_string(),
+        ));
+    }
+    let (header, input) = input.split_at(COMPRESSION_HEADER_SIZE);
+    let magic = String::from_utf8_lossy(&header[..2]);
+    let (_, (comp_len, uncomp_len)) =
+        pair(le_u24, le_u24)(&header[3..]).map_err(|_| Error::Decompression("Invalid header".to_string()))?;
+    let (comp_len, uncomp_len) = (comp_len as usize, uncomp_len as usize);
+    if input.len() < comp_len {
+        return Err(Error::Decompression(format!(
+            "Compressed block of {} bytes is truncated to {} bytes",
+            comp_len,
+            input.len()
+        )));
+    }
+    let (comp_buf, input) = input.split_at(comp_len);
+    let buf = decode_reader(comp_buf, &magic, uncomp_len)?;
+    if buf.len() != uncomp_len {
+        return Err(Error::Decompression(format!(
+            "Decompressed block has {} bytes, but its header claims {}",
+            buf.len(),
+            uncomp_len
+        )));
+    }
+    Ok((input, buf))
+}
+
+/// Decompress the given buffer. Objects larger than 16MiB are split
+/// into several consecutive compressed blocks by ROOT, each with its
+/// own header. Blocks are read until `uncomp_len` bytes were
+/// produced.
+pub fn decompress(input: &[u8], uncomp_len: usize) -> Result<Vec<u8>, Error> {
+    let mut input = input;
+    let mut ret = Vec::with_capacity(uncomp_len);
+    while ret.len() < uncomp_len {
+        let (i, block) = compressed_block(input)?;
+        ret.extend(block);
+        input = i;
+    }
+    if ret.len() != uncomp_len {
+        return Err(Error::Decompression(format!(
+            "Expected {} bytes but decompressed {}",
+            uncomp_len,
+            ret.len()
+        )));
+    }
+    Ok(ret)
 }
 
 /// Parse a null terminated string
@@ -265,7 +357,6 @@ pub fn classinfo(input: &[u8]) -> nom::IResult<&[u8], ClassInfo>
 /// this buffer and the associated data. This function needs a
 /// `Context`, though, which may not be avialable. If so, have a look
 /// at the `classinfo` parser.
-#[allow(unused_variables)]
 pub fn class_name_and_buffer<'s, 'c>(
     input: &'s [u8],
     context: &'c Context,
@@ -273,32 +364,45 @@ pub fn class_name_and_buffer<'s, 'c>(
 where
     's: 'c,
 {
-    let ctx_offset = u32::try_from(context.offset)
-        .expect("Encountered pointer larger than 32 bits. Please file a bug.");
-    let get_name_elsewhere = |tag| {
-        let abs_offset = tag & !Flags::CLASS_MASK.bits();
-        let s = &context.s[((abs_offset - ctx_offset) as usize)..];
-        let (_, (name, _)) = class_name_and_buffer(s, context).unwrap();
-        name
+    // Follow a reference to an absolute position in the buffer of the context
+    let elsewhere = |tag: u32| {
+        let s = u64::from(tag)
+            .checked_sub(context.offset)
+            .and_then(|pos| context.s.get(pos as usize..))
+            .ok_or(nom::Err::Failure((input, ErrorKind::Eof)))?;
+        class_name_and_buffer(s, context)
+            .map(|(_, name_and_buf)| name_and_buf)
+            .map_err(|e| reborrow_err(input, e))
     };
-    let get_name_and_buf_elsewhere = |tag| {
-        let abs_offset = tag;
-        // Sometimes, the reference points to `0`; so we return an empty slice
-        if abs_offset == 0 {
-            return ("".to_string(), &context.s[..0]);
+    let (i, ci) = classinfo(input)?;
+    match ci {
+        ClassInfo::New(name) => {
+            let (i, buf) = length_data(checked_byte_count)(i)?;
+            Ok((i, (name, buf)))
         }
-        let s = &context.s[((abs_offset - ctx_offset) as usize)..];
-        let (_, (name, buf)) = class_name_and_buffer(s, context).unwrap();
-        (name, buf)
-    };
-    do_parse!(input,
-              ci: switch!(classinfo,
-                          ClassInfo::New(s) => tuple!(value!(s), length_value!(checked_byte_count, call!(rest))) |
-                          ClassInfo::Exists(tag) => tuple!(value!(get_name_elsewhere(tag)),
-                                                             length_value!(checked_byte_count, call!(rest))) |
-                          ClassInfo::References(tag) => value!(get_name_and_buf_elsewhere(tag))) >>
-              (ci)
-    )
+        ClassInfo::Exists(tag) => {
+            let (name, _) = elsewhere(tag & !Flags::CLASS_MASK.bits())?;
+            let (i, buf) = length_data(checked_byte_count)(i)?;
+            Ok((i, (name, buf)))
+        }
+        // Sometimes, the reference points to `0`; so we return an empty slice
+        ClassInfo::References(0) => Ok((i, ("".to_string(), &context.s[..0]))),
+        ClassInfo::References(tag) => Ok((i, elsewhere(tag)?)),
+    }
+}
+
+/// Move an error which occurred while parsing a part of `input` (or
+/// some other buffer) to `input`. This is needed if the sub-slice
+/// does not live as long as `input`. The position of the error is
+/// preserved if it lies within `input`.
+pub(crate) fn reborrow_err<'s>(
+    input: &'s [u8],
+    err: nom::Err<(&[u8], ErrorKind)>,
+) -> nom::Err<(&'s [u8], ErrorKind)> {
+    err.map(|(rest, kind)| {
+        let pos = (rest.as_ptr() as usize).wrapping_sub(input.as_ptr() as usize);
+        (input.get(pos..).unwrap_or(input), kind)
+    })
 }
 
 /// Parse a `Raw` chunk from the given input buffer. This is usefull when one does not know the exact type at the time of parsing
@@ -314,9 +418,9 @@ where
     )
 }
 
-/// Same as `raw` but doesn't require a `Context` as input. Panics if
+/// Same as `raw` but doesn't require a `Context` as input. Fails if
 /// a `Context` is required to parse the underlying buffer (i.e., the
-/// given buffer contains a reference to some other part of the file.
+/// given buffer contains a reference to some other part of the file).
 pub fn raw_no_context(input: &[u8]) -> nom::IResult<&[u8], (ClassInfo, &[u8])> {
     use super::ClassInfo::*;
     let (input, ci) = classinfo(input)?;
@@ -325,11 +429,79 @@ pub fn raw_no_context(input: &[u8]) -> nom::IResult<&[u8], (ClassInfo, &[u8])> {
         References(0) => value!(input, &input[..0]),
         New(_) | Exists(_) => length_value!(input, checked_byte_count, call!(rest)),
         // If its a reference to any other thing but 0 it needs a context
-        _ => panic!("Object needs context!"),
+        _ => Err(nom::Err::Failure((input, ErrorKind::Verify))),
     };
     obj.map(|(i, o)| (i, (ci, o)))
 }
 
+/// Parse a pointer to an object, e.g. the data member `TObject*
+/// fObj`. Null pointers yield `None`. Pointers to objects which were
+/// already read earlier in the buffer of `context` yield that object.
+pub fn object_pointer<'s, 'c>(
+    input: &'s [u8],
+    context: &'c Context,
+) -> nom::IResult<&'s [u8], Option<Raw<'c>>>
+where
+    's: 'c,
+{
+    match be_u32(input)? {
+        (i, 0) => Ok((i, None)),
+        _ => map(|i| raw(i, context), Some)(input),
+    }
+}
+
+/// Parse a `TArray` whose elements are read with `parser`, e.g.
+/// `tarray(be_f64)` for a `TArrayD`. Note that `TArray`s are not
+/// preceeded by a byte count.
+pub fn tarray<'s, O, F>(parser: F) -> impl Fn(&'s [u8]) -> nom::IResult<&'s [u8], Vec<O>>
+where
+    F: Fn(&'s [u8]) -> nom::IResult<&'s [u8], O>,
+{
+    move |input| {
+        let (input, n) = be_i32(input)?;
+        count(&parser, n.max(0) as usize)(input)
+    }
+}
+
+/// Parse an array of `n` elements which is stored behind a pointer,
+/// e.g. `Float_t* fX; //[fN]`. The length `n` is given by an earlier
+/// data member. A leading zero byte marks a null pointer.
+pub fn counted_array<'s, O, F>(
+    parser: F,
+    n: usize,
+) -> impl Fn(&'s [u8]) -> nom::IResult<&'s [u8], Vec<O>>
+where
+    F: Fn(&'s [u8]) -> nom::IResult<&'s [u8], O>,
+{
+    move |input| match be_u8(input)? {
+        (i, 0) => Ok((i, vec![])),
+        (i, _) => count(&parser, n)(i),
+    }
+}
+
+/// Parse a `std::vector` data member whose elements are read with
+/// `parser`. Vectors which were streamed member-wise are not
+/// supported.
+pub fn stl_vector<'s, O, F>(parser: F) -> impl Fn(&'s [u8]) -> nom::IResult<&'s [u8], Vec<O>>
+where
+    F: Fn(&'s [u8]) -> nom::IResult<&'s [u8], O>,
+{
+    move |input| {
+        let elements = |i| {
+            let is_memberwise = |v: &u16| u32::from(*v) & Flags::BYTE_COUNT_VMASK.bits() != 0;
+            let (i, _ver) = verify(be_u16, |v| !is_memberwise(v))(i)?;
+            let (i, n) = be_i32(i)?;
+            count(&parser, n.max(0) as usize)(i)
+        };
+        length_value(checked_byte_count, elements)(input)
+    }
+}
+
+/// Parse a `std::string` data member
+pub fn stl_string(input: &[u8]) -> nom::IResult<&[u8], String> {
+    length_value(checked_byte_count, preceded(be_u16, string))(input)
+}
+
 /// ESD trigger classes are strings describing a particular
 /// Trigger. Each event (but in reality every run) might have a
 /// different "menu" of available triggers. The trigger menu is saved
@@ -355,7 +527,8 @@ pub fn parse_tobjarray_of_tnameds(input: &[u8]) -> nom::IResult<&[u8], Vec<Strin
 /// Some Double_* values are saved with a custom mantissa... The
 /// number of bytes can be found in the comment string of the
 /// generated YAML code (for ALICE ESD files at least).  This function
-/// reconstructs a float from the exponent and mantissa 
+/// reconstructs a float from the exponent and mantissa. The sign is
+/// stored in the bit following the mantissa.
 pub fn parse_custom_mantissa(input: &[u8], nbits: usize) -> nom::IResult<&[u8], f32> {
     // TODO: Use ByteOrder crate to be cross-platform?
     pair(be_u8, be_u16)(input)
@@ -364,10 +537,91 @@ pub fn parse_custom_mantissa(input: &[u8], nbits: usize) -> nom::IResult<&[u8],
             // Move the exponent into the last 23 bits
             s <<= 23;
             s |= (u32::from(man) & ((1 << (nbits + 1)) - 1)) << (23 - nbits);
-            (input, f32::from_bits(s))
+            let f = f32::from_bits(s);
+            if u32::from(man) & (1 << (nbits + 1)) != 0 {
+                (input, -f)
+            } else {
+                (input, f)
+            }
         })
 }
 
+/// Some `Double32_t` and `Float16_t` values are saved as an `u32`
+/// which is scaled to the range given in the comment of their data
+/// member; see `TBufferFile::ReadWithFactor` in ROOT. `factor` is the
+/// number of steps per unit of the range.
+pub fn scaled_float(input: &[u8], xmin: f64, factor: f64) -> nom::IResult<&[u8], f32> {
+    map(be_u32, |v| (f64::from(v) / factor + xmin) as f32)(input)
+}
+
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod decompress_test {
+    use std::io::Write;
+
+    use flate2::{write::DeflateEncoder, Compression};
+
+    use super::decompress;
+
+    fn deflate(buf: &[u8]) -> Vec<u8> {
+        let mut enc = DeflateEncoder::new(vec![], Compression::default());
+        enc.write_all(buf).unwrap();
+        enc.finish().unwrap()
+    }
+
+    /// Prepend a ROOT compression header to the given compressed buffer
+    fn with_header(magic: &[u8], method: u8, comp: Vec<u8>, uncomp_len: usize) -> Vec<u8> {
+        let mut out = magic.to_vec();
+        out.push(method);
+        out.extend_from_slice(&(comp.len() as u32).to_le_bytes()[..3]);
+        out.extend_from_slice(&(uncomp_len as u32).to_le_bytes()[..3]);
+        out.extend(comp);
+        out
+    }
+
+    /// The first 64kB of the uncompressed sample files; small enough
+    /// to fit into a single compressed block
+    fn fixtures() -> Vec<Vec<u8>> {
+        ["5.23.02", "5.30.00", "6.10.05"]
+            .iter()
+            .map(|v| format!("./src/test_data/sample-{}-uncompressed.root", v))
+            .map(|p| std::fs::read(p).unwrap().into_iter().take(1 << 16).collect())
+            .collect()
+    }
+
+    #[test]
+    fn zstd_round_trip() {
+        for orig in fixtures() {
+            let comp = zstd::stream::encode_all(orig.as_slice(), 0).unwrap();
+            let buf = with_header(b"ZS", 1, comp, orig.len());
+            assert_eq!(decompress(&buf, orig.len()).unwrap(), orig);
+        }
+    }
+
+    #[test]
+    fn legacy_cs_round_trip() {
+        for orig in fixtures() {
+            let buf = with_header(b"CS", 8, deflate(&orig), orig.len());
+            assert_eq!(decompress(&buf, orig.len()).unwrap(), orig);
+        }
+    }
+
+    #[test]
+    fn multiple_blocks() {
+        for orig in fixtures() {
+            let mut buf = vec![];
+            for chunk in orig.chunks(10_000) {
+                let comp = zstd::stream::encode_all(chunk, 0).unwrap();
+                buf.extend(with_header(b"ZS", 1, comp, chunk.len()));
+            }
+            assert_eq!(decompress(&buf, orig.len()).unwrap(), orig);
+            // Claiming more bytes than there are blocks must fail
+            assert!(decompress(&buf, orig.len() + 1).is_err());
+            // Blocks are not split up
+            assert!(decompress(&buf, orig.len() - 1).is_err());
+        }
+    }
+}
 
 #[cfg(test)]
 mod classinfo_test {
@@ -402,3 +656,21 @@ mod classinfo_test {
         assert_eq!(i.len(), 352);
     }
 }
+
+#[cfg(test)]
+mod custom_mantissa_test {
+    use super::parse_custom_mantissa;
+
+    /// Bytes as written by ROOT's `TBufferFile::WriteWithNbits` for
+    /// -3.25 with 10 bits of mantissa. The sign is stored in the bit
+    /// after the mantissa; it used to be ignored.
+    #[test]
+    fn negative_value() {
+        let (i, v) = parse_custom_mantissa(&[0x80, 0x0a, 0x80], 10).unwrap();
+        assert!(i.is_empty());
+        assert_eq!(v, -3.25);
+        // Same value without the sign bit
+        let (_, v) = parse_custom_mantissa(&[0x80, 0x02, 0x80], 10).unwrap();
+        assert_eq!(v, 3.25);
+    }
+}
@@ -9,14 +9,14 @@ pub struct TKeyHeader {
     pub(crate) total_size: u32,
     version: u16,
     pub(crate) uncomp_len: u32,
-    datime: u32,
+    pub(crate) datime: u32,
     pub(crate) key_len: i16,
-    cycle: i16,
+    pub(crate) cycle: i16,
     pub(crate) seek_key: SeekPointer,
     seek_pdir: SeekPointer,
     pub(crate) class_name: String,
     pub(crate) obj_name: String,
-    obj_title: String,
+    pub(crate) obj_title: String,
 }
 
 /// A `TKey` wraps a streamed oject. The object is decompress when
@@ -68,21 +68,24 @@ fn seek_point(input: &[u8], version: u16) -> nom::IResult<&[u8], u64> {
     }
 }
 
-named!(
-    #[doc="Parse a full TKey including its payload"],
-    pub tkey<&[u8], TKey>,
-    do_parse!(hdr: tkey_header >>
-              obj: take!(hdr.total_size - hdr.key_len as u32) >>
-              ({
-                  let obj = if hdr.uncomp_len as usize > obj.len() {
-                      decompress(obj).unwrap().1
-                  } else {
-                      obj.to_vec()
-                  };
-                  TKey {hdr, obj}
-              })
-    )
-);
+/// Parse a full TKey including its payload
+pub fn tkey(input: &[u8]) -> IResult<&[u8], TKey> {
+    let (input, hdr) = tkey_header(input)?;
+    let uncomp_len = hdr.uncomp_len as usize;
+    let comp_len = hdr.total_size.saturating_sub(hdr.key_len as u32);
+    let (input, obj) = map_res!(
+        input,
+        take!(comp_len),
+        |obj: &[u8]| {
+            if uncomp_len > obj.len() {
+                decompress(obj, uncomp_len)
+            } else {
+                Ok(obj.to_vec())
+            }
+        }
+    )?;
+    Ok((input, TKey { hdr, obj }))
+}
 
 /// Special thing for the keylist in the file header
 pub(crate) fn tkey_headers(input: &[u8]) -> IResult<&[u8], Vec<TKeyHeader>> {
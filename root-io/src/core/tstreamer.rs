@@ -5,8 +5,11 @@ use quote::*;
 
 use crate::{
     code_gen::rust::{ToRustParser, ToRustType},
-    code_gen::utils::{alias_or_lifetime, sanitize, type_is_core},
+    code_gen::utils::{class_ident, sanitize},
+    core::interpreter::{is_pointer, pointee, vector_element},
     core::*,
+    tree_reader::Double32,
+    Error,
 };
 
 /// Union of all posible `TStreamers`. See figure at
@@ -64,10 +67,6 @@ pub(crate) enum TStreamer {
     },
     StlString {
         el: TStreamerElement,
-        /// type of STL vector
-        vtype: StlTypeID,
-        /// STL contained type
-        ctype: TypeID,
     },
 }
 
@@ -75,13 +74,13 @@ pub(crate) enum TStreamer {
 #[derive(Debug)]
 pub(crate) struct TStreamerElement {
     ver: u16,
-    name: TNamed,
-    el_type: TypeID,
-    size: i32,
-    array_len: i32,
+    pub(crate) name: TNamed,
+    pub(crate) el_type: TypeID,
+    pub(crate) size: i32,
+    pub(crate) array_len: i32,
     array_dim: i32,
     max_idx: Vec<u32>,
-    type_name: String,
+    pub(crate) type_name: String,
     // For ver == 3
     // pub(crate) xmin: f32,
     // pub(crate) xmax: f32,
@@ -89,8 +88,58 @@ pub(crate) struct TStreamerElement {
 }
 
 /// Parse a `TStreamer` from a `Raw` buffer. This is usually the case
-/// after reading the `TList` of `TStreamerInfo`s from a ROOT file
-pub(crate) fn tstreamer<'c>(raw: &Raw<'c>) -> IResult<&'c [u8], TStreamer> {
+/// after reading the `TList` of `TStreamerInfo`s from a ROOT file.
+/// Unknown streamer classes and the old layout of the wrapped
+/// `TStreamerElement` are an `Error::Unsupported`; other failures
+/// are reported as a `Parse` error of the object at `offset`.
+pub(crate) fn tstreamer(raw: &Raw, offset: u64) -> Result<TStreamer, Error> {
+    if !STREAMER_CLASSES.contains(&raw.classinfo.as_str()) {
+        return Err(Error::Unsupported(format!(
+            "Streamer elements of class `{}`",
+            raw.classinfo
+        )));
+    }
+    match element_version(raw) {
+        Some(ver) if ver <= 3 => Err(Error::Unsupported(format!(
+            "`{}` with an element of version {}",
+            raw.classinfo, ver
+        ))),
+        _ => tstreamer_body(raw)
+            .map(|(_, s)| s)
+            .map_err(|e| Error::parse(&raw.classinfo, offset, raw.obj, e)),
+    }
+}
+
+/// Classes of `TStreamer`s understood by `tstreamer`
+const STREAMER_CLASSES: &[&str] = &[
+    "TStreamerBase",
+    "TStreamerBasicType",
+    "TStreamerBasicPointer",
+    "TStreamerLoop",
+    "TStreamerObject",
+    "TStreamerObjectPointer",
+    "TStreamerObjectAny",
+    "TStreamerObjectAnyPointer",
+    "TStreamerString",
+    "TStreamerSTL",
+    "TStreamerSTLstring",
+];
+
+/// Version of the `TStreamerElement` wrapped in the given `TStreamer`
+fn element_version(raw: &Raw) -> Option<u16> {
+    let el_ver = preceded!(raw.obj, pair!(be_u16, checked_byte_count), be_u16);
+    let (i, ver) = el_ver.ok()?;
+    if raw.classinfo == "TStreamerSTLstring" {
+        // The element is wrapped in the layout of `TStreamerSTL`
+        preceded!(i, checked_byte_count, be_u16)
+            .ok()
+            .map(|(_, v)| v)
+    } else {
+        Some(ver)
+    }
+}
+
+fn tstreamer_body<'c>(raw: &Raw<'c>) -> IResult<&'c [u8], TStreamer> {
     let wrapped_tstreamerelem = |i| length_value!(i, checked_byte_count, tstreamerelement);
     match raw.classinfo.as_str() {
         "TStreamerBase" => do_parse!(raw.obj,
@@ -139,25 +188,30 @@ pub(crate) fn tstreamer<'c>(raw: &Raw<'c>) -> IResult<&'c [u8], TStreamer> {
         "TStreamerSTL" => do_parse!(raw.obj,
                                     _ver: be_u16 >>
                                     el: wrapped_tstreamerelem >>
-                                    vtype: map!(be_i32, StlTypeID::new) >>
+                                    vtype: map_res!(be_i32, StlTypeID::new) >>
                                     ctype: map_res!(be_i32, TypeID::new) >>
                                     (TStreamer::Stl {el, vtype, ctype})),
         "TStreamerSTLstring" => do_parse!(raw.obj,
-                                          // Two version bcs `stlstring` derives from `stl`
-                                          _ver: be_u16 >>
                                           _ver: be_u16 >>
-                                          el: wrapped_tstreamerelem >>
-                                          vtype: map!(be_i32, StlTypeID::new) >>
-                                          ctype: map_res!(be_i32, TypeID::new) >>
-                                          (TStreamer::StlString {el, vtype, ctype})),
-        ci => unimplemented!("Unknown TStreamer {}", ci)
+                                          // `stlstring` derives from `stl`; the STL type
+                                          // and contained type are both `kSTLstring`
+                                          el: length_value!(
+                                              checked_byte_count,
+                                              do_parse!(_ver: be_u16 >>
+                                                        el: wrapped_tstreamerelem >>
+                                                        _vtype: be_i32 >>
+                                                        _ctype: be_i32 >>
+                                                        (el))) >>
+                                          (TStreamer::StlString {el})),
+        _ => Err(Err::Failure((raw.obj, error::ErrorKind::Switch))),
     }
 }
 
 named!(
     #[doc="The element which is wrapped in a TStreamer."],
     tstreamerelement<&[u8], TStreamerElement>,
-    do_parse!(ver: be_u16 >>
+    do_parse!(// Older layouts (with xmin, xmax and factor) are rejected by `tstreamer`
+              ver: be_u16 >>
               name: length_value!(checked_byte_count, tnamed) >>
               el_type: map_res!(be_i32, TypeID::new) >>
               size: be_i32 >>
@@ -168,14 +222,9 @@ named!(
                                _ => count!(be_u32, 5)) >>
               type_name: string >>
               _eof: eof!() >>
-              ({
-                  if ver <= 3 {
-                      unimplemented!();
-                  }
-                  TStreamerElement {
-                      ver, name, el_type, size, array_len,
-                      array_dim, max_idx, type_name
-                  }
+              (TStreamerElement {
+                  ver, name, el_type, size, array_len,
+                  array_dim, max_idx, type_name
               })
     )
 );
@@ -195,14 +244,14 @@ impl TStreamer {
             | ObjectAnyPointer { ref el }
             | String { ref el }
             | Stl { ref el, .. }
-            | StlString { ref el, .. } => el,
+            | StlString { ref el } => el,
         }
     }
 
     /// Get the comment associated with this particular member
     pub(crate) fn member_comment(&self) -> Ident {
         let cmt = &self.elem().name.title;
-        Ident::new(cmt.to_string())
+        Ident::new(cmt.replace('\n', " "))
     }
     /// The name of the member/field to be used in the generated struct
     pub(crate) fn member_name(&self) -> Ident {
@@ -212,12 +261,12 @@ impl TStreamer {
 }
 
 impl ToTokens for TStreamer {
-    /// Converts TStreamer to "\n///comment \n name: type"
+    /// Converts TStreamer to "\n///comment \n pub name: type"
     fn to_tokens(&self, tokens: &mut Tokens) {
         // insert a new line befor and after the comment!
         tokens.append("\n/// ");
         self.member_comment().to_tokens(tokens);
-        tokens.append("\n");
+        tokens.append("\npub ");
         self.member_name().to_tokens(tokens);
         tokens.append(": ");
         self.type_name().to_tokens(tokens);
@@ -226,166 +275,261 @@ impl ToTokens for TStreamer {
 
 impl ToRustType for TStreamer {
     fn type_name(&self) -> Tokens {
-        use self::TypeID::*;
-        let name = Ident::new(alias_or_lifetime(&self.elem().name.name.to_owned()));
-        match self {
-            TStreamer::Base { ref el, .. } => {
-                match el.el_type {
-                    Object | Base | Named | TObject => quote! {#name},
-                    // Not sure about the following branch...
-                    InvalidOrCounter(-1) => quote! {#name},
-                    _ => panic!("{:#?}", self),
-                }
-            }
-            TStreamer::BasicType { ref el } => match el.el_type {
-                Primitive(ref id) => id.type_name(),
-                Offset(ref id) => {
-                    let s = Ident::new(format!(
-                        "[{}; {}]",
-                        id.type_name().to_string(),
-                        el.array_len
-                    ));
-                    quote! {#s}
-                }
-                _ => panic!("{:#?}", self),
-            },
-            TStreamer::BasicPointer { ref el, .. } => {
-                match el.el_type {
-                    Array(ref id) => {
-                        // Arrays are preceeded by a byte and then have a length given by a
-                        // previous member
-                        let s = Ident::new(format!("Vec<{}>", id.type_name().to_string()));
-                        quote! {#s}
-                    }
-                    _ => panic!("{:#?}", self),
-                }
-            }
-            TStreamer::Object { ref el } => match el.el_type {
-                Object => quote! {#name},
-                _ => panic!("{:#?}", self),
-            },
-            TStreamer::ObjectPointer { ref el } => {
-                match el.el_type {
-                    // Pointers may be null!
-                    ObjectP => quote! {Option<#name>},
-                    _ => panic!("{:#?}", self),
-                }
-            }
-            TStreamer::ObjectAny { ref el } | &TStreamer::ObjectAnyPointer { ref el } => {
-                match el.el_type {
-                    Any => quote! {#name},
-                    AnyP => quote! {#name},
-                    // No idea what this is; probably an array of custom type? Found in AliESDs
-                    Unknown(82) => quote! {Vec<u8>},
-                    _ => panic!("{:#?}", self),
-                }
-            }
-            TStreamer::String { ref el } => match el.el_type {
-                String => quote! {String},
-                _ => panic!("{:#?}", self),
-            },
-            TStreamer::Stl { ref vtype, .. } => match vtype {
-                StlTypeID::Vector => {
-                    quote! {Stl_vec}
-                }
-                StlTypeID::Bitset => {
-                    quote! {Stl_bitset}
-                }
-            },
-            _ => panic!("{:#?}", self),
-        }
+        self.type_and_parser().0
     }
 }
 
 impl ToRustParser for TStreamer {
     fn to_inline_parser(&self) -> Tokens {
+        self.type_and_parser().1
+    }
+}
+
+impl TStreamer {
+    /// The type of this member in the generated struct and the
+    /// expression of its parser. The parser may use the `context`
+    /// and the values of the members read before it. Members which
+    /// cannot be read (yet) get a parser which always fails.
+    fn type_and_parser(&self) -> (Tokens, Tokens) {
         use self::TypeID::*;
-        let name = match self {
+        let el = self.elem();
+        let member = match self {
             //  `Base` types, i.e. types from which the current object inherited;
             // In that case the name is actually the type
-            TStreamer::Base { .. } => &self.elem().name.name,
-            _ => &self.elem().type_name,
-        };
-        // Most core-types do not need the context, but some do
-        let name = if type_is_core(name.as_str()) && name != "TObjArray" {
-            name.to_lowercase()
-        } else {
-            format!("apply!({}, &context)", name.to_lowercase())
-        };
-
-        let name = Ident::new(name);
-
-        match self {
-            TStreamer::Base { ref el, .. } => match el.el_type {
-                Object | Base | Named => quote! {length_value!(checked_byte_count, #name)},
-                TObject => quote! {#name},
+            TStreamer::Base { .. } => match el.el_type {
+                Object | Base | Named | TObject => object(&el.name.name),
+                // Not sure about this one...
                 InvalidOrCounter(-1) => {
-                    let size = el.size;
-                    quote! {map!(take!(#size), |v| v.to_vec())}
+                    let size = el.size.max(0) as usize;
+                    Some((
+                        quote!(Vec<u8>),
+                        quote!(map(take(#size), |v: &[u8]| v.to_vec())),
+                    ))
                 }
-                _ => panic!("{:#?}", self),
+                _ => None,
             },
-            TStreamer::BasicType { ref el } => {
-                match el.el_type {
-                    Primitive(ref id) => id.to_inline_parser(),
-                    // Offsets are floating points with a custom mantissa
-                    // By default, parse as Vec<u8>
-                    Offset(_) => {
-                        let size = el.size;
-                        quote! {map!(take!(#size), |v| v.to_vec())}
-                    }
-                    _ => panic!("{:#?}", self),
-                }
-            }
-            TStreamer::BasicPointer {
-                ref el, ref cname, ..
-            } => {
-                let n_entries_array = Ident::new(cname.to_lowercase());
-                match el.el_type {
-                    Array(ref id) => {
-                        // Arrays are preceeded by a byte and then have a length given by a
-                        // previous member
-                        let b_par = id.to_inline_parser();
-                        quote! {preceded!(be_u8, count!(#b_par, #n_entries_array as usize))}
-                    }
-                    _ => panic!("{:#?}", self),
-                }
-            }
-            TStreamer::Object { ref el } => match el.el_type {
-                Object => quote! {length_value!(checked_byte_count, #name)},
-                _ => panic!("{:#?}", self),
+            TStreamer::BasicType { .. } => match el.el_type {
+                Primitive(ref id) => primitive(id, el),
+                Offset(ref id) => primitive(id, el).map(|(t, p)| {
+                    let n = el.array_len.max(0) as usize;
+                    (vec_of(&t), quote!(count(#p, #n)))
+                }),
+                _ => None,
+            },
+            // Arrays are preceeded by a byte and then have a length
+            // given by a previous member
+            TStreamer::BasicPointer { ref cname, .. } => match el.el_type {
+                Array(ref id) => primitive(id, el).map(|(t, p)| {
+                    let n = Ident::new(sanitize(&cname.to_lowercase()));
+                    (vec_of(&t), quote!(counted_array(#p, #n as usize)))
+                }),
+                _ => None,
             },
-            TStreamer::ObjectPointer { ref el } => {
-                match el.el_type {
-                    // Pointers may be null!
-                    ObjectP => quote! {switch!(peek!(be_u32),
-                    0 => map!(call!(be_u32), |_| None) |
-                    _ => map!(call!(_curried_raw), Some))},
-                    _ => panic!("{:#?}", self),
+            // Skip the byte count and version of the loop; each object
+            // has its own
+            TStreamer::Loop { ref cname, .. } => object(pointee(&el.type_name)).map(|(t, p)| {
+                let n = Ident::new(sanitize(&cname.to_lowercase()));
+                (
+                    vec_of(&t),
+                    quote!(preceded(take(6usize), count(#p, #n as usize))),
+                )
+            }),
+            TStreamer::Object { .. } | TStreamer::ObjectAny { .. } => object(&el.type_name),
+            // Pointers may be null!
+            TStreamer::ObjectPointer { .. } | TStreamer::ObjectAnyPointer { .. } => {
+                if is_pointer(el) {
+                    Some(pointer())
+                } else {
+                    object(pointee(&el.type_name))
                 }
             }
-            TStreamer::ObjectAny { ref el } | &TStreamer::ObjectAnyPointer { ref el } => {
-                match el.el_type {
-                    Any => quote! {#name},
-                    AnyP => quote! {#name},
-                    // No idea what this is; probably an array of custom type? Found in AliESDs
-                    Unknown(82) => quote! {map!(eof!(), |o| o.to_vec())},
-                    _ => panic!("{:#?}", self),
-                }
+            TStreamer::String { .. } => Some((quote!(String), quote!(string))),
+            TStreamer::StlString { .. } => Some((quote!(String), quote!(stl_string))),
+            TStreamer::Stl {
+                vtype: StlTypeID::Vector,
+                ref ctype,
+                ..
+            } => match ctype {
+                Primitive(ref id) => primitive(id, el),
+                _ => match vector_element(&el.type_name) {
+                    Some("string") => Some((quote!(String), quote!(string))),
+                    Some(class) if class.ends_with('*') => Some(pointer()),
+                    Some(class) => object(class),
+                    None => None,
+                },
             }
-            TStreamer::String { ref el } => match el.el_type {
-                String => quote! {string},
-                _ => panic!("{:#?}", self),
-            },
-            TStreamer::Stl { ref vtype, .. } => match vtype {
-                StlTypeID::Vector => {
-                    quote! {stl_vec}
-                }
-                StlTypeID::Bitset => {
-                    quote! {stl_bitset}
-                }
+            .map(|(t, p)| (vec_of(&t), quote!(stl_vector(#p)))),
+            TStreamer::Stl {
+                vtype: StlTypeID::Bitset,
+                ..
+            } => None,
+        };
+        member.unwrap_or_else(|| {
+            (
+                quote!(()),
+                quote!(|i| Err(nom::Err::Failure((i, nom::error::ErrorKind::Switch)))),
+            )
+        })
+    }
+}
+
+/// Type and parser of a primitive member. `Double32_t` and
+/// `Float16_t` are read as `f32` following the range in the comment
+/// of the member.
+fn primitive(id: &PrimitiveID, el: &TStreamerElement) -> Option<(Tokens, Tokens)> {
+    let parser = match id.0 {
+        9 => float_parser(Double32::from_title(&el.name.title).ok()?),
+        19 => float_parser(Double32::float16_from_title(&el.name.title).ok()?),
+        // `None` for "kCharStar" and "kLegacyChar"
+        _ => id.to_inline_parser()?,
+    };
+    Some((id.type_name(), parser))
+}
+
+/// Parser of a `Double32_t` or `Float16_t` with the given encoding
+fn float_parser(encoding: Double32) -> Tokens {
+    match encoding {
+        Double32::Float => quote!(be_f32),
+        Double32::Mantissa(nbits) => quote!(|i| parse_custom_mantissa(i, #nbits)),
+        Double32::Range { xmin, factor } => {
+            // `Debug` keeps the decimal point of whole numbers
+            let xmin = Ident::new(format!("{:?}", xmin));
+            let factor = Ident::new(format!("{:?}", factor));
+            quote!(|i| scaled_float(i, #xmin, #factor))
+        }
+    }
+}
+
+/// Type and parser of an object of `class` which is stored as a
+/// member, i.e. preceeded by its byte count unless it has a custom
+/// streamer
+fn object(class: &str) -> Option<(Tokens, Tokens)> {
+    let (t, p) = match class {
+        "TObject" => ("TObject".to_string(), "tobject".to_string()),
+        "TString" => ("String".to_string(), "string".to_string()),
+        "TNamed" => (
+            "TNamed".to_string(),
+            "length_value(checked_byte_count, tnamed)".to_string(),
+        ),
+        "TObjString" => (
+            "String".to_string(),
+            "length_value(checked_byte_count, tobjstring)".to_string(),
+        ),
+        "TList" | "THashList" => (
+            "TList<'s>".to_string(),
+            "length_value(checked_byte_count, |i| tlist(i, context))".to_string(),
+        ),
+        "TObjArray" => (
+            "Vec<Raw<'s>>".to_string(),
+            "length_value(checked_byte_count, |i| tobjarray(i, context))".to_string(),
+        ),
+        _ if class.starts_with("TArray") => {
+            // Element type of the array by the suffix of its class
+            let id = match &class["TArray".len()..] {
+                "C" => PrimitiveID(1),
+                "S" => PrimitiveID(2),
+                "I" => PrimitiveID(3),
+                "L" | "L64" => PrimitiveID(4),
+                "F" => PrimitiveID(5),
+                "D" => PrimitiveID(8),
+                _ => return None,
+            };
+            let (t, p) = (id.type_name(), id.to_inline_parser()?);
+            return Some((vec_of(&t), quote!(tarray(#p))));
+        }
+        _ => {
+            let name = class_ident(class);
+            (
+                format!("{}<'s>", name),
+                format!(
+                    "length_value(checked_byte_count, |i| {}(i, context))",
+                    name.to_lowercase()
+                ),
+            )
+        }
+    };
+    let (t, p) = (Ident::new(t), Ident::new(p));
+    Some((quote!(#t), quote!(#p)))
+}
+
+/// Type and parser of a pointer to an object of any class
+fn pointer() -> (Tokens, Tokens) {
+    (
+        quote!(Option<Raw<'s>>),
+        quote!(|i| object_pointer(i, context)),
+    )
+}
+
+fn vec_of(t: &Tokens) -> Tokens {
+    let t = Ident::new(format!("Vec<{}>", t));
+    quote!(#t)
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    fn basic_type(id: i32, title: &str) -> TStreamer {
+        let el = TStreamerElement {
+            ver: 4,
+            name: TNamed {
+                name: "fX".to_string(),
+                title: title.to_string(),
             },
-            _ => panic!("{:#?}", self),
+            el_type: TypeID::new(id).unwrap(),
+            size: 4,
+            array_len: 0,
+            array_dim: 0,
+            max_idx: vec![0; 5],
+            type_name: "Double32_t".to_string(),
+        };
+        TStreamer::BasicType { el }
+    }
+
+    #[test]
+    fn float_parsers() {
+        let parser = |id, title| basic_type(id, title).to_inline_parser().to_string();
+        assert_eq!(parser(9, "no range"), "be_f32");
+        assert_eq!(
+            parser(9, "[0, 0, 8]"),
+            "| i | parse_custom_mantissa ( i , 8usize )"
+        );
+        assert_eq!(
+            parser(9, "[-1, 1, 8]"),
+            "| i | scaled_float ( i , -1.0 , 128.0 )"
+        );
+        assert_eq!(
+            parser(19, "no range"),
+            "| i | parse_custom_mantissa ( i , 12usize )"
+        );
+        assert_eq!(basic_type(19, "").type_name().to_string(), "f32");
+        // The range is not known from the type alone
+        assert!(PrimitiveID(19).to_inline_parser().is_none());
+        // Members which cannot be read fail at runtime
+        assert_eq!(
+            parser(7, ""),
+            "| i | Err ( nom :: Err :: Failure ( ( i , nom :: error :: ErrorKind :: Switch ) ) )"
+        );
+    }
+
+    #[test]
+    fn unsupported_streamers() {
+        let unknown = Raw {
+            classinfo: "TStreamerArtificial".to_string(),
+            obj: &[],
+        };
+        match tstreamer(&unknown, 0) {
+            Err(Error::Unsupported(msg)) => assert!(msg.contains("TStreamerArtificial")),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        // Version of the streamer, byte count and version of the element
+        let old = Raw {
+            classinfo: "TStreamerBasicType".to_string(),
+            obj: &[0, 2, 0x40, 0, 0, 2, 0, 3],
+        };
+        match tstreamer(&old, 0) {
+            Err(Error::Unsupported(msg)) => assert!(msg.contains("version 3")),
+            other => panic!("Unexpected result: {:?}", other),
         }
     }
 }
@@ -5,57 +5,53 @@ use quote::*;
 
 use crate::{
     code_gen::rust::{ToNamedRustParser, ToRustParser, ToRustStruct, ToRustType},
-    code_gen::utils::type_is_core,
+    code_gen::utils::{class_ident, type_is_core},
     core::*,
+    Error,
 };
 
 #[derive(Debug)]
 pub struct TStreamerInfo {
     tstreamerinfo_ver: u16,
-    named: TNamed,
+    pub(crate) named: TNamed,
     checksum: u32,
     new_class_version: u32,
-    data_members: Vec<TStreamer>,
+    pub(crate) data_members: Vec<TStreamer>,
 }
 
-/// Parse one `TStreamerInfo` object (as found in the `TList`)
-pub(crate) fn tstreamerinfo<'s, 'c>(
-    input: &'s [u8],
-    context: &'c Context,
-) -> IResult<&'s [u8], TStreamerInfo>
-where
-    's: 'c,
-{
-    let wrapped_tobjarray = |i| tobjarray(i, &context);
-    do_parse!(input,
-              tstreamerinfo_ver: be_u16 >>
-              named: length_value!(checked_byte_count, tnamed) >>
-              checksum: be_u32 >>
-              new_class_version: be_u32 >>
-              _size_tobjarray_with_class_info: checked_byte_count >>
-              _class_info_objarray: classinfo >>
-              data_members: length_value!(checked_byte_count, wrapped_tobjarray) >>
-              _eof: eof!() >>
-              ({
-                  let data_members = data_members.iter()
-                      .filter_map(|el| {
-                          match tstreamer(el) {
-                              Ok((_, v)) => Some(v),
-                              _ => {println!("Failed to parse TStreamer for {}:\n{}",
-                                             el.classinfo, el.obj.to_hex(16));
-                                    None
-                              },
-                          }
-                      })
-                      .collect();
-                  TStreamerInfo {
-                      tstreamerinfo_ver,
-                      named,
-                      checksum,
-                      new_class_version,
-                      data_members,
-                  }})
-    )
+/// Parse one `TStreamerInfo` object (as found in the `TList` of the
+/// key at `offset`). A data member described by an unsupported
+/// `TStreamer` is an `Error::Unsupported`.
+pub(crate) fn tstreamerinfo(
+    input: &[u8],
+    context: &Context,
+    offset: u64,
+) -> Result<TStreamerInfo, Error> {
+    let wrapped_tobjarray = |i| tobjarray(i, context);
+    let (_, (tstreamerinfo_ver, named, checksum, new_class_version, data_members)) =
+        do_parse!(input,
+                  tstreamerinfo_ver: be_u16 >>
+                  named: length_value!(checked_byte_count, tnamed) >>
+                  checksum: be_u32 >>
+                  new_class_version: be_u32 >>
+                  _size_tobjarray_with_class_info: checked_byte_count >>
+                  _class_info_objarray: classinfo >>
+                  data_members: length_value!(checked_byte_count, wrapped_tobjarray) >>
+                  _eof: eof!() >>
+                  ((tstreamerinfo_ver, named, checksum, new_class_version, data_members))
+        )
+        .map_err(|e| Error::parse("TStreamerInfo", offset, input, e))?;
+    let data_members = data_members
+        .iter()
+        .map(|el| tstreamer(el, offset))
+        .collect::<Result<_, _>>()?;
+    Ok(TStreamerInfo {
+        tstreamerinfo_ver,
+        named,
+        checksum,
+        new_class_version,
+        data_members,
+    })
 }
 
 impl ToRustParser for TStreamerInfo {
@@ -65,7 +61,7 @@ impl ToRustParser for TStreamerInfo {
             // Don't generate a parser if its a core type
             return quote!(#(self.named.name.to_lowercase()));
         }
-        let struct_name = Ident::new(self.named.name.as_str());
+        let struct_name = self.type_name();
         let member_names: &Vec<Ident> =
             &self.data_members.iter().map(|m| m.member_name()).collect();
         let member_parsers: &Vec<Tokens> = &self
@@ -73,22 +69,21 @@ impl ToRustParser for TStreamerInfo {
             .iter()
             .map(|m| m.to_inline_parser())
             .collect();
-        quote!{
-            do_parse!(ver: be_u16 >>
-                      #(#member_names : #member_parsers >> )*
-                      ({let phantom = PhantomData;
-                        #struct_name {
-                            phantom,
-                            ver,
-                            #(#member_names),*
-                        }})
-            )}
+        quote! {
+            let (input, ver) = be_u16(input)?;
+            #(let (input, #member_names) = (#member_parsers)(input)?;)*
+            Ok((input, #struct_name {
+                phantom: PhantomData,
+                ver,
+                #(#member_names),*
+            }))
+        }
     }
 }
 
 impl ToNamedRustParser for TStreamerInfo {
     fn parser_name(&self) -> Tokens {
-        let ret = Ident::new(self.named.name.to_lowercase());
+        let ret = Ident::new(class_ident(&self.named.name).to_lowercase());
         quote!(#ret)
     }
 
@@ -101,9 +96,9 @@ impl ToNamedRustParser for TStreamerInfo {
         let parser = self.to_inline_parser();
         let struct_name = self.type_name();
         quote! {
-            pub fn #parser_name<'s>(input: &'s[u8], context: &'s Context<'s>)
-                                    -> IResult<&'s[u8], #struct_name<'s>> {
-                value!(input, #parser)
+            pub fn #parser_name<'s>(input: &'s [u8], context: &'s Context<'s>)
+                                    -> IResult<&'s [u8], #struct_name<'s>> {
+                #parser
             }
         }
     }
@@ -124,7 +119,7 @@ impl ToRustStruct for TStreamerInfo {
                 /// Gurantee that this object does not outlive its underlying slice
                 phantom: PhantomData<&'s[u8]>,
                 #ver_comment
-                ver: u16,
+                pub ver: u16,
                 #(#fields), *
             }
         }
@@ -137,12 +132,27 @@ impl ToRustType for TStreamerInfo {
         quote!(#ret)
     }
     fn type_name(&self) -> Tokens {
-        let ret = Ident::new(self.named.name.as_str());
+        let ret = Ident::new(class_ident(&self.named.name));
         quote!(#ret)
     }
 }
 
 impl TStreamerInfo {
+    /// The comment of the data member `member` of `class`, which is
+    /// looked up in `infos`. Base classes are searched as well.
+    pub(crate) fn member_title<'a>(
+        infos: &'a [TStreamerInfo],
+        class: &str,
+        member: &str,
+    ) -> Option<&'a str> {
+        let info = infos.iter().find(|i| i.named.name == class)?;
+        info.data_members.iter().find_map(|m| match m {
+            TStreamer::Base { el, .. } => Self::member_title(infos, &el.name.name, member),
+            _ if m.elem().name.name == member => Some(m.elem().name.title.as_str()),
+            _ => None,
+        })
+    }
+
     pub(crate) fn to_yaml(&self) -> String {
         if type_is_core(self.named.name.as_str()) {
             return "".to_string();
@@ -1,7 +1,7 @@
-use failure::Error;
 use quote::*;
 
-use crate::code_gen::rust::{ToRustParser, ToRustType};
+use crate::code_gen::rust::ToRustType;
+use crate::Error;
 
 /// Integer ID describing a streamed type in a `TStreamer`
 #[derive(Debug, Clone)]
@@ -39,7 +39,7 @@ impl PrimitiveID {
     pub(crate) fn new(id: i32) -> Result<PrimitiveID, Error> {
         match id {
             1..=19 => Ok(PrimitiveID(id)),
-            id => Err(format_err!("Invalid base type id {}", id)),
+            id => Err(Error::Unsupported(format!("Base type id {}", id))),
         }
     }
 }
@@ -70,12 +70,12 @@ impl TypeID {
 }
 
 impl StlTypeID {
-    pub(crate) fn new(id: i32) -> StlTypeID {
+    pub(crate) fn new(id: i32) -> Result<StlTypeID, Error> {
         use self::StlTypeID::*;
         match id {
-            1 => Vector,
-            8 => Bitset,
-            _ => unimplemented!("`StlTypeID` {} not implemented.", id),
+            1 => Ok(Vector),
+            8 => Ok(Bitset),
+            _ => Err(Error::Unsupported(format!("`StlTypeID` {}", id))),
         }
     }
 }
@@ -94,28 +94,29 @@ impl ToRustType for TypeID {
             Any => "Vec<u8>".to_string(),
             AnyP => "Vec<u8>".to_string(),
             InvalidOrCounter(-1) => "u32".to_string(),
-            _ => panic!("{:?}: type not implemented, yet", self),
+            // Base classes, `TObject`, `TNamed` and other unknown types
+            _ => "Vec<u8>".to_string(),
         };
         let t = Ident::new(t);
         quote!(#t)
     }
 }
 
-impl ToRustParser for PrimitiveID {
-    fn to_inline_parser(&self) -> Tokens {
+impl PrimitiveID {
+    /// Parser of a value of this type in the generated code. `None` if
+    /// the type cannot be read without knowing more about the member
+    /// than its type.
+    pub(crate) fn to_inline_parser(&self) -> Option<Tokens> {
         let t = match self.0 {
             1 => "be_i8",      //"kChar",
             2 => "be_i16",     //"kShort",
             3 | 6 => "be_i32", //"kInt", "kCounter",
             4 => "be_i64",     //"kLong",
             5 => "be_f32",     //"kFloat",
-            // "kCharStar"
-            7 => unimplemented!("{:?}: type not implemented, yet", self),
-            8 => "be_f64", //"kDouble",
-            // "kDouble32"; This one is nasty! Check the TFileBuffer.cxx sources in ROOT
-            9 => "custom_float",
-            // "kLegacyChar"
-            10 => unimplemented!("{:?}: type not implemented, yet", self),
+            8 => "be_f64",     //"kDouble",
+            // "kDouble32"; Without a range it is stored as a float. The
+            // range is only known from the comment of the member
+            9 => "be_f32",
             11 => "be_u8",  //"kUChar",
             12 => "be_u16", //"kUShort",
             13 => "be_u32", //"kUInt",
@@ -124,14 +125,13 @@ impl ToRustParser for PrimitiveID {
             16 => "be_i64", //"kLong64",
             17 => "be_u64", //"kULong64",
             18 => "be_u8",  //"kBool",
-            19 => "be_f16", //"kFloat16",
-            id => panic!(
-                "Invalid base type id {} which should not be possible here",
-                id
-            ),
+            // "kFloat16"; Needs the range of the member as well
+            19 => return None,
+            // "kCharStar", "kLegacyChar" and invalid ids
+            _ => return None,
         };
         let t = Ident::new(t);
-        quote!(#t)
+        Some(quote!(#t))
     }
 }
 
@@ -143,13 +143,9 @@ impl ToRustType for PrimitiveID {
             3 | 6 => "i32", //"kInt", "kCounter",
             4 => "i64",     //"kLong",
             5 => "f32",     //"kFloat",
-            // "kCharStar"
-            7 => unimplemented!("{:?}: type not implemented, yet", self),
-            8 => "f64", //"kDouble",
+            8 => "f64",     //"kDouble",
             // "kDouble32"; This one is nasty! Check the TFileBuffer.cxx sources in ROOT
             9 => "f32",
-            // "kLegacyChar"
-            10 => unimplemented!("{:?}: type not implemented, yet", self),
             11 => "u8",  //"kUChar",
             12 => "u16", //"kUShort",
             13 => "u32", //"kUInt",
@@ -158,11 +154,10 @@ impl ToRustType for PrimitiveID {
             16 => "i64", //"kLong64",
             17 => "u64", //"kULong64",
             18 => "u8",  //"kBool",
-            19 => "f16", //"kFloat16",
-            id => panic!(
-                "Invalid base type id {} which should not be possible here",
-                id
-            ),
+            19 => "f32", //"kFloat16",
+            // "kCharStar", "kLegacyChar" and invalid ids are treated as
+            // byte strings, like other types which cannot be read
+            _ => "Vec<u8>",
         };
         let t = Ident::new(t);
         quote!(#t)
@@ -44,6 +44,31 @@ pub struct TObject {
     pub(crate) bits: TObjectFlags,
 }
 
+/// Date and time as stored by ROOT's `TDatime`, e.g. in the header of a `TKey`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Datime {
+    pub year: u32,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl Datime {
+    /// Unpack the bit-packed representation used on disk
+    pub(crate) fn from_packed(v: u32) -> Self {
+        Datime {
+            year: (v >> 26) + 1995,
+            month: (v >> 22) & 0xF,
+            day: (v >> 17) & 0x1F,
+            hour: (v >> 12) & 0x1F,
+            minute: (v >> 6) & 0x3F,
+            second: v & 0x3F,
+        }
+    }
+}
+
 /// A ROOT object with a name and a title
 #[derive(Debug, Clone)]
 pub struct TNamed {
@@ -82,6 +107,20 @@ pub struct Context<'s> {
     pub s: &'s [u8],
 }
 
+impl<'s> Raw<'s> {
+    /// Name of the class of this object
+    pub fn class_name(&self) -> &str {
+        &self.classinfo
+    }
+
+    /// The data of this object, starting with the version of its
+    /// class. It can be read with the parser generated for that
+    /// class.
+    pub fn data(&self) -> &'s [u8] {
+        self.obj
+    }
+}
+
 impl<'s> fmt::Debug for Raw<'s> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{} \n {}", self.classinfo, self.obj.to_hex(16))
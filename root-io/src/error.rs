@@ -0,0 +1,179 @@
+use std::{fmt, io};
+
+use nom::error::ErrorKind;
+
+/// Errors which may occur while reading a ROOT file
+#[derive(Debug)]
+pub enum Error {
+    /// Reading from a local file failed
+    Io(io::Error),
+    /// Fetching data from a remote file failed
+    Http(reqwest::Error),
+    /// A server did not answer the request for a range of a remote
+    /// file as expected
+    Response {
+        url: String,
+        range: String,
+        msg: String,
+    },
+    /// A compressed buffer could not be decompressed
+    Decompression(String),
+    /// The file makes use of a feature of the ROOT format which is
+    /// not supported (yet)
+    Unsupported(String),
+    /// An object of class `class` whose key starts at byte `offset`
+    /// of the file could not be parsed
+    Parse {
+        class: String,
+        offset: u64,
+        msg: String,
+    },
+    /// Reading entry `entry`, stored in basket number `basket` of
+    /// the branch `branch`, failed
+    Branch {
+        branch: String,
+        basket: usize,
+        entry: u64,
+        error: Box<Error>,
+    },
+    /// The requested item, branch, etc. does not exist
+    NotFound(String),
+    /// The trees in the files of a `Chain` do not have the same
+    /// branches, or an object does not have the requested shape
+    Incompatible(String),
+    /// Writing generated output failed
+    Fmt(fmt::Error),
+}
+
+impl Error {
+    /// Create a `Parse` error from a failed nom parser. `buf` is the
+    /// (uncompressed) buffer of the object given to the parser
+    pub(crate) fn parse(
+        class: &str,
+        offset: u64,
+        buf: &[u8],
+        err: nom::Err<(&[u8], ErrorKind)>,
+    ) -> Self {
+        let msg = match err {
+            nom::Err::Incomplete(_) => "Unexpected end of buffer".to_string(),
+            nom::Err::Error((rest, kind)) | nom::Err::Failure((rest, kind)) => {
+                // `rest` may point into another buffer than `buf`
+                let pos = (rest.as_ptr() as usize).wrapping_sub(buf.as_ptr() as usize);
+                if pos <= buf.len() {
+                    format!("{:?} at byte {} of the object", kind, pos)
+                } else {
+                    format!("{:?}", kind)
+                }
+            }
+        };
+        Error::Parse {
+            class: class.to_string(),
+            offset,
+            msg,
+        }
+    }
+
+    /// A copy of this error, e.g. to report it for several entries of
+    /// a branch. The causes of `Io` and `Http` errors cannot be
+    /// copied; only their messages are kept.
+    // `io::Error::other` is not available on older toolchains
+    #[allow(clippy::io_other_error)]
+    pub(crate) fn duplicate(&self) -> Self {
+        use self::Error::*;
+        match self {
+            Io(e) => Io(io::Error::new(e.kind(), e.to_string())),
+            Http(e) => Io(io::Error::new(io::ErrorKind::Other, e.to_string())),
+            Response { url, range, msg } => Response {
+                url: url.clone(),
+                range: range.clone(),
+                msg: msg.clone(),
+            },
+            Decompression(msg) => Decompression(msg.clone()),
+            Unsupported(what) => Unsupported(what.clone()),
+            Parse { class, offset, msg } => Parse {
+                class: class.clone(),
+                offset: *offset,
+                msg: msg.clone(),
+            },
+            Branch {
+                branch,
+                basket,
+                entry,
+                error,
+            } => Branch {
+                branch: branch.clone(),
+                basket: *basket,
+                entry: *entry,
+                error: Box::new(error.duplicate()),
+            },
+            NotFound(what) => NotFound(what.clone()),
+            Incompatible(msg) => Incompatible(msg.clone()),
+            Fmt(e) => Fmt(*e),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Error::*;
+        match self {
+            Io(e) => write!(f, "IO error: {}", e),
+            Http(e) => write!(f, "HTTP error: {}", e),
+            Response { url, range, msg } => write!(
+                f,
+                "Invalid response for bytes {} of {}: {}",
+                range, url, msg
+            ),
+            Decompression(msg) => write!(f, "Decompression failed: {}", msg),
+            Unsupported(what) => write!(f, "Unsupported feature: {}", what),
+            Parse { class, offset, msg } => write!(
+                f,
+                "Failed to parse `{}` at offset {}: {}",
+                class, offset, msg
+            ),
+            Branch {
+                branch,
+                basket,
+                entry,
+                error,
+            } => write!(
+                f,
+                "Failed to read entry {} (basket {}) of branch `{}`: {}",
+                entry, basket, branch, error
+            ),
+            NotFound(what) => write!(f, "Not found: {}", what),
+            Incompatible(msg) => write!(f, "Incompatible objects: {}", msg),
+            Fmt(e) => write!(f, "Formatting error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Http(e) => Some(e),
+            Error::Branch { error, .. } => Some(error.as_ref()),
+            Error::Fmt(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+impl From<fmt::Error> for Error {
+    fn from(e: fmt::Error) -> Self {
+        Error::Fmt(e)
+    }
+}
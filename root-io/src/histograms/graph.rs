@@ -0,0 +1,96 @@
+use nom::error::ErrorKind;
+use nom::multi::count;
+use nom::number::complete::*;
+use nom::*;
+
+use crate::core::parsers::*;
+use crate::core::types::TNamed;
+
+/// A set of points with optional errors (ROOT's `TGraph` and
+/// `TGraphErrors`); see `FileItem::as_graph`
+#[derive(Debug, Clone)]
+pub struct Graph {
+    tnamed: TNamed,
+    x: Vec<f64>,
+    y: Vec<f64>,
+    /// Errors of `x`; empty for a `TGraph`
+    ex: Vec<f64>,
+    /// Errors of `y`; empty for a `TGraph`
+    ey: Vec<f64>,
+}
+
+impl Graph {
+    /// The name of this graph
+    pub fn name(&self) -> &str {
+        &self.tnamed.name
+    }
+
+    /// The title of this graph
+    pub fn title(&self) -> &str {
+        &self.tnamed.title
+    }
+
+    /// The x coordinate of each point
+    pub fn x(&self) -> &[f64] {
+        &self.x
+    }
+
+    /// The y coordinate of each point
+    pub fn y(&self) -> &[f64] {
+        &self.y
+    }
+
+    /// The error of the x coordinate of each point, if this graph has
+    /// errors
+    pub fn x_errors(&self) -> Option<&[f64]> {
+        Some(self.ex.as_slice()).filter(|_| self.has_errors())
+    }
+
+    /// The error of the y coordinate of each point, if this graph has
+    /// errors
+    pub fn y_errors(&self) -> Option<&[f64]> {
+        Some(self.ey.as_slice()).filter(|_| self.has_errors())
+    }
+
+    fn has_errors(&self) -> bool {
+        self.ex.len() == self.x.len() && self.ey.len() == self.y.len() && !self.x.is_empty()
+    }
+}
+
+/// Parse an array of `n` `f64`s stored behind a pointer, which is
+/// preceded by a flag telling if the pointer was null
+fn f64s_behind_pointer(input: &[u8], n: usize) -> IResult<&[u8], Vec<f64>> {
+    let (input, is_array) = be_u8(input)?;
+    if is_array == 0 {
+        return Ok((input, vec![]));
+    }
+    count(be_f64, n)(input)
+}
+
+/// Parse the members of `TGraph`, returning the number of points
+fn tgraph(input: &[u8]) -> IResult<&[u8], (usize, Graph)> {
+    do_parse!(input,
+              _ver: be_u16 >>
+              tnamed: length_value!(checked_byte_count, tnamed) >>
+              _tattline: length_data!(checked_byte_count) >>
+              _tattfill: length_data!(checked_byte_count) >>
+              _tattmarker: length_data!(checked_byte_count) >>
+              n: map!(be_i32, |n| n.max(0) as usize) >>
+              x: call!(f64s_behind_pointer, n) >>
+              y: call!(f64s_behind_pointer, n) >>
+              ((n, Graph {tnamed, x, y, ex: vec![], ey: vec![]})))
+}
+
+/// Parse a graph of the given class, either `TGraph` or `TGraphErrors`
+pub(crate) fn graph<'s>(input: &'s [u8], class: &str) -> IResult<&'s [u8], Graph> {
+    match class {
+        "TGraph" => tgraph(input).map(|(i, (_, graph))| (i, graph)),
+        "TGraphErrors" => do_parse!(input,
+                                    _ver: be_u16 >>
+                                    base: length_value!(checked_byte_count, tgraph) >>
+                                    ex: call!(f64s_behind_pointer, base.0) >>
+                                    ey: call!(f64s_behind_pointer, base.0) >>
+                                    (Graph {ex, ey, ..base.1})),
+        _ => Err(nom::Err::Error((input, ErrorKind::Tag))),
+    }
+}
@@ -0,0 +1,262 @@
+use histogram::{Histogram, NDim};
+use ndarray::{ArrayD, IxDyn, ShapeBuilder};
+use nom::combinator::map;
+use nom::error::ErrorKind;
+use nom::multi::count;
+use nom::number::complete::*;
+use nom::*;
+
+use crate::core::parsers::*;
+use crate::core::types::TNamed;
+use crate::Error;
+
+/// An axis of a histogram (ROOT's `TAxis`)
+#[derive(Debug, Clone)]
+pub(crate) struct Axis {
+    n_bins: usize,
+    min: f64,
+    max: f64,
+    /// Edges of the bins if they are not of equal width; empty
+    /// otherwise
+    edges: Vec<f64>,
+}
+
+impl Axis {
+    /// The `n_bins + 1` edges of the bins on this axis
+    pub(crate) fn edges(&self) -> Vec<f64> {
+        if self.edges.len() == self.n_bins + 1 {
+            return self.edges.clone();
+        }
+        let width = (self.max - self.min) / self.n_bins as f64;
+        (0..=self.n_bins)
+            .map(|i| self.min + width * i as f64)
+            .collect()
+    }
+}
+
+/// A histogram of one to three dimensions (ROOT's `TH1`, `TH2` and
+/// `TH3` of any bin content type)
+#[derive(Debug, Clone)]
+pub(crate) struct Hist {
+    tnamed: TNamed,
+    /// One axis per dimension
+    axes: Vec<Axis>,
+    /// Content of all bins including under- and overflow bins; the
+    /// first axis changes fastest
+    content: Vec<f64>,
+    /// Sum of squared weights of each bin as `content`; empty if the
+    /// histogram was not filled with weights
+    sumw2: Vec<f64>,
+}
+
+impl Hist {
+    /// The number of bins along each axis, including the under- and
+    /// overflow bin
+    fn shape(&self) -> Vec<usize> {
+        self.axes.iter().map(|a| a.n_bins + 2).collect()
+    }
+
+    /// Arrange a list of values of each bin like the bins
+    fn to_array(&self, values: Vec<f64>) -> Result<ArrayD<f64>, Error> {
+        ArrayD::from_shape_vec(IxDyn(&self.shape()).f(), values).map_err(|e| {
+            Error::Incompatible(format!(
+                "Bins of histogram `{}` do not match its axes: {}",
+                self.tnamed.name, e
+            ))
+        })
+    }
+
+    /// Convert into a `Histogram` of the same dimension. Without
+    /// weights, the sum of squared weights is equal to the content
+    pub(crate) fn to_histogram<D: NDim>(&self) -> Result<Histogram<f64, D>, Error> {
+        let sumw2 = if self.sumw2.is_empty() {
+            self.content.clone()
+        } else {
+            self.sumw2.clone()
+        };
+        to_histogram(self, self.content.clone(), sumw2)
+    }
+}
+
+/// Build a `Histogram` with the axes of `hist` and the given content
+/// and sum of squared weights
+fn to_histogram<D: NDim>(
+    hist: &Hist,
+    content: Vec<f64>,
+    sumw2: Vec<f64>,
+) -> Result<Histogram<f64, D>, Error> {
+    let edges: Vec<_> = hist.axes.iter().map(Axis::edges).collect();
+    let counts = hist.to_array(content)?;
+    let sumw2 = hist.to_array(sumw2)?;
+    Histogram::from_bins_with_flow(&edges, counts, Some(sumw2)).ok_or_else(|| {
+        Error::Incompatible(format!(
+            "Histogram `{}` has {} dimensions, but {} were requested",
+            hist.tnamed.name,
+            hist.axes.len(),
+            D::N
+        ))
+    })
+}
+
+/// A profile histogram (ROOT's `TProfile`), holding the mean of a
+/// quantity in each bin
+#[derive(Debug, Clone)]
+pub(crate) struct Profile {
+    /// The sum of weighted values in `content` and of weighted squared
+    /// values in `sumw2`
+    hist: Hist,
+    /// Sum of weights of each bin
+    bin_entries: Vec<f64>,
+    /// How the errors are computed (ROOT's `EErrorType`)
+    error_mode: i32,
+    /// Sum of squared weights of each bin; empty if the profile was
+    /// not filled with weights
+    bin_sumw2: Vec<f64>,
+}
+
+impl Profile {
+    /// The mean and its error in each bin, computed as in ROOT's
+    /// `TProfile::GetBinContent` and `TProfile::GetBinError`
+    fn means_and_errors(&self) -> (Vec<f64>, Vec<f64>) {
+        (0..self.bin_entries.len())
+            .map(|bin| {
+                let sumw = self.bin_entries[bin];
+                if sumw == 0. {
+                    return (0., 0.);
+                }
+                let mean = self.hist.content.get(bin).copied().unwrap_or(0.) / sumw;
+                let mean_of_squares = self.hist.sumw2.get(bin).copied().unwrap_or(0.) / sumw;
+                let spread = (mean_of_squares - mean * mean).abs().sqrt();
+                let neff = match self.bin_sumw2.get(bin) {
+                    Some(sumw2) if *sumw2 > 0. => sumw * sumw / sumw2,
+                    Some(_) => 0.,
+                    None => sumw,
+                };
+                let error = match self.error_mode {
+                    // "s": the spread of the values
+                    1 => spread,
+                    // "i": as the default, but at least the error of
+                    // integer values
+                    2 if spread == 0. => 1. / (12. * neff).sqrt(),
+                    // "g": the error of a mean of gaussian values
+                    3 => 1. / sumw.sqrt(),
+                    // The error of the mean
+                    _ => spread / neff.sqrt(),
+                };
+                (mean, error)
+            })
+            .unzip()
+    }
+
+    /// Convert into a `Histogram` holding the mean of each bin. The
+    /// squared error of each mean is stored as its sum of squared
+    /// weights
+    pub(crate) fn to_histogram<D: NDim>(&self) -> Result<Histogram<f64, D>, Error> {
+        let (means, errors) = self.means_and_errors();
+        let variances = errors.iter().map(|e| e * e).collect();
+        to_histogram(&self.hist, means, variances)
+    }
+}
+
+/// Parse a `TAxis`
+fn taxis(input: &[u8]) -> IResult<&[u8], Axis> {
+    do_parse!(input,
+              _ver: be_u16 >>
+              _tnamed: length_value!(checked_byte_count, tnamed) >>
+              _tattaxis: length_data!(checked_byte_count) >>
+              n_bins: be_i32 >>
+              min: be_f64 >>
+              max: be_f64 >>
+              edges: tarrayd >>
+              (Axis {n_bins: n_bins.max(0) as usize, min, max, edges}))
+}
+
+/// Parse the members of `TH1` common to all histograms; the content
+/// is stored by the derived classes
+fn th1(input: &[u8]) -> IResult<&[u8], Hist> {
+    let axis = |i| length_value!(i, checked_byte_count, taxis);
+    do_parse!(input,
+              _ver: be_u16 >>
+              tnamed: length_value!(checked_byte_count, tnamed) >>
+              _tattline: length_data!(checked_byte_count) >>
+              _tattfill: length_data!(checked_byte_count) >>
+              _tattmarker: length_data!(checked_byte_count) >>
+              _ncells: be_i32 >>
+              x: axis >>
+              y: axis >>
+              z: axis >>
+              _bar_offset: be_i16 >>
+              _bar_width: be_i16 >>
+              // Number of entries, sums of weights, extrema and the
+              // normalization factor
+              _stats: count!(be_f64, 8) >>
+              _contour: tarrayd >>
+              sumw2: tarrayd >>
+              (Hist {tnamed, axes: vec![x, y, z], content: vec![], sumw2}))
+}
+
+/// Parse a `TArray` of the type given by the last letter of the class
+/// name of a histogram (e.g. `F` for `TH1F`)
+fn tarray_of(input: &[u8], ty: char) -> IResult<&[u8], Vec<f64>> {
+    let (input, n) = be_i32(input)?;
+    let n = n.max(0) as usize;
+    match ty {
+        'C' => count(map(be_i8, f64::from), n)(input),
+        'S' => count(map(be_i16, f64::from), n)(input),
+        'I' => count(map(be_i32, f64::from), n)(input),
+        'F' => count(map(be_f32, f64::from), n)(input),
+        'D' => count(be_f64, n)(input),
+        _ => Err(nom::Err::Error((input, ErrorKind::Alt))),
+    }
+}
+
+/// The dimension and the type of the bin content of a histogram
+/// class such as `TH2F`
+pub(crate) fn hist_class(class: &str) -> Option<(usize, char)> {
+    let mut chars = class.strip_prefix("TH")?.chars();
+    let n_dim = chars.next()?.to_digit(10)? as usize;
+    let ty = chars.next()?;
+    if chars.next().is_some() || !(1..=3).contains(&n_dim) || !"CSIFD".contains(ty) {
+        return None;
+    }
+    Some((n_dim, ty))
+}
+
+/// Parse a histogram of the given class (e.g. `TH1F` or `TH3D`)
+pub(crate) fn hist<'s>(input: &'s [u8], class: &str) -> IResult<&'s [u8], Hist> {
+    let (n_dim, ty) = hist_class(class).ok_or(nom::Err::Error((input, ErrorKind::Tag)))?;
+    // `TH2` and `TH3` wrap `TH1` and add more sums
+    let base = |i| match n_dim {
+        1 => th1(i),
+        _ => preceded!(i, be_u16, length_value!(checked_byte_count, th1)),
+    };
+    do_parse!(input,
+    _ver: be_u16 >>
+    hist: length_value!(checked_byte_count, base) >>
+    content: call!(tarray_of, ty) >>
+    ({
+        let mut hist = hist;
+        hist.axes.truncate(n_dim);
+        hist.content = content;
+        hist
+    }))
+}
+
+/// Parse a `TProfile`
+pub(crate) fn profile(input: &[u8]) -> IResult<&[u8], Profile> {
+    let th1d = |i| hist(i, "TH1D");
+    do_parse!(input,
+    ver: be_u16 >>
+    hist: length_value!(checked_byte_count, th1d) >>
+    bin_entries: tarrayd >>
+    error_mode: be_i32 >>
+    // Range of the values and their sums
+    _stats: count!(be_f64, 4) >>
+    bin_sumw2: cond!(ver >= 7, tarrayd) >>
+    (Profile {
+        hist,
+        bin_entries,
+        error_mode,
+        bin_sumw2: bin_sumw2.unwrap_or_default(),
+    }))
+}
@@ -0,0 +1,185 @@
+//! Parsers for histograms (`TH1`, `TH2`, `TH3` and `TProfile`) and
+//! graphs (`TGraph` and `TGraphErrors`). Histograms are converted to
+//! the `Histogram` type of the `histogram` crate; see
+//! `FileItem::as_histogram`.
+
+mod graph;
+mod hist;
+
+pub(crate) use self::graph::graph;
+pub use self::graph::Graph;
+pub(crate) use self::hist::{hist, hist_class, profile};
+
+// The objects below are serialized by hand, following the streamers
+// of ROOT 6. None of the files in `test_data` holds a histogram or a
+// graph; a file written by ROOT with a `TH1F`, a `TH2D`, a `TProfile`
+// and a `TGraphErrors`, whose edges, flow bins and sums of squared
+// weights are checked against the values ROOT reports, is still
+// missing.
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use histogram::{Centers, Histogram};
+    use ndarray::{arr1, arr2};
+
+    use super::*;
+    use crate::test_utils::{tnamed, tstring, with_byte_count};
+
+    /// Serialized attributes of lines, markers, etc.
+    fn tatt() -> Vec<u8> {
+        with_byte_count(vec![0, 2, 0, 1, 0, 1])
+    }
+
+    fn f64s(values: &[f64]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|v| v.to_be_bytes().to_vec())
+            .collect()
+    }
+
+    fn tarrayd(values: &[f64]) -> Vec<u8> {
+        let mut out = (values.len() as i32).to_be_bytes().to_vec();
+        out.extend(f64s(values));
+        out
+    }
+
+    fn taxis(n_bins: i32, min: f64, max: f64, edges: &[f64]) -> Vec<u8> {
+        let mut out = vec![0, 10];
+        out.extend(tnamed("xaxis", "a title"));
+        out.extend(tatt());
+        out.extend(&n_bins.to_be_bytes());
+        out.extend(f64s(&[min, max]));
+        out.extend(tarrayd(edges));
+        // First and last bin, etc.
+        out.extend(&[0; 12]);
+        with_byte_count(out)
+    }
+
+    /// A serialized `TH1` with the given axes and sums of squared weights
+    fn th1(axes: &[Vec<u8>], sumw2: &[f64]) -> Vec<u8> {
+        let mut out = vec![0, 8];
+        out.extend(tnamed("hist", "a title"));
+        out.extend(tatt());
+        out.extend(tatt());
+        out.extend(tatt());
+        out.extend(&0i32.to_be_bytes());
+        for axis in axes {
+            out.extend(axis);
+        }
+        out.extend(&[0; 4]);
+        out.extend(f64s(&[10., 0., 0., 0., 0., 0., 0., 0.]));
+        out.extend(tarrayd(&[]));
+        out.extend(tarrayd(sumw2));
+        // Option, functions, buffer, etc.
+        out.extend(tstring(""));
+        out.extend(&[0; 13]);
+        with_byte_count(out)
+    }
+
+    #[test]
+    fn th1f() {
+        let axes = [
+            taxis(3, 0., 3., &[]),
+            taxis(1, 0., 1., &[]),
+            taxis(1, 0., 1., &[]),
+        ];
+        let mut buf = vec![0, 2];
+        buf.extend(th1(&axes, &[]));
+        buf.extend(&5i32.to_be_bytes());
+        for v in &[1f32, 2., 3., 4., 5.] {
+            buf.extend(&v.to_be_bytes());
+        }
+        let (_, hist) = hist(&buf, "TH1F").unwrap();
+        let h: Histogram<f64, [usize; 1]> = hist.to_histogram().unwrap();
+        assert_eq!(h.counts, arr1(&[2., 3., 4.]).into_dyn());
+        assert_eq!(h.centers(0), &[0.5, 1.5, 2.5]);
+        let with_flow = arr1(&[1., 2., 3., 4., 5.]).into_dyn();
+        assert_eq!(h.counts_with_flow(), Some(&with_flow));
+        // Unweighted histograms store no sum of squared weights
+        assert_eq!(h.sumw2(), Some(&with_flow));
+        assert!(hist.to_histogram::<[usize; 2]>().is_err());
+    }
+
+    #[test]
+    fn th2d() {
+        let axes = [
+            taxis(2, 0., 3., &[0., 1., 3.]),
+            taxis(1, -1., 1., &[]),
+            taxis(1, 0., 1., &[]),
+        ];
+        let content: Vec<f64> = (0..12).map(f64::from).collect();
+        let sumw2: Vec<f64> = content.iter().map(|c| 2. * c).collect();
+        let mut th2 = vec![0, 4];
+        th2.extend(th1(&axes, &sumw2));
+        th2.extend(f64s(&[0.; 4]));
+        let mut buf = vec![0, 3];
+        buf.extend(with_byte_count(th2));
+        buf.extend(tarrayd(&content));
+        let (_, hist) = hist(&buf, "TH2D").unwrap();
+        let h: Histogram<f64, [usize; 2]> = hist.to_histogram().unwrap();
+        // The x axis changes fastest in ROOT's layout
+        assert_eq!(h.counts, arr2(&[[5.], [6.]]).into_dyn());
+        assert_eq!(h.centers(0), &[0.5, 2.]);
+        assert_eq!(h.centers(1), &[0.]);
+        let sumw2 = h.sumw2().unwrap();
+        assert_eq!(sumw2.shape(), &[4, 3]);
+        assert_eq!(sumw2[[1, 2].as_ref()], 18.);
+    }
+
+    #[test]
+    fn tprofile() {
+        let axes = [
+            taxis(2, 0., 2., &[]),
+            taxis(1, 0., 1., &[]),
+            taxis(1, 0., 1., &[]),
+        ];
+        // The values 1 and 3 in the first bin; the second one is empty
+        let mut th1d = vec![0, 2];
+        th1d.extend(th1(&axes, &[0., 10., 0., 0.]));
+        th1d.extend(tarrayd(&[0., 4., 0., 0.]));
+        let mut buf = vec![0, 7];
+        buf.extend(with_byte_count(th1d));
+        buf.extend(tarrayd(&[0., 2., 0., 0.]));
+        buf.extend(&0i32.to_be_bytes());
+        buf.extend(f64s(&[0.; 4]));
+        buf.extend(tarrayd(&[]));
+        let (_, profile) = profile(&buf).unwrap();
+        let h: Histogram<f64, [usize; 1]> = profile.to_histogram().unwrap();
+        assert_eq!(h.counts, arr1(&[2., 0.]).into_dyn());
+        let sumw2 = h.sumw2().unwrap();
+        assert!((sumw2[[1].as_ref()] - 0.5).abs() < 1e-12);
+        assert_eq!(sumw2[[2].as_ref()], 0.);
+    }
+
+    #[test]
+    fn tgrapherrors() {
+        let mut tgraph = vec![0, 4];
+        tgraph.extend(tnamed("graph", "a title"));
+        tgraph.extend(tatt());
+        tgraph.extend(tatt());
+        tgraph.extend(tatt());
+        tgraph.extend(&2i32.to_be_bytes());
+        for values in &[[1., 2.], [10., 20.]] {
+            tgraph.push(1);
+            tgraph.extend(f64s(values));
+        }
+        // Functions, histogram, extrema
+        tgraph.extend(&[0; 24]);
+        let (_, g) = graph(&tgraph, "TGraph").unwrap();
+        assert_eq!(g.name(), "graph");
+        assert_eq!(g.title(), "a title");
+        assert_eq!(g.y(), &[10., 20.]);
+        assert_eq!(g.y_errors(), None);
+
+        let mut buf = vec![0, 3];
+        buf.extend(with_byte_count(tgraph));
+        for values in &[[0.5, 0.5], [1., 2.]] {
+            buf.push(1);
+            buf.extend(f64s(values));
+        }
+        let (_, g) = graph(&buf, "TGraphErrors").unwrap();
+        assert_eq!(g.x(), &[1., 2.]);
+        assert_eq!(g.x_errors(), Some(&[0.5, 0.5][..]));
+        assert_eq!(g.y_errors(), Some(&[1., 2.][..]));
+        assert!(graph(&buf, "TGraphAsymmErrors").is_err());
+    }
+}
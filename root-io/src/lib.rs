@@ -21,17 +21,21 @@ extern crate bitflags;
 extern crate nom;
 #[macro_use]
 extern crate quote;
-#[macro_use]
 extern crate failure;
 extern crate flate2;
+extern crate lz4_flex;
 extern crate lzma_rs;
 extern crate reqwest;
+extern crate ruzstd;
+extern crate xxhash_rust;
 
 extern crate alice_open_data;
 
 // pub mod core_types;
 mod code_gen;
 pub mod core;
+mod error;
+mod histograms;
 pub mod test_utils;
 mod tests;
 pub mod tree_reader;
@@ -39,7 +43,14 @@ pub mod tree_reader;
 // Contains the stream_zip macro
 pub mod utils;
 
-pub use crate::core::{FileItem, RootFile, Source};
+#[cfg(not(target_arch = "wasm32"))]
+pub use crate::core::CachedSource;
+pub use crate::core::{
+    DataSource, Directory, FileItem, HttpOptions, RootFile, Source, SourceFuture, SourceVersion,
+};
+pub use crate::error::Error;
+pub use crate::histograms::Graph;
+pub use crate::tree_reader::Chain;
 
 /// Offset when using Context; should be in `Context`, maybe?
 const MAP_OFFSET: u64 = 2;
@@ -2,6 +2,30 @@
 
 pub use cfg_gated::log;
 
+/// A serialized `TString` shorter than 255 bytes
+pub(crate) fn tstring(s: &str) -> Vec<u8> {
+    let mut out = vec![s.len() as u8];
+    out.extend(s.as_bytes());
+    out
+}
+
+/// `obj` preceded by its byte count, as written for most objects
+pub(crate) fn with_byte_count(obj: Vec<u8>) -> Vec<u8> {
+    let mut out = (0x4000_0000 | obj.len() as u32).to_be_bytes().to_vec();
+    out.extend(obj);
+    out
+}
+
+/// A serialized `TNamed`, including its byte count, with the given
+/// name and title shorter than 255 bytes
+pub(crate) fn tnamed(name: &str, title: &str) -> Vec<u8> {
+    // Versions of `TNamed` and `TObject`, unique id and bits
+    let mut out = vec![0, 1, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0];
+    out.extend(tstring(name));
+    out.extend(tstring(title));
+    with_byte_count(out)
+}
+
 #[cfg(target_arch = "wasm32")]
 mod cfg_gated {
     use wasm_bindgen::JsValue;
@@ -1,11 +1,33 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::collections::HashMap;
+use std::iter;
+use std::ops::Range;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Mutex;
+
+#[cfg(not(target_arch = "wasm32"))]
+use futures::executor::ThreadPool;
+use futures::future::Either;
 use futures::prelude::*;
-use nom::multi::count;
+use nom::bytes::complete::take;
+use nom::combinator::{all_consuming, map};
+use nom::multi::{count, many0};
 use nom::number::complete::*;
 use nom::*;
+#[cfg(not(target_arch = "wasm32"))]
+use once_cell::sync::Lazy;
 
 use crate::{
-    code_gen::rust::ToRustType, core::parsers::*, core::types::*,
-    tree_reader::container::Container, tree_reader::leafs::tleaf, tree_reader::leafs::TLeaf,
+    code_gen::rust::ToRustType,
+    core::parsers::*,
+    core::types::*,
+    core::{Interpreter, TStreamerInfo},
+    tree_reader::column::{basket_column, BasketColumn, Primitive},
+    tree_reader::container::{BasketData, Container},
+    tree_reader::leafs::tleaves,
+    tree_reader::leafs::TLeaf,
+    tree_reader::value::{Double32, Scalar, Value, ValueLayout},
+    Error,
 };
 
 /// A `TBranch` describes one "Column" of a `TTree`
@@ -31,7 +53,7 @@ pub struct TBranch {
     /// Branch split level
     fsplitlevel: i32,
     /// Number of entries
-    fentries: i64,
+    pub(crate) fentries: i64,
     /// Number of the first entry in this branch
     ffirstentry: i64,
     /// Total number of bytes in all leaves before compression
@@ -43,8 +65,10 @@ pub struct TBranch {
     /// -> List of leaves of this branch (TODO: Parse to TLeafC/I/F..)
     fleaves: Vec<TLeaf>,
     /// Table of first entry in each basket
-    fbasketentry: Vec<i64>,
+    pub(crate) fbasketentry: Vec<i64>,
     containers: Vec<Container>,
+    /// Class of the object stored in a `TBranchElement` or `TBranchObject`
+    class_name: Option<String>,
 }
 
 impl TBranch {
@@ -84,48 +108,31 @@ impl TBranch {
     ///
     /// # Example
     /// ```
-    /// extern crate failure;
-    /// extern crate nom;
-    /// extern crate root_io;
     /// use futures::StreamExt;
-    ///
-    /// use std::path::Path;
     /// use nom::number::complete::be_i32;
+    /// use std::path::Path;
+    /// use root_io::{Error, RootFile};
     ///
-    /// use root_io::tree_reader::Tree;
-    /// use root_io::RootFile;
-    ///
-    /// #[tokio::main]
-    ///# async fn main
-    ///
-    ///# () {
-    ///     let path = Path::new("./src/test_data/simple.root");
-    ///     let f = RootFile::new(path).await.expect("Failed to open file");
-    ///     let tree = f.items()[0].as_tree().await.unwrap();
-    ///     let numbers = tree
-    ///         .branch_by_name("one").unwrap()
-    ///         // Must pass parser as closure
-    ///         .as_fixed_size_iterator(|i| be_i32(i));
-    ///     numbers.for_each(|n| async move {
-    ///         println!("All the numbers of this branch{:?}", n);
-    ///     }).await;
-    ///# }
+    /// # async fn f() -> Result<(), Error> {
+    /// let path = Path::new("./src/test_data/simple.root");
+    /// let f = RootFile::new(path).await?;
+    /// let tree = f.items()[0].as_tree().await?;
+    /// let numbers = tree
+    ///     .branch_by_name("one")?
+    ///     // Must pass parser as closure
+    ///     .as_fixed_size_iterator(|i| be_i32(i));
+    /// numbers.for_each(|n| async move {
+    ///     println!("All the numbers of this branch{:?}", n);
+    /// }).await;
+    /// # Ok(())
+    /// # }
     /// ```
     pub fn as_fixed_size_iterator<T, P>(&self, p: P) -> impl Stream<Item = T>
     where
         P: Fn(&[u8]) -> IResult<&[u8], T>,
     {
-        stream::iter(self.containers().to_owned())
-            .then(|basket| async move { basket.raw_data().await.unwrap() })
-            .map(move |(n_events_in_basket, buffer)| {
-                // Parse the entire basket buffer; if something is left over its just junk
-                let events = match count(&p, n_events_in_basket as usize)(&buffer) {
-                    Ok((_rest, output)) => output,
-                    Err(e) => panic!(format!("Parser failed unexpectedly {:?}", e)),
-                };
-                stream::iter(events)
-            })
-            .flatten()
+        self.try_as_fixed_size_iterator(p)
+            .map(|res| res.unwrap_or_else(|e| panic!("{}", e)))
     }
 
     /// Iterator over the data of a column (`TBranch`) with a variable
@@ -136,46 +143,722 @@ impl TBranch {
     where
         P: Fn(&[u8]) -> IResult<&[u8], T>,
     {
-        let mut elems_per_event = el_counter.to_owned().into_iter();
-        stream::iter(self.containers().to_owned())
-            .then(|basket| async move { basket.raw_data().await.unwrap() })
-            .map(move |(n_events_in_basket, buffer)| {
-                let mut buffer = buffer.as_slice();
-                let mut events = Vec::with_capacity(n_events_in_basket as usize);
-                for _ in 0..n_events_in_basket {
-                    if let Some(n_elems_in_event) = elems_per_event.next() {
-                        match count(&p, n_elems_in_event as usize)(&buffer) {
-                            Ok((rest, output)) => {
-                                buffer = rest;
-                                events.push(output)
-                            }
-                            Err(e) => panic!(format!("Parser failed unexpectedly {:?}", e)),
+        self.try_as_var_size_iterator(p, el_counter)
+            .map(|res| res.unwrap_or_else(|e| panic!("{}", e)))
+    }
+
+    /// Iterator over the data of a column (`TBranch`) with a variable
+    /// number of elements per entry. Contrary to
+    /// `as_var_size_iterator`, no external counter is needed; the
+    /// entries are split using the entry offsets stored in each
+    /// basket. The given parser is applied repeatedly until all bytes
+    /// of an entry are consumed.
+    pub fn as_jagged_iterator<T, P>(&self, p: P) -> impl Stream<Item = Vec<T>>
+    where
+        P: Fn(&[u8]) -> IResult<&[u8], T>,
+    {
+        self.try_as_jagged_iterator(p)
+            .map(|res| res.unwrap_or_else(|e| panic!("{}", e)))
+    }
+
+    /// Stream over the entries of this branch as dynamically typed
+    /// `Value`s. The decoder is chosen based on the leaf of this
+    /// branch. `streamers` (see `RootFile::streamers`) are needed to
+    /// decode `Double32_t` members, whose precision is given in the
+    /// comment of their streamer. Jagged branches yield `Value::Vec`.
+    pub fn as_value_stream(
+        &self,
+        streamers: &[TStreamerInfo],
+    ) -> impl Stream<Item = Result<Value, Error>> {
+        match self.value_layout(streamers) {
+            Err(e) => Either::Left(stream::once(future::ready(Err(e)))),
+            Ok(layout) if layout.jagged => Either::Right(Either::Left(
+                self.try_as_jagged_iterator(move |i| layout.parse_element(i))
+                    .map(|res| res.map(Value::Vec)),
+            )),
+            Ok(layout) => Either::Right(Either::Right(
+                self.try_as_fixed_size_iterator(move |i| layout.parse_element(i)),
+            )),
+        }
+    }
+
+    /// Stream over the objects stored in this branch, decoded into
+    /// `Value::Struct`s by following the streamer info of their class
+    /// (see `RootFile::streamers`). This works for branches holding
+    /// whole objects, i.e. `TBranchObject`s and `TBranchElement`s
+    /// which are not split. Pointers within the objects can only be
+    /// followed if they define the class of the object they point to.
+    pub fn as_object_stream<'a>(
+        &self,
+        streamers: &'a [TStreamerInfo],
+    ) -> impl Stream<Item = Result<Value, Error>> + 'a {
+        let interpreter = match (&self.class_name, self.fleaves.as_slice()) {
+            (Some(class), [leaf]) if self.fbranches.is_empty() && leaf.holds_objects() => {
+                Interpreter::new(streamers, class).map(|i| (i, class.clone()))
+            }
+            _ => Err(Error::Unsupported(format!(
+                "Reading branch `{}` as objects; it does not hold whole objects",
+                self.name
+            ))),
+        };
+        match interpreter {
+            Err(e) => Either::Left(stream::once(future::ready(Err(e)))),
+            Ok((interpreter, class)) => {
+                let name = self.name();
+                let stream = self.raw_baskets().map(move |(basket, entries, raw)| {
+                    let (events, err) = match raw {
+                        Ok((offset, data)) => {
+                            let (events, err) =
+                                parse_entries(&data, offset, entries.start, |_, i| {
+                                    Some(interpreter.object(i, &class))
+                                });
+                            // Name the data member which could not be read
+                            let err = err.map(|(entry, e)| (entry, interpreter.annotate(e)));
+                            (events, err)
                         }
+                        Err(e) => (vec![], Some((entries.start, e))),
+                    };
+                    stream::iter(basket_items(&name, basket, entries.end, events, err))
+                });
+                Either::Right(stream.flatten())
+            }
+        }
+    }
+
+    /// Stream over the baskets of this branch, each decoded into a
+    /// column of primitive values. Contrary to the other iterators,
+    /// no parser is run per element; the big-endian values are
+    /// converted for a whole basket at once, which is much
+    /// faster. `T` has to match the type of the leaf (see
+    /// `element_types`). `Double32_t` branches are not supported;
+    /// use `as_value_stream` for those.
+    ///
+    /// # Example
+    /// ```
+    /// use futures::TryStreamExt;
+    /// use std::path::Path;
+    /// use root_io::{Error, RootFile};
+    ///
+    /// # async fn f() -> Result<(), Error> {
+    /// let path = Path::new("./src/test_data/sample-5.30.00-zlib.root");
+    /// let f = RootFile::new(path).await?;
+    /// let tree = f.items()[0].as_tree().await?;
+    /// let sum: i32 = tree
+    ///     .branch_by_name("i4")?
+    ///     .read_basket_columns::<i32>()
+    ///     .try_fold(0, |acc, basket| async move {
+    ///         Ok(acc + basket.values.iter().sum::<i32>())
+    ///     })
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_basket_columns<T: Primitive>(
+        &self,
+    ) -> impl Stream<Item = Result<BasketColumn<T>, Error>> {
+        let layout = self
+            .value_layout(&[])
+            .and_then(|layout| match layout.scalar {
+                Scalar::Double32(_) => Err(Error::Unsupported(format!(
+                    "Reading `Double32_t` branch `{}` as columns",
+                    self.name
+                ))),
+                scalar if scalar.type_name() != T::TYPE_NAME => Err(Error::Unsupported(format!(
+                    "Reading branch `{}` of type `{}` as `{}`",
+                    self.name,
+                    scalar.type_name(),
+                    T::TYPE_NAME
+                ))),
+                _ => Ok(layout),
+            });
+        let layout = match layout {
+            Ok(layout) => layout,
+            Err(e) => return Either::Left(stream::once(future::ready(Err(e)))),
+        };
+        let name = self.name();
+        Either::Right(self.raw_baskets().map(move |(basket, entries, raw)| {
+            raw.and_then(|(offset, data)| basket_column(&layout, &data, offset, entries.start))
+                .map_err(|e| branch_error(&name, basket, entries.start, e))
+        }))
+    }
+
+    /// The layout of the values of this branch, given by its leaf
+    fn value_layout(&self, streamers: &[TStreamerInfo]) -> Result<ValueLayout, Error> {
+        let leaf = match self.fleaves.as_slice() {
+            [leaf] => leaf,
+            leaves => {
+                return Err(Error::Unsupported(format!(
+                    "Reading branch `{}` with {} leaves as `Value`",
+                    self.name,
+                    leaves.len()
+                )))
+            }
+        };
+        leaf.value_layout(|member| {
+            let title = self
+                .class_name
+                .as_ref()
+                .and_then(|class| TStreamerInfo::member_title(streamers, class, member));
+            Double32::from_title(title.unwrap_or(""))
+        })
+    }
+
+    /// Same as `as_fixed_size_iterator`, but errors are returned
+    /// instead of panicking. If a basket cannot be read or parsed, an
+    /// `Error::Branch` is yielded for each entry which is lost and the
+    /// stream continues with the next basket. Hence, the streams of
+    /// several branches of a tree stay aligned when zipped.
+    pub fn try_as_fixed_size_iterator<T, P>(&self, p: P) -> impl Stream<Item = Result<T, Error>>
+    where
+        P: Fn(&[u8]) -> IResult<&[u8], T>,
+    {
+        self.stream_with(p, ReadOptions::default())
+    }
+
+    /// Same as `try_as_fixed_size_iterator`, but the baskets are read
+    /// as configured by `options`. With read-ahead, several baskets
+    /// are fetched at the same time, which hides the latency of
+    /// remote files. The entries are still yielded in order.
+    ///
+    /// # Example
+    /// ```
+    /// use futures::TryStreamExt;
+    /// use nom::number::complete::be_i32;
+    /// use std::path::Path;
+    /// use root_io::tree_reader::ReadOptions;
+    /// use root_io::{Error, RootFile};
+    ///
+    /// # async fn f() -> Result<(), Error> {
+    /// let path = Path::new("./src/test_data/sample-5.30.00-zlib.root");
+    /// let f = RootFile::new(path).await?;
+    /// let tree = f.items()[0].as_tree().await?;
+    /// let options = ReadOptions {
+    ///     prefetch: 4,
+    ///     decompress_threads: 2,
+    /// };
+    /// let numbers: Vec<i32> = tree
+    ///     .branch_by_name("i4")?
+    ///     .stream_with(|i| be_i32(i), options)
+    ///     .try_collect()
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn stream_with<T, P>(
+        &self,
+        p: P,
+        options: ReadOptions,
+    ) -> impl Stream<Item = Result<T, Error>>
+    where
+        P: Fn(&[u8]) -> IResult<&[u8], T>,
+    {
+        let name = self.name();
+        self.raw_baskets_with(options)
+            .map(move |(basket, entries, raw)| {
+                let (events, err) = match raw {
+                    Ok((offset, data)) => {
+                        parse_entries(&data, offset, entries.start, |_, i| Some(p(i)))
                     }
-                }
-                stream::iter(events)
+                    Err(e) => (vec![], Some((entries.start, e))),
+                };
+                stream::iter(basket_items(&name, basket, entries.end, events, err))
             })
             .flatten()
     }
+
+    /// Same as `as_var_size_iterator`, but errors are returned
+    /// instead of panicking. Entries which cannot be read are handled
+    /// as in `try_as_fixed_size_iterator`.
+    pub fn try_as_var_size_iterator<T, P>(
+        &self,
+        p: P,
+        el_counter: &[u32],
+    ) -> impl Stream<Item = Result<Vec<T>, Error>>
+    where
+        P: Fn(&[u8]) -> IResult<&[u8], T>,
+    {
+        let name = self.name();
+        let el_counter = el_counter.to_owned();
+        self.raw_baskets()
+            .map(move |(basket, entries, raw)| {
+                let (events, err) = match raw {
+                    Ok((offset, data)) => {
+                        parse_entries(&data, offset, entries.start, |entry, i| {
+                            el_counter
+                                .get(entry as usize)
+                                .map(|n_elems_in_event| count(&p, *n_elems_in_event as usize)(i))
+                        })
+                    }
+                    Err(e) => (vec![], Some((entries.start, e))),
+                };
+                stream::iter(basket_items(&name, basket, entries.end, events, err))
+            })
+            .flatten()
+    }
+
+    /// Same as `as_jagged_iterator`, but errors are returned instead
+    /// of panicking. Entries which cannot be read are handled as in
+    /// `try_as_fixed_size_iterator`.
+    pub fn try_as_jagged_iterator<T, P>(&self, p: P) -> impl Stream<Item = Result<Vec<T>, Error>>
+    where
+        P: Fn(&[u8]) -> IResult<&[u8], T>,
+    {
+        let name = self.name();
+        self.raw_baskets()
+            .map(move |(basket, entries, raw)| {
+                let first_entry = entries.start;
+                let (events, err) = match raw {
+                    Ok((offset, data)) => match data.entry_offsets {
+                        Some(ref offsets) => {
+                            parse_entries(&data, offset, first_entry, |entry, i| {
+                                let k = (entry - first_entry) as usize;
+                                Some(jagged_entry(&p, i, offsets[k + 1] - offsets[k]))
+                            })
+                        }
+                        None => {
+                            let e = Error::Unsupported(
+                                "Jagged iteration over a basket without entry offsets".to_string(),
+                            );
+                            (vec![], Some((first_entry, e)))
+                        }
+                    },
+                    Err(e) => (vec![], Some((first_entry, e))),
+                };
+                stream::iter(basket_items(&name, basket, entries.end, events, err))
+            })
+            .flatten()
+    }
+
+    /// Read a single entry of a branch with a constant number of
+    /// elements per entry. Only the basket containing this entry is
+    /// read (and fetched, if the file is remote).
+    pub async fn entry<T, P>(&self, i: u64, p: P) -> Result<T, Error>
+    where
+        P: Fn(&[u8]) -> IResult<&[u8], T>,
+    {
+        let mut entries = self.entries(i..i + 1, p).await?;
+        entries.pop().ok_or_else(|| self.missing_entries(i..i + 1))
+    }
+
+    /// Read the entries in `range` of a branch with a constant number
+    /// of elements per entry. Only the baskets containing these
+    /// entries are read (and fetched, if the file is remote).
+    pub async fn entries<T, P>(&self, range: Range<u64>, p: P) -> Result<Vec<T>, Error>
+    where
+        P: Fn(&[u8]) -> IResult<&[u8], T>,
+    {
+        self.read_entries(range, |_, _, _, i| Some(p(i))).await
+    }
+
+    /// Read a single entry of a branch with a variable number of
+    /// elements per entry. `n_elems` is the number of elements of
+    /// this entry, i.e. the value of the counter branch for entry `i`.
+    pub async fn var_size_entry<T, P>(&self, i: u64, p: P, n_elems: u32) -> Result<Vec<T>, Error>
+    where
+        P: Fn(&[u8]) -> IResult<&[u8], T>,
+    {
+        let mut entries = self.var_size_entries(i..i + 1, p, &[n_elems]).await?;
+        entries.pop().ok_or_else(|| self.missing_entries(i..i + 1))
+    }
+
+    /// Read the entries in `range` of a branch with a variable number
+    /// of elements per entry. `el_counter` contains the number of
+    /// elements of each entry in `range`; e.g. it is read from the
+    /// counter branch with `entries(range, ..)`.
+    ///
+    /// # Example
+    /// ```
+    /// use nom::number::complete::{be_i32, be_u32};
+    /// use std::path::Path;
+    /// use root_io::{Error, RootFile};
+    ///
+    /// # async fn f() -> Result<(), Error> {
+    /// let path = Path::new("./src/test_data/sample-5.30.00-zlib.root");
+    /// let f = RootFile::new(path).await?;
+    /// let tree = f.items()[0].as_tree().await?;
+    /// let counter = tree
+    ///     .branch_by_name("n")?
+    ///     .entries(10..20, |i| be_u32(i))
+    ///     .await?;
+    /// let values: Vec<Vec<i32>> = tree
+    ///     .branch_by_name("Ai4")?
+    ///     .var_size_entries(10..20, |i| be_i32(i), &counter)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn var_size_entries<T, P>(
+        &self,
+        range: Range<u64>,
+        p: P,
+        el_counter: &[u32],
+    ) -> Result<Vec<Vec<T>>, Error>
+    where
+        P: Fn(&[u8]) -> IResult<&[u8], T>,
+    {
+        if el_counter.len() as u64 != range.end.saturating_sub(range.start) {
+            return Err(Error::Incompatible(format!(
+                "{} element counts given for entries {:?} of branch `{}`",
+                el_counter.len(),
+                range,
+                self.name
+            )));
+        }
+        let starts_basket = self.fbasketentry.contains(&(range.start as i64));
+        if !starts_basket && self.fentryoffsetlen <= 0 {
+            return Err(Error::Unsupported(format!(
+                "Reading entries {:?} of branch `{}`, which has no entry offsets",
+                range, self.name
+            )));
+        }
+        let first = range.start;
+        self.read_entries(range, |basket, k, entry, i| {
+            match entry.checked_sub(first) {
+                Some(n) => el_counter
+                    .get(n as usize)
+                    .map(|n_elems_in_event| count(&p, *n_elems_in_event as usize)(i)),
+                // Entries before the range are skipped with the entry offsets
+                None => Some(match basket.entry_offsets {
+                    Some(ref offsets) => map(take(offsets[k + 1] - offsets[k]), |_| vec![])(i),
+                    None => Err(Err::Failure((i, error::ErrorKind::Verify))),
+                }),
+            }
+        })
+        .await
+    }
+
+    /// Read the baskets containing the entries in `range` and parse
+    /// those entries with `parse_entry` (see `parse_entries`). Besides
+    /// the number of the entry and the input, `parse_entry` receives
+    /// the basket and the index of the entry within it.
+    async fn read_entries<T, F>(
+        &self,
+        range: Range<u64>,
+        mut parse_entry: F,
+    ) -> Result<Vec<T>, Error>
+    where
+        F: for<'a> FnMut(&BasketData, usize, u64, &'a [u8]) -> Option<IResult<&'a [u8], T>>,
+    {
+        if range.end > self.fentries as u64 {
+            return Err(self.missing_entries(range));
+        }
+        if range.start >= range.end {
+            return Ok(vec![]);
+        }
+        let baskets = self.baskets_for_entries(&range);
+        let first_entry_of = |basket: usize| self.fbasketentry.get(basket).map(|e| *e as u64);
+        // Fetch all needed baskets concurrently
+        let raw = future::try_join_all(baskets.clone().map(|basket| {
+            let container = self.containers[basket].to_owned();
+            let name = &self.name;
+            async move {
+                let offset = container.offset();
+                container
+                    .raw_data()
+                    .await
+                    .map(|data| (offset, data))
+                    .map_err(|e| branch_error(name, basket, first_entry_of(basket).unwrap_or(0), e))
+            }
+        }))
+        .await?;
+
+        let mut out = Vec::with_capacity((range.end - range.start) as usize);
+        let mut next_entry = first_entry_of(baskets.start).unwrap_or(0);
+        for (basket, (offset, data)) in baskets.zip(raw) {
+            let first_entry = first_entry_of(basket).unwrap_or(next_entry);
+            next_entry = first_entry + u64::from(data.n_entries);
+            let (events, err) = parse_entries(&data, offset, first_entry, |entry, i| {
+                if entry < range.end {
+                    parse_entry(&data, (entry - first_entry) as usize, entry, i)
+                } else {
+                    None
+                }
+            });
+            if let Some((entry, e)) = err {
+                return Err(branch_error(&self.name, basket, entry, e));
+            }
+            let skip = range.start.saturating_sub(first_entry) as usize;
+            out.extend(events.into_iter().skip(skip));
+        }
+        if out.len() as u64 != range.end - range.start {
+            return Err(self.missing_entries(range));
+        }
+        Ok(out)
+    }
+
+    /// Indices of the baskets holding the given (non-empty) range of entries
+    pub(crate) fn baskets_for_entries(&self, range: &Range<u64>) -> Range<usize> {
+        // Baskets which end before the range starts
+        let start = self
+            .fbasketentry
+            .iter()
+            .skip(1)
+            .take_while(|e| **e as u64 <= range.start)
+            .count();
+        // Baskets which start before the range ends
+        let end = self
+            .fbasketentry
+            .iter()
+            .take_while(|e| (**e as u64) < range.end)
+            .count();
+        // Baskets which are not in `fbasketentry` come after the known ones
+        if end == self.fbasketentry.len() {
+            start..self.containers.len()
+        } else {
+            start..end
+        }
+    }
+
+    fn missing_entries(&self, range: Range<u64>) -> Error {
+        Error::NotFound(format!(
+            "Entries {:?} of branch `{}` with {} entries",
+            range, self.name, self.fentries
+        ))
+    }
+
+    /// Stream over the baskets of this branch. Each item consists of
+    /// the index of the basket, the range of its entries and either
+    /// its offset in the file and content, or the error which
+    /// occurred while reading it.
+    #[allow(clippy::type_complexity)]
+    fn raw_baskets(
+        &self,
+    ) -> impl Stream<Item = (usize, Range<u64>, Result<(u64, BasketData), Error>)> {
+        self.raw_baskets_with(ReadOptions::default())
+    }
+
+    /// Same as `raw_baskets`, but the baskets are read as configured
+    /// by `options`
+    #[allow(clippy::type_complexity)]
+    fn raw_baskets_with(
+        &self,
+        options: ReadOptions,
+    ) -> impl Stream<Item = (usize, Range<u64>, Result<(u64, BasketData), Error>)> {
+        let first_entries = self.fbasketentry.clone();
+        let n_baskets = self.containers.len();
+        let n_entries = self.fentries as u64;
+        #[cfg(not(target_arch = "wasm32"))]
+        let pool = options.decompress_pool();
+        stream::iter(self.containers().to_owned().into_iter().enumerate())
+            .map(move |(basket, container)| {
+                #[cfg(not(target_arch = "wasm32"))]
+                let pool = match &pool {
+                    Ok(pool) => Ok(pool.clone()),
+                    Err(e) => Err(e.duplicate()),
+                };
+                async move {
+                    let offset = container.offset();
+                    #[cfg(not(target_arch = "wasm32"))]
+                    let raw = match pool {
+                        Ok(pool) => container.raw_data_on(pool).await,
+                        Err(e) => Err(e),
+                    };
+                    #[cfg(target_arch = "wasm32")]
+                    let raw = container.raw_data().await;
+                    (basket, raw.map(|data| (offset, data)))
+                }
+            })
+            // `buffered` keeps the order of the baskets
+            .buffered(options.prefetch + 1)
+            .scan(0, move |next_entry, (basket, raw)| {
+                // Prefer the entry numbers stored in the branch; they
+                // are still correct after a basket failed to be read
+                let entry_of = |i: usize| first_entries.get(i).map(|e| *e as u64);
+                let first_entry = entry_of(basket).unwrap_or(*next_entry);
+                *next_entry = match raw {
+                    Ok((_, ref data)) => first_entry + u64::from(data.n_entries),
+                    Err(_) if basket + 1 == n_baskets => n_entries.max(first_entry),
+                    // If unknown, assume that the basket held a single entry
+                    Err(_) => entry_of(basket + 1).unwrap_or(first_entry + 1),
+                };
+                future::ready(Some((basket, first_entry..*next_entry, raw)))
+            })
+    }
+}
+
+/// Options for reading the baskets of a branch; see `TBranch::stream_with`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReadOptions {
+    /// Number of baskets which are fetched and decompressed ahead of
+    /// the one currently being consumed
+    pub prefetch: usize,
+    /// Number of threads used to decompress baskets. If zero, the
+    /// baskets are decompressed in the task polling the stream. The
+    /// threads are shared by all streams using the same number of
+    /// threads. This option is ignored on `wasm32`.
+    pub decompress_threads: usize,
+}
+
+/// The thread pools for decompressing baskets, by their number of
+/// threads
+#[cfg(not(target_arch = "wasm32"))]
+static DECOMPRESS_POOLS: Lazy<Mutex<HashMap<usize, ThreadPool>>> = Lazy::new(Default::default);
+
+impl ReadOptions {
+    /// The thread pool for decompressing baskets, if any. It is
+    /// created on first use and kept for later streams.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn decompress_pool(&self) -> Result<Option<ThreadPool>, Error> {
+        if self.decompress_threads == 0 {
+            return Ok(None);
+        }
+        let mut pools = DECOMPRESS_POOLS.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(pool) = pools.get(&self.decompress_threads) {
+            return Ok(Some(pool.clone()));
+        }
+        let pool = ThreadPool::builder()
+            .pool_size(self.decompress_threads)
+            .name_prefix("root-io-decompress-")
+            .create()?;
+        pools.insert(self.decompress_threads, pool.clone());
+        Ok(Some(pool))
+    }
+}
+
+/// Parse the entries of a basket, the first of which has the number
+/// `first_entry`, with `parse_entry`. It is called with the number of
+/// each entry and returns `None` if that entry should not be
+/// parsed. Parsing stops at the first such entry or at the first
+/// error. The latter is returned together with the failing entry.
+pub(crate) fn parse_entries<T, F>(
+    basket: &BasketData,
+    offset: u64,
+    first_entry: u64,
+    mut parse_entry: F,
+) -> (Vec<T>, Option<(u64, Error)>)
+where
+    F: for<'a> FnMut(u64, &'a [u8]) -> Option<IResult<&'a [u8], T>>,
+{
+    let mut events = Vec::with_capacity(basket.n_entries as usize);
+    // If something is left over in the buffer its just junk
+    let mut i = &basket.data[..];
+    for entry in first_entry..first_entry + u64::from(basket.n_entries) {
+        match parse_entry(entry, i) {
+            Some(Ok((rest, output))) => {
+                i = rest;
+                events.push(output);
+            }
+            Some(Err(e)) => {
+                let e = Error::parse("TBasket", offset, &basket.data, e);
+                return (events, Some((entry, e)));
+            }
+            None => break,
+        }
+    }
+    (events, None)
+}
+
+/// Turn the entries parsed from a basket into the items of a
+/// stream. If an entry failed (see `parse_entries`), an
+/// `Error::Branch` is yielded for it and for each following entry
+/// before `end`, since these are lost as well.
+fn basket_items<T>(
+    branch: &str,
+    basket: usize,
+    end: u64,
+    events: Vec<T>,
+    err: Option<(u64, Error)>,
+) -> impl Iterator<Item = Result<T, Error>> {
+    let errors = err.map(|(failed, e)| {
+        let lost: Vec<_> = (failed + 1..end)
+            .map(|entry| branch_error(branch, basket, entry, e.duplicate()))
+            .collect();
+        iter::once(branch_error(branch, basket, failed, e)).chain(lost)
+    });
+    events
+        .into_iter()
+        .map(Ok)
+        .chain(errors.into_iter().flatten().map(Err))
+}
+
+/// The baskets of a branch which were not yet written to disk when
+/// the branch was saved. ROOT keeps them in `fBaskets` at their index
+/// in the branch, i.e. after the `n_written` baskets on disk; other
+/// slots are empty or hold copies of baskets on disk.
+pub(crate) fn in_memory_baskets(fbaskets: &[Raw], n_written: usize) -> Vec<Container> {
+    fbaskets
+        .iter()
+        .skip(n_written)
+        .take_while(|s| !s.obj.is_empty())
+        .map(|s| Container::InMemory(s.obj.to_vec()))
+        .collect()
+}
+
+/// Parse the first `len` bytes of `input` as a sequence of elements
+pub(crate) fn jagged_entry<'s, T, P>(
+    p: &P,
+    input: &'s [u8],
+    len: usize,
+) -> IResult<&'s [u8], Vec<T>>
+where
+    P: Fn(&[u8]) -> IResult<&[u8], T>,
+{
+    let (rest, entry) = take!(input, len)?;
+    let (_, elements) = all_consuming(many0(p))(entry).map_err(|e| reborrow_err(input, e))?;
+    Ok((rest, elements))
+}
+
+/// Add the context in which an error occurred while reading a branch
+pub(crate) fn branch_error(branch: &str, basket: usize, entry: u64, e: Error) -> Error {
+    Error::Branch {
+        branch: branch.to_string(),
+        basket,
+        entry,
+        error: Box::new(e),
+    }
 }
 
 /// `TBranchElements` are a subclass of `TBranch` if the content is an Object
-/// We ignore the extra information for now and just parse the TBranch"Header" in either case
-pub(crate) fn tbranch_hdr<'s>(raw: &Raw<'s>, ctxt: &'s Context) -> IResult<&'s [u8], TBranch> {
+/// Of the extra information, only the class name is kept. Branches of
+/// any other class are an `Error::Unsupported`.
+pub(crate) fn tbranch_hdr<'s>(
+    raw: &Raw<'s>,
+    ctxt: &'s Context,
+) -> IResult<&'s [u8], Result<TBranch, Error>> {
     match raw.classinfo.as_str() {
         "TBranchElement" | "TBranchObject" => {
-            preceded!(
+            do_parse!(
                 raw.obj,
-                be_u16, // version
-                length_value!(checked_byte_count, call!(tbranch, ctxt))
+                _ver: be_u16
+                    >> branch: length_value!(checked_byte_count, call!(tbranch, ctxt))
+                    >> class_name: string
+                    >> (branch.map(|branch| TBranch {
+                        class_name: Some(class_name),
+                        ..branch
+                    }))
             )
         }
         "TBranch" => tbranch(raw.obj, ctxt),
-        name => panic!("Unexpected Branch type {}", name),
+        other => Ok((
+            raw.obj,
+            Err(Error::Unsupported(format!("Branches of class `{}`", other))),
+        )),
     }
 }
 
-fn tbranch<'s>(input: &'s [u8], context: &Context<'s>) -> IResult<&'s [u8], TBranch> {
+/// Parse a `TObjArray` of `TBranch`es (and its subclasses). The
+/// output is an `Error::Unsupported` if any of the branches (or their
+/// sub-branches) is of an unsupported class.
+pub(crate) fn tbranches<'s, 'c>(
+    input: &'s [u8],
+    context: &'c Context,
+) -> IResult<&'s [u8], Result<Vec<TBranch>, Error>>
+where
+    's: 'c,
+{
+    let (i, raws) = length_value!(input, checked_byte_count, call!(tobjarray, context))?;
+    let branches = raws
+        .iter()
+        .map(|r| tbranch_hdr(r, context).map(|(_, b)| b))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| reborrow_err(input, e))?;
+    Ok((i, branches.into_iter().collect()))
+}
+
+fn tbranch<'s>(
+    input: &'s [u8],
+    context: &Context<'s>,
+) -> IResult<&'s [u8], Result<TBranch, Error>> {
     let _curried_raw = |i| raw(i, context);
     let wrapped_tobjarray =
         |i: &'s [u8]| length_value!(i, checked_byte_count, call!(tobjarray, context));
@@ -196,44 +879,39 @@ fn tbranch<'s>(input: &'s [u8], context: &Context<'s>) -> IResult<&'s [u8], TBra
             >> ffirstentry: be_i64
             >> ftotbytes: be_i64
             >> fzipbytes: be_i64
-            >> fbranches: wrapped_tobjarray
-            >> fleaves: wrapped_tobjarray
+            >> fbranches: call!(tbranches, context)
+            >> fleaves: call!(tleaves, context)
             >> fbaskets: wrapped_tobjarray
             >> fbasketbytes: preceded!(be_u8, count!(be_i32, fmaxbaskets as usize))
             >> fbasketentry: preceded!(be_u8, count!(be_i64, fmaxbaskets as usize))
             >> fbasketseek: preceded!(be_u8, count!(be_u64, fmaxbaskets as usize))
             >> ffilename: string
-            >> ({
+            >> (fbranches.map(|fbranches| {
                 let name = tnamed.name;
-                let fbranches = fbranches
-                    .iter()
-                    .map(|s| tbranch_hdr(s, context).unwrap().1)
-                    .collect();
-                let fleaves = fleaves
-                    .into_iter()
-                    .map(|r| tleaf(r.obj, context, &r.classinfo).unwrap().1)
-                    .collect();
-                // Remove tailing empty baskets informations
-                let fbaskets = fbaskets
-                    .into_iter()
-                    .filter(|s| !s.obj.is_empty())
-                    .map(|s| Container::InMemory(s.obj.to_vec()));
                 let nbaskets = fwritebasket as usize;
+                let fbaskets = in_memory_baskets(&fbaskets, nbaskets);
                 let fbasketbytes = fbasketbytes
                     .into_iter()
                     .take(nbaskets)
                     .map(|val| val as usize);
-                let fbasketentry = fbasketentry.into_iter().take(nbaskets).collect();
+                let fbasketentry = fbasketentry
+                    .into_iter()
+                    .take(nbaskets + fbaskets.len())
+                    .collect();
                 let fbasketseek = fbasketseek.into_iter().take(nbaskets);
-                let source = if ffilename == "" {
-                    context.source.to_owned()
-                } else {
-                    unimplemented!("Root files referencing other Root files is not implemented")
-                };
-                let containers_disk = fbasketseek
-                    .zip(fbasketbytes)
-                    .map(|(seek, len)| Container::OnDisk(source.clone(), seek, len as u64));
-                let containers = fbaskets.chain(containers_disk).collect();
+                let containers_disk = fbasketseek.zip(fbasketbytes).map(|(seek, len)| {
+                    if ffilename.is_empty() {
+                        Container::OnDisk(context.source.clone(), seek, len as u64)
+                    } else {
+                        Container::External(
+                            context.source.clone(),
+                            ffilename.clone(),
+                            seek,
+                            len as u64,
+                        )
+                    }
+                });
+                let containers = containers_disk.chain(fbaskets).collect();
                 TBranch {
                     name,
                     fcompress,
@@ -251,8 +929,9 @@ fn tbranch<'s>(input: &'s [u8], context: &Context<'s>) -> IResult<&'s [u8], TBra
                     fleaves,
                     fbasketentry,
                     containers,
+                    class_name: None,
                 }
-            })
+            }))
     )
 }
 
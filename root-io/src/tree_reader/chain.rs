@@ -0,0 +1,293 @@
+use std::sync::{Arc, Mutex};
+
+use futures::prelude::*;
+use nom::IResult;
+
+use crate::core::{boxed, RootFile, Source, SourceFuture};
+use crate::tree_reader::branch::TBranch;
+use crate::tree_reader::{FromTree, ReadOptions, Tree};
+use crate::Error;
+
+/// A tree stored in several files, read as one (ROOT's `TChain`).
+/// The files are only opened once they are needed. All files must
+/// contain a tree of the given name with the same branches; this is
+/// checked when a file is opened. The entries of the chain are
+/// numbered consecutively, starting with the entries of the first
+/// file.
+///
+/// # Example
+/// ```
+/// use futures::TryStreamExt;
+/// use nom::number::complete::{be_i32, be_u32};
+/// use std::path::Path;
+/// use root_io::{Chain, Error};
+///
+/// # async fn f() -> Result<(), Error> {
+/// let files = ["sample-5.30.00-zlib.root", "sample-6.10.05-zlib.root"]
+///     .iter()
+///     .map(|f| Path::new("./src/test_data").join(f));
+/// let chain = Chain::new("sample", files);
+/// let data: Vec<i32> = chain.stream("i4", |i| be_i32(i)).try_collect().await?;
+/// assert_eq!(data.len() as u64, chain.n_entries().await?);
+///
+/// // Branches with a variable number of elements per entry
+/// let counter: Vec<u32> = chain.stream("n", |i| be_u32(i)).try_collect().await?;
+/// let arrays: Vec<Vec<i32>> = chain
+///     .var_size_stream("Ai4", |i| be_i32(i), &counter)
+///     .try_collect()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Chain {
+    tree_name: String,
+    sources: Vec<Source>,
+    /// The tree of each file, once it was opened
+    trees: Vec<Mutex<Option<Arc<Tree>>>>,
+}
+
+impl Chain {
+    /// A chain of the trees called `tree_name` in the given files
+    pub fn new<I, S>(tree_name: &str, sources: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<Source>,
+    {
+        let sources: Vec<Source> = sources.into_iter().map(Into::into).collect();
+        Self {
+            tree_name: tree_name.to_string(),
+            trees: sources.iter().map(|_| Mutex::new(None)).collect(),
+            sources,
+        }
+    }
+
+    /// The number of files in this chain
+    pub fn n_files(&self) -> usize {
+        self.sources.len()
+    }
+
+    /// The tree in the file with the given index. The file is opened
+    /// if that did not happen before, and its tree is checked against
+    /// the tree of the first file.
+    pub async fn tree(&self, file: usize) -> Result<Arc<Tree>, Error> {
+        self.tree_boxed(file).await
+    }
+
+    /// The body of `tree` behind a pointer, as it awaits itself
+    fn tree_boxed(&self, file: usize) -> SourceFuture<'_, Result<Arc<Tree>, Error>> {
+        boxed(async move {
+            let slot = self.trees.get(file).ok_or_else(|| {
+                Error::NotFound(format!(
+                    "File {} in a chain of {} files",
+                    file,
+                    self.n_files()
+                ))
+            })?;
+            if let Some(tree) = slot.lock().unwrap_or_else(|e| e.into_inner()).as_ref() {
+                return Ok(tree.clone());
+            }
+            let f = RootFile::new(self.sources[file].clone()).await?;
+            let tree = Arc::new(f.item_by_name(&self.tree_name)?.as_tree().await?);
+            if file > 0 {
+                let first = self.tree_boxed(0).await?;
+                if first.branch_names_and_types() != tree.branch_names_and_types() {
+                    return Err(Error::Incompatible(format!(
+                        "The branches of `{}` in file {} differ from those in file 0",
+                        self.tree_name, file
+                    )));
+                }
+            }
+            *slot.lock().unwrap_or_else(|e| e.into_inner()) = Some(tree.clone());
+            Ok(tree)
+        })
+    }
+
+    /// The first global entry of each file, followed by the total
+    /// number of entries. All files are opened.
+    pub async fn first_entries(&self) -> Result<Vec<u64>, Error> {
+        let mut first_entries = vec![0];
+        for file in 0..self.n_files() {
+            let n = self.tree(file).await?.n_entries();
+            first_entries.push(first_entries[file] + n);
+        }
+        Ok(first_entries)
+    }
+
+    /// The total number of entries in all files. All files are opened.
+    pub async fn n_entries(&self) -> Result<u64, Error> {
+        Ok(*self.first_entries().await?.last().expect("Not empty"))
+    }
+
+    /// Translate a global entry of this chain into the index of the
+    /// file containing it and the entry within that file. Only the
+    /// files up to that one are opened.
+    pub async fn locate(&self, entry: u64) -> Result<(usize, u64), Error> {
+        let mut first_entry = 0;
+        for file in 0..self.n_files() {
+            let n = self.tree(file).await?.n_entries();
+            if entry < first_entry + n {
+                return Ok((file, entry - first_entry));
+            }
+            first_entry += n;
+        }
+        Err(Error::NotFound(format!(
+            "Entry {} in a chain of {} entries",
+            entry, first_entry
+        )))
+    }
+
+    /// The tree in the file with the given index, and the global
+    /// number of its first entry. The files up to that one are opened.
+    async fn tree_and_first_entry(&self, file: usize) -> Result<(Arc<Tree>, u64), Error> {
+        let mut first_entry = 0;
+        for previous in 0..file {
+            first_entry += self.tree(previous).await?.n_entries();
+        }
+        Ok((self.tree(file).await?, first_entry))
+    }
+
+    /// Read a single entry of a branch with a constant number of
+    /// elements per entry; see `TBranch::entry`
+    pub async fn entry<T, P>(&self, branch: &str, entry: u64, p: P) -> Result<T, Error>
+    where
+        P: Fn(&[u8]) -> IResult<&[u8], T>,
+    {
+        let (file, local) = self.locate(entry).await?;
+        let tree = self.tree(file).await?;
+        tree.branch_by_name(branch)?.entry(local, p).await
+    }
+
+    /// Read a single entry of a branch with a variable number of
+    /// elements per entry; see `TBranch::var_size_entry`
+    pub async fn var_size_entry<T, P>(
+        &self,
+        branch: &str,
+        entry: u64,
+        p: P,
+        n_elems: u32,
+    ) -> Result<Vec<T>, Error>
+    where
+        P: Fn(&[u8]) -> IResult<&[u8], T>,
+    {
+        let (file, local) = self.locate(entry).await?;
+        let tree = self.tree(file).await?;
+        tree.branch_by_name(branch)?
+            .var_size_entry(local, p, n_elems)
+            .await
+    }
+
+    /// Stream over a branch with a constant number of elements per
+    /// entry in all files; see `TBranch::try_as_fixed_size_iterator`
+    pub fn stream<'a, T, P>(
+        &'a self,
+        branch: &str,
+        p: P,
+    ) -> impl Stream<Item = Result<T, Error>> + 'a
+    where
+        T: 'a,
+        P: Fn(&[u8]) -> IResult<&[u8], T> + Clone + 'a,
+    {
+        self.stream_with(branch, p, ReadOptions::default())
+    }
+
+    /// Same as `stream`, but the baskets are read as configured by
+    /// `options`; see `TBranch::stream_with`
+    pub fn stream_with<'a, T, P>(
+        &'a self,
+        branch: &str,
+        p: P,
+        options: ReadOptions,
+    ) -> impl Stream<Item = Result<T, Error>> + 'a
+    where
+        T: 'a,
+        P: Fn(&[u8]) -> IResult<&[u8], T> + Clone + 'a,
+    {
+        self.concat(branch, move |b, _| b.stream_with(p.clone(), options))
+    }
+
+    /// Stream over a branch with a variable number of elements per
+    /// entry in all files; see `TBranch::try_as_var_size_iterator`.
+    /// `el_counter` holds the number of elements of each entry of the
+    /// whole chain, e.g. as read from the counter branch with
+    /// `stream`.
+    pub fn var_size_stream<'a, T, P>(
+        &'a self,
+        branch: &str,
+        p: P,
+        el_counter: &'a [u32],
+    ) -> impl Stream<Item = Result<Vec<T>, Error>> + 'a
+    where
+        T: 'a,
+        P: Fn(&[u8]) -> IResult<&[u8], T> + Clone + 'a,
+    {
+        self.concat(branch, move |b, first_entry| {
+            let start = el_counter.len().min(first_entry as usize);
+            b.try_as_var_size_iterator(p.clone(), &el_counter[start..])
+        })
+    }
+
+    /// Stream over a jagged branch in all files; see
+    /// `TBranch::try_as_jagged_iterator`
+    pub fn jagged_stream<'a, T, P>(
+        &'a self,
+        branch: &str,
+        p: P,
+    ) -> impl Stream<Item = Result<Vec<T>, Error>> + 'a
+    where
+        T: 'a,
+        P: Fn(&[u8]) -> IResult<&[u8], T> + Clone + 'a,
+    {
+        self.concat(branch, move |b, _| b.try_as_jagged_iterator(p.clone()))
+    }
+
+    /// Stream over the entries of all files as read by `T`; see
+    /// `FromTree::stream_from_tree`. An error is yielded for each file
+    /// which cannot be opened and for each entry which cannot be
+    /// read.
+    pub fn stream_from_tree<'a, T>(&'a self) -> impl Stream<Item = Result<T, Error>> + 'a
+    where
+        T: FromTree + 'a,
+    {
+        stream::iter(0..self.n_files())
+            .then(move |file| async move {
+                let tree = self.tree(file).await?;
+                T::stream_from_tree(&tree).await
+            })
+            .map(|entries| match entries {
+                Ok(entries) => entries.left_stream(),
+                Err(e) => stream::once(future::ready(Err(e))).right_stream(),
+            })
+            .flatten()
+    }
+
+    /// Concatenate the streams returned by `entries` for `branch` in
+    /// each file. Besides the branch, `entries` receives the global
+    /// number of the first entry of its file. Each file is opened
+    /// once the stream reaches it; an error is yielded for each file
+    /// which cannot be opened or lacks the branch.
+    fn concat<'a, T, S, F>(
+        &'a self,
+        branch: &str,
+        entries: F,
+    ) -> impl Stream<Item = Result<T, Error>> + 'a
+    where
+        T: 'a,
+        S: Stream<Item = Result<T, Error>> + 'a,
+        F: Fn(&TBranch, u64) -> S + 'a,
+    {
+        let branch = branch.to_string();
+        stream::iter(0..self.n_files())
+            .then(move |file| self.tree_and_first_entry(file))
+            .map(move |tree| {
+                let entries = tree.and_then(|(tree, first_entry)| {
+                    Ok(entries(tree.branch_by_name(&branch)?, first_entry))
+                });
+                match entries {
+                    Ok(entries) => entries.left_stream(),
+                    Err(e) => stream::once(future::ready(Err(e))).right_stream(),
+                }
+            })
+            .flatten()
+    }
+}
@@ -0,0 +1,224 @@
+use std::ops::Range;
+
+use nom::IResult;
+
+use crate::tree_reader::branch::{branch_error, jagged_entry, parse_entries, TBranch};
+use crate::tree_reader::container::{raw_data_coalesced, BasketData};
+use crate::Error;
+
+/// The content of several branches for the entries of one cluster;
+/// see `Tree::read_clusters`
+#[derive(Debug)]
+pub struct Cluster {
+    entries: Range<u64>,
+    /// Name of each branch and its baskets overlapping this cluster
+    branches: Vec<(String, Vec<ClusterBasket>)>,
+}
+
+/// A basket read for a `Cluster`
+#[derive(Debug)]
+struct ClusterBasket {
+    /// Index of this basket in its branch
+    index: usize,
+    first_entry: u64,
+    /// Position of the basket in its file
+    offset: u64,
+    data: BasketData,
+}
+
+impl Cluster {
+    /// The entries of the tree in this cluster
+    pub fn entries(&self) -> Range<u64> {
+        self.entries.clone()
+    }
+
+    /// The entries of this cluster in a branch with a constant number
+    /// of elements per entry; see `TBranch::as_fixed_size_iterator`
+    pub fn fixed_size<T, P>(&self, branch: &str, p: P) -> Result<Vec<T>, Error>
+    where
+        P: Fn(&[u8]) -> IResult<&[u8], T>,
+    {
+        self.parse(branch, |_, _, i| Some(p(i)))
+    }
+
+    /// The entries of this cluster in a branch with a variable number
+    /// of elements per entry; see `TBranch::as_jagged_iterator`
+    pub fn jagged<T, P>(&self, branch: &str, p: P) -> Result<Vec<Vec<T>>, Error>
+    where
+        P: Fn(&[u8]) -> IResult<&[u8], T>,
+    {
+        self.parse(branch, |basket, k, i| {
+            let offsets = basket.entry_offsets.as_ref()?;
+            Some(jagged_entry(&p, i, offsets[k + 1] - offsets[k]))
+        })
+    }
+
+    /// Parse the entries of this cluster in the given branch. Besides
+    /// the input, `parse_entry` receives the basket and the index of
+    /// the entry within it.
+    fn parse<T, F>(&self, branch: &str, mut parse_entry: F) -> Result<Vec<T>, Error>
+    where
+        F: for<'a> FnMut(&BasketData, usize, &'a [u8]) -> Option<IResult<&'a [u8], T>>,
+    {
+        let baskets = self
+            .branches
+            .iter()
+            .find(|(name, _)| name == branch)
+            .map(|(_, baskets)| baskets)
+            .ok_or_else(|| {
+                Error::NotFound(format!("Branch `{}` was not read for this cluster", branch))
+            })?;
+        let end = self.entries.end;
+        let mut out = Vec::with_capacity((end - self.entries.start) as usize);
+        for basket in baskets {
+            let first_entry = basket.first_entry;
+            let (events, err) =
+                parse_entries(&basket.data, basket.offset, first_entry, |entry, i| {
+                    if entry < end {
+                        parse_entry(&basket.data, (entry - first_entry) as usize, i)
+                    } else {
+                        None
+                    }
+                });
+            if let Some((entry, e)) = err {
+                return Err(branch_error(branch, basket.index, entry, e));
+            }
+            let skip = self.entries.start.saturating_sub(first_entry) as usize;
+            out.extend(events.into_iter().skip(skip));
+        }
+        if out.len() as u64 != end - self.entries.start {
+            return Err(Error::NotFound(format!(
+                "Entries {:?} of branch `{}`; found only {}",
+                self.entries,
+                branch,
+                out.len()
+            )));
+        }
+        Ok(out)
+    }
+}
+
+/// Split `n_entries` into clusters following ROOT's
+/// `TTree::TClusterIterator`. `ranges` holds the last entry and the
+/// cluster size of each range of clusters (`fClusterRangeEnd` and
+/// `fClusterSize`). Where neither these nor a positive `autoflush`
+/// give the cluster size, the next cluster starts at the next entry
+/// in `basket_starts`.
+pub(crate) fn cluster_ranges(
+    n_entries: u64,
+    autoflush: i64,
+    ranges: &[(i64, i64)],
+    basket_starts: &[u64],
+) -> Vec<Range<u64>> {
+    let estimated = |start: u64| {
+        if autoflush > 0 {
+            start + autoflush as u64
+        } else {
+            basket_starts
+                .iter()
+                .copied()
+                .find(|e| *e > start)
+                .unwrap_or(n_entries)
+        }
+    };
+    let mut clusters = vec![];
+    let mut range = 0;
+    let mut start = 0;
+    while start < n_entries {
+        while range < ranges.len() && start as i64 > ranges[range].0 {
+            range += 1;
+        }
+        let next = match ranges.get(range) {
+            Some((end, size)) => {
+                let next = if *size > 0 {
+                    start + *size as u64
+                } else {
+                    estimated(start)
+                };
+                // The last cluster of a range may be smaller
+                next.min(*end as u64 + 1)
+            }
+            None => estimated(start),
+        };
+        let next = next.min(n_entries);
+        clusters.push(start..next);
+        start = next;
+    }
+    clusters
+}
+
+/// Read the baskets of `branches` holding the given entries. The
+/// baskets of all branches are fetched together, merging requests
+/// for baskets which lie next to each other in the file.
+pub(crate) async fn read_cluster(
+    branches: &[TBranch],
+    entries: Range<u64>,
+) -> Result<Cluster, Error> {
+    let selected: Vec<_> = branches
+        .iter()
+        .map(|b| b.baskets_for_entries(&entries))
+        .collect();
+    let containers = branches
+        .iter()
+        .zip(selected.iter())
+        .flat_map(|(b, baskets)| b.containers()[baskets.clone()].iter().cloned())
+        .collect();
+    let mut raw = raw_data_coalesced(containers).await?.into_iter();
+
+    let mut cluster = Cluster {
+        entries,
+        branches: vec![],
+    };
+    for (branch, baskets) in branches.iter().zip(selected) {
+        let first_entry_of = |basket: usize| branch.fbasketentry.get(basket).map(|e| *e as u64);
+        let mut next_entry = first_entry_of(baskets.start).unwrap_or(0);
+        let mut read = vec![];
+        for (index, data) in baskets.zip(&mut raw) {
+            let first_entry = first_entry_of(index).unwrap_or(next_entry);
+            let data = data.map_err(|e| branch_error(&branch.name, index, first_entry, e))?;
+            next_entry = first_entry + u64::from(data.n_entries);
+            let offset = branch.containers()[index].offset();
+            read.push(ClusterBasket {
+                index,
+                first_entry,
+                offset,
+                data,
+            });
+        }
+        cluster.branches.push((branch.name(), read));
+    }
+    Ok(cluster)
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clusters_from_autoflush() {
+        assert_eq!(
+            cluster_ranges(25, 10, &[], &[]),
+            vec![0..10, 10..20, 20..25]
+        );
+        assert_eq!(cluster_ranges(0, 10, &[], &[]), vec![]);
+    }
+
+    #[test]
+    fn clusters_from_ranges() {
+        // Clusters of 5 entries up to entry 9, then of `autoflush`
+        // entries up to entry 29, then again of `autoflush` entries
+        assert_eq!(
+            cluster_ranges(40, 7, &[(9, 5), (29, 0)], &[]),
+            vec![0..5, 5..10, 10..17, 17..24, 24..30, 30..37, 37..40]
+        );
+    }
+
+    #[test]
+    fn clusters_from_baskets() {
+        assert_eq!(
+            cluster_ranges(20, -30_000_000, &[], &[0, 6, 17]),
+            vec![0..6, 6..17, 17..20]
+        );
+        assert_eq!(cluster_ranges(20, -30_000_000, &[], &[]), vec![0..20]);
+    }
+}
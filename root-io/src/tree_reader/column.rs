@@ -0,0 +1,159 @@
+use std::mem::size_of;
+
+use crate::tree_reader::container::BasketData;
+use crate::tree_reader::value::ValueLayout;
+use crate::Error;
+
+/// Primitive types which can be decoded in bulk from the big-endian
+/// data of a basket; see `TBranch::read_basket_columns`
+pub trait Primitive: Sized + Copy {
+    /// Name of this type; it has to match the type of the leaf
+    const TYPE_NAME: &'static str;
+
+    /// Decode all values in `bytes`. Its length is a multiple of the
+    /// size of `Self`.
+    fn from_be_slice(bytes: &[u8]) -> Vec<Self>;
+}
+
+macro_rules! primitive_number {
+    ($($t:ty),*) => {
+        $(
+            impl Primitive for $t {
+                const TYPE_NAME: &'static str = stringify!($t);
+
+                fn from_be_slice(bytes: &[u8]) -> Vec<Self> {
+                    // Simple enough for the compiler to vectorize the byte swaps
+                    bytes
+                        .chunks_exact(size_of::<Self>())
+                        .map(|chunk| {
+                            let mut buf = [0; size_of::<$t>()];
+                            buf.copy_from_slice(chunk);
+                            Self::from_be_bytes(buf)
+                        })
+                        .collect()
+                }
+            }
+        )*
+    };
+}
+
+primitive_number!(i8, i16, i32, i64, u8, u16, u32, u64, f32, f64);
+
+impl Primitive for bool {
+    const TYPE_NAME: &'static str = "bool";
+
+    fn from_be_slice(bytes: &[u8]) -> Vec<Self> {
+        bytes.iter().map(|b| *b != 0).collect()
+    }
+}
+
+/// The elements of all entries of one basket
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasketColumn<T> {
+    /// Number of the first entry of this basket in the tree
+    pub first_entry: u64,
+    /// Number of entries in this basket
+    pub n_entries: usize,
+    /// The elements of all entries stored one after another. For
+    /// fixed size arrays, each entry consists of several elements.
+    pub values: Vec<T>,
+    /// For jagged branches, the elements of entry `i` are
+    /// `values[offsets[i]..offsets[i + 1]]`. `None` for branches with
+    /// a fixed number of elements per entry.
+    pub offsets: Option<Vec<usize>>,
+}
+
+impl<T> BasketColumn<T> {
+    /// The elements of the `i`th entry of this basket (counting from
+    /// the first entry of the basket)
+    pub fn entry(&self, i: usize) -> Option<&[T]> {
+        if i >= self.n_entries {
+            return None;
+        }
+        match self.offsets {
+            Some(ref offsets) => self.values.get(offsets[i]..offsets[i + 1]),
+            None => {
+                let len = self.values.len() / self.n_entries;
+                self.values.get(i * len..(i + 1) * len)
+            }
+        }
+    }
+}
+
+/// Decode the content of a basket starting at byte `offset` of the
+/// file. The type `T` has already been checked against `layout`.
+pub(crate) fn basket_column<T: Primitive>(
+    layout: &ValueLayout,
+    basket: &BasketData,
+    offset: u64,
+    first_entry: u64,
+) -> Result<BasketColumn<T>, Error> {
+    let size = size_of::<T>();
+    let n_entries = basket.n_entries as usize;
+    let invalid = |msg: String| Error::Parse {
+        class: "TBasket".to_string(),
+        offset,
+        msg,
+    };
+    let (len, offsets) = if layout.jagged {
+        let offsets = basket.entry_offsets.as_ref().ok_or_else(|| {
+            Error::Unsupported("Jagged iteration over a basket without entry offsets".to_string())
+        })?;
+        if let Some(o) = offsets.iter().find(|o| *o % size != 0) {
+            return Err(invalid(format!(
+                "Entry offset {} is not a multiple of the element size {}",
+                o, size
+            )));
+        }
+        let offsets: Vec<_> = offsets.iter().map(|o| o / size).collect();
+        (offsets[n_entries] * size, Some(offsets))
+    } else {
+        (n_entries * layout.len * size, None)
+    };
+    // If something is left over in the buffer its just junk
+    let data = basket.data.get(..len).ok_or_else(|| {
+        invalid(format!(
+            "Expected {} bytes of data, found {}",
+            len,
+            basket.data.len()
+        ))
+    })?;
+    Ok(BasketColumn {
+        first_entry,
+        n_entries,
+        values: T::from_be_slice(data),
+        offsets,
+    })
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn byte_swap() {
+        assert_eq!(i16::from_be_slice(&[0, 1, 255, 254]), vec![1, -2]);
+        assert_eq!(f32::from_be_slice(&[63, 192, 0, 0]), vec![1.5]);
+        assert_eq!(bool::from_be_slice(&[0, 1, 2]), vec![false, true, true]);
+    }
+
+    #[test]
+    fn entries_of_column() {
+        let fixed = BasketColumn {
+            first_entry: 0,
+            n_entries: 2,
+            values: vec![1, 2, 3, 4],
+            offsets: None,
+        };
+        assert_eq!(fixed.entry(1), Some(&[3, 4][..]));
+        assert_eq!(fixed.entry(2), None);
+        let jagged = BasketColumn {
+            offsets: Some(vec![0, 0, 3, 4]),
+            n_entries: 3,
+            ..fixed
+        };
+        assert_eq!(jagged.entry(0), Some(&[][..]));
+        assert_eq!(jagged.entry(1), Some(&[1, 2, 3][..]));
+        assert_eq!(jagged.entry(2), Some(&[4][..]));
+    }
+}
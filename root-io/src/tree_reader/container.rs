@@ -1,9 +1,16 @@
-use failure::Error;
-use nom::combinator::rest;
+use std::borrow::Cow;
+#[cfg(not(target_arch = "wasm32"))]
+use std::io;
+use std::ops::Range;
+
+use futures::future;
+#[cfg(not(target_arch = "wasm32"))]
+use futures::{executor::ThreadPool, task::SpawnExt};
 use nom::number::complete::*;
 use nom::*;
 
 use crate::core::*;
+use crate::Error;
 
 #[derive(Debug, Clone)]
 pub(crate) enum Container {
@@ -11,18 +18,83 @@ pub(crate) enum Container {
     InMemory(Vec<u8>),
     /// Filename, start byte, and len of a `TBasket` on disk
     OnDisk(Source, u64, u64),
+    /// Source of the file referring to the basket, name of the
+    /// other file (relative to the first one), start byte, and len of
+    /// a `TBasket` stored in that other file
+    External(Source, String, u64, u64),
+}
+
+/// The decompressed content of a `TBasket`
+#[derive(Debug, PartialEq)]
+pub(crate) struct BasketData {
+    /// Number of entries in this basket
+    pub(crate) n_entries: u32,
+    /// The data of all entries; borrowed from the file if it is
+    /// uncompressed and kept in memory
+    pub(crate) data: Buffer,
+    /// Start of each entry in `data` followed by the end of the last
+    /// entry. Only available for branches with a variable size per entry.
+    pub(crate) entry_offsets: Option<Vec<usize>>,
 }
 
 impl Container {
-    /// Return the number of entries and the data; reading it from disk if necessary
-    pub(crate) async fn raw_data(self) -> Result<(u32, Vec<u8>), Error> {
-        let buf = match self {
-            Container::InMemory(buf) => buf,
-            Container::OnDisk(source, seek, len) => source.fetch(seek, len).await?,
-        };
-        match tbasket2vec(buf.as_slice()) {
-            Ok((_, v)) => Ok(v),
-            _ => Err(format_err!("tbasket2vec parser failed")),
+    /// Return the content of this basket; reading it from disk if necessary
+    pub(crate) async fn raw_data(self) -> Result<BasketData, Error> {
+        let seek = self.offset();
+        let buf = self.fetch().await?;
+        tbasket_from_buffer(&buf, seek)
+    }
+
+    /// Same as `raw_data`, but the basket is decompressed on `pool`
+    /// (if given) rather than in the calling task
+    #[cfg(not(target_arch = "wasm32"))]
+    // `io::Error::other` is not available on older toolchains
+    #[allow(clippy::io_other_error)]
+    pub(crate) async fn raw_data_on(self, pool: Option<ThreadPool>) -> Result<BasketData, Error> {
+        let seek = self.offset();
+        let buf = self.fetch().await?;
+        let decompress = async move { tbasket_from_buffer(&buf, seek) };
+        match pool {
+            Some(pool) => {
+                pool.spawn_with_handle(decompress)
+                    .map_err(|e| {
+                        let msg = format!("Spawning the decompression of a basket: {}", e);
+                        Error::Io(io::Error::new(io::ErrorKind::Other, msg))
+                    })?
+                    .await
+            }
+            None => decompress.await,
+        }
+    }
+
+    /// The (possibly compressed) `TBasket` including its key
+    async fn fetch(self) -> Result<Buffer, Error> {
+        match self {
+            Container::InMemory(buf) => Ok(Buffer::Owned(buf)),
+            Container::OnDisk(source, seek, len) => source.fetch_buffer(seek, len).await,
+            Container::External(source, file_name, seek, len) => {
+                source.sibling(&file_name)?.fetch_buffer(seek, len).await
+            }
+        }
+    }
+
+    /// The source holding this basket, and its position and length
+    /// in that source; `None` if it is kept in memory
+    fn location(&self) -> Result<Option<(Source, u64, u64)>, Error> {
+        match self {
+            Container::InMemory(_) => Ok(None),
+            Container::OnDisk(source, seek, len) => Ok(Some((source.clone(), *seek, *len))),
+            Container::External(source, file_name, seek, len) => {
+                Ok(Some((source.sibling(file_name)?, *seek, *len)))
+            }
+        }
+    }
+
+    /// The position of this container in its file; zero if it is kept in memory
+    pub(crate) fn offset(&self) -> u64 {
+        match self {
+            Container::InMemory(_) => 0,
+            Container::OnDisk(_, seek, _) | Container::External(_, _, seek, _) => *seek,
         }
     }
     // /// For debugging: Try to find the file of this container. Out of luck if the container was inlined
@@ -35,10 +107,103 @@ impl Container {
     // }
 }
 
-/// Return a tuple indicating the number of elements in this basket
-/// and the content as a Vec<u8>
-fn tbasket2vec(input: &[u8]) -> IResult<&[u8], (u32, Vec<u8>)>
-{
+/// Baskets which are at most this many bytes apart are fetched with
+/// a single request by `raw_data_coalesced`
+const COALESCE_GAP: u64 = 32 * 1024;
+
+/// A range of bytes holding several baskets
+struct Span {
+    start: u64,
+    end: u64,
+    /// Index, position, and length of each basket in the span
+    baskets: Vec<(usize, u64, u64)>,
+}
+
+/// Return the content of several baskets, in the order of
+/// `containers`. The baskets are grouped by the file holding them,
+/// e.g. the file of a friend tree or an external file. In each
+/// file, baskets which lie close to each other are merged into
+/// spans, which are then fetched with a single call of
+/// `Source::fetch_many`; the files are read concurrently. The outer
+/// error is returned if fetching failed, the inner ones if a basket
+/// could not be read.
+pub(crate) async fn raw_data_coalesced(
+    containers: Vec<Container>,
+) -> Result<Vec<Result<BasketData, Error>>, Error> {
+    let mut out: Vec<_> = containers.iter().map(|_| None).collect();
+    // Index, position, and length of the baskets in each file
+    let mut groups: Vec<(Source, Vec<_>)> = vec![];
+    for (i, container) in containers.into_iter().enumerate() {
+        match container.location() {
+            Ok(Some((source, seek, len))) if !source.in_memory() => {
+                match groups.iter_mut().find(|(s, _)| s.same_as(&source)) {
+                    Some((_, baskets)) => baskets.push((i, seek, len)),
+                    None => groups.push((source, vec![(i, seek, len)])),
+                }
+            }
+            Ok(_) => out[i] = Some(container.raw_data().await),
+            Err(e) => out[i] = Some(Err(e)),
+        }
+    }
+    let fetched = future::try_join_all(
+        groups
+            .into_iter()
+            .map(|(source, baskets)| fetch_coalesced(source, baskets)),
+    )
+    .await?;
+    for (i, basket) in fetched.into_iter().flatten() {
+        out[i] = Some(basket);
+    }
+    Ok(out
+        .into_iter()
+        .map(|basket| basket.expect("Every basket was read"))
+        .collect())
+}
+
+/// Fetch the given baskets of `source`, given by their index,
+/// position, and length, merging those close to each other into
+/// spans. Return the content of each basket with its index.
+async fn fetch_coalesced(
+    source: Source,
+    mut baskets: Vec<(usize, u64, u64)>,
+) -> Result<Vec<(usize, Result<BasketData, Error>)>, Error> {
+    baskets.sort_by_key(|(_, seek, _)| *seek);
+    let mut spans: Vec<Span> = vec![];
+    for (i, seek, len) in baskets {
+        match spans.last_mut() {
+            Some(span) if seek <= span.end + COALESCE_GAP => {
+                span.end = span.end.max(seek + len);
+                span.baskets.push((i, seek, len));
+            }
+            _ => spans.push(Span {
+                start: seek,
+                end: seek + len,
+                baskets: vec![(i, seek, len)],
+            }),
+        }
+    }
+    let ranges: Vec<_> = spans.iter().map(|s| (s.start, s.end - s.start)).collect();
+    let bufs = source.fetch_many(&ranges).await?;
+    Ok(spans
+        .iter()
+        .zip(bufs)
+        .flat_map(|(span, buf)| {
+            span.baskets
+                .iter()
+                .map(|(i, seek, len)| {
+                    let begin = (seek - span.start) as usize;
+                    (*i, tbasket2vec(&buf[begin..begin + *len as usize], *seek))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect())
+}
+
+/// Parse the header of a `TBasket`. Returns the key header, the
+/// number of entries, and the end of the useful data (relative to
+/// the start of the key). The remaining input is the (possibly
+/// compressed) content.
+fn tbasket_header(input: &[u8]) -> IResult<&[u8], (TKeyHeader, u32, u32)> {
     do_parse!(input,
               hdr: tkey_header >>
               _ver: be_u16 >>
@@ -47,19 +212,103 @@ fn tbasket2vec(input: &[u8]) -> IResult<&[u8], (u32, Vec<u8>)>
 	      n_entry_buf: be_u32 >>
 	      last: be_u32 >>
 	      _flag: be_i8 >>
-              buf: rest >>
-              ({
-                  let buf = if hdr.uncomp_len as usize > buf.len() {
-                      decompress(buf).unwrap().1
-                  } else {
-                      buf.to_vec()
-                  };
-                  // Not the whole buffer is filled, no, no, no, that
-                  // would be to easy! Its only filled up to `last`,
-                  // whereby we have to take the key_len into account...
-                  let useful_bytes = (last - hdr.key_len as u32) as usize;
-                  (n_entry_buf, buf.as_slice()[..useful_bytes].to_vec())
-              }))
+              ((hdr, n_entry_buf, last)))
+}
+
+/// Parse the `fEntryOffset` array which is stored after the data of
+/// a basket of a branch with a variable size per entry. It consists
+/// of its length followed by the start of each entry relative to the
+/// beginning of the basket's key. Newer versions of ROOT append the
+/// end of the last entry, which is dropped here.
+fn entry_offsets(input: &[u8], n_entries: u32) -> IResult<&[u8], Vec<u32>> {
+    let (input, mut offsets) = length_count!(
+        input,
+        verify!(be_u32, |n| *n == n_entries || *n == n_entries + 1),
+        be_u32
+    )?;
+    offsets.truncate(n_entries as usize);
+    Ok((input, offsets))
+}
+
+/// Return the content of the basket at `input`. `offset` is the
+/// position of the basket in its file and only used for error
+/// reporting.
+fn tbasket2vec(input: &[u8], offset: u64) -> Result<BasketData, Error> {
+    tbasket_data(input, offset, |range| Buffer::Owned(input[range].to_vec()))
+}
+
+/// Same as `tbasket2vec`, but the content of uncompressed baskets
+/// is borrowed from `input` if possible
+fn tbasket_from_buffer(input: &Buffer, offset: u64) -> Result<BasketData, Error> {
+    tbasket_data(input, offset, |range| input.slice(range))
+}
+
+/// Return the content of the basket at `input`. If the basket is
+/// not compressed, its data is taken from `input` with
+/// `uncompressed`, which is given the range of the data in `input`.
+fn tbasket_data<F>(input: &[u8], offset: u64, uncompressed: F) -> Result<BasketData, Error>
+where
+    F: FnOnce(Range<usize>) -> Buffer,
+{
+    let (buf, (hdr, n_entry_buf, last)) =
+        tbasket_header(input).map_err(|e| Error::parse("TBasket", offset, input, e))?;
+    let content_start = input.len() - buf.len();
+    let buf: Cow<[u8]> = if hdr.uncomp_len as usize > buf.len() {
+        Cow::Owned(decompress(buf, hdr.uncomp_len as usize)?)
+    } else {
+        Cow::Borrowed(buf)
+    };
+    let invalid = |msg| Error::Parse {
+        class: "TBasket".to_string(),
+        offset,
+        msg,
+    };
+    // Not the whole buffer is filled, no, no, no, that
+    // would be to easy! Its only filled up to `last`,
+    // whereby we have to take the key_len into account...
+    let key_len = hdr.key_len as u32;
+    let useful_bytes = last
+        .checked_sub(key_len)
+        .map(|n| n as usize)
+        .filter(|n| *n <= buf.len())
+        .ok_or_else(|| {
+            invalid(format!(
+                "Invalid end of data {} in buffer of {} bytes",
+                last,
+                buf.len()
+            ))
+        })?;
+    // Baskets of branches with a variable size per entry store the
+    // entry offsets after the data
+    let tail = &buf[useful_bytes..];
+    let entry_offsets = if tail.is_empty() {
+        None
+    } else {
+        let (_, offsets) = entry_offsets(tail, n_entry_buf)
+            .map_err(|e| Error::parse("TBasket", offset, &buf, e))?;
+        let mut offsets = offsets
+            .into_iter()
+            .map(|o| o.checked_sub(key_len).map(|o| o as usize))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| invalid("Entry offset lies within the key".to_string()))?;
+        offsets.push(useful_bytes);
+        if offsets.first() != Some(&0) || offsets.windows(2).any(|w| w[0] > w[1]) {
+            return Err(invalid(format!("Invalid entry offsets {:?}", offsets)));
+        }
+        Some(offsets)
+    };
+    let data = match buf {
+        Cow::Borrowed(_) => uncompressed(content_start..content_start + useful_bytes),
+        Cow::Owned(mut buf) => {
+            buf.truncate(useful_bytes);
+            Buffer::Owned(buf)
+        }
+    };
+    Ok(BasketData {
+        n_entries: n_entry_buf,
+        data,
+        entry_offsets,
+    })
 }
 
 #[cfg(test)]
@@ -86,7 +335,93 @@ mod tests {
         println!("{}", buf.to_hex(16));
         println!("{:?}", tkey_header(&buf));
         // println!("{:#?}", tbasket(&buf, be_u32));
-        println!("{:#?}", tbasket2vec(&buf));
+        let basket = tbasket2vec(&buf, 218).unwrap();
+        assert_eq!(basket.n_entries, 4);
+        assert_eq!(basket.data.len(), 16);
+        assert!(basket.entry_offsets.is_none());
+    }
+
+    #[tokio::test]
+    async fn basket_entry_offsets() {
+        use crate::core::RootFile;
+        use futures::StreamExt;
+        use nom::number::complete::be_u32;
+        use std::path::Path;
+
+        let path = Path::new("./src/test_data/sample-5.30.00-zlib.root");
+        let f = RootFile::new(path).await.unwrap();
+        let tree = f.items()[0].as_tree().await.unwrap();
+        let counter: Vec<u32> = tree
+            .branch_by_name("n")
+            .unwrap()
+            .as_fixed_size_iterator(|i| be_u32(i))
+            .collect()
+            .await;
+        let branch = tree.branch_by_name("Ai4").unwrap();
+        let mut entry = 0;
+        for container in branch.containers() {
+            let basket = container.clone().raw_data().await.unwrap();
+            let offsets = basket.entry_offsets.unwrap();
+            // The start of each entry and the end of the last one
+            assert_eq!(offsets.len(), basket.n_entries as usize + 1);
+            assert_eq!(offsets[0], 0);
+            assert_eq!(*offsets.last().unwrap(), basket.data.len());
+            for w in offsets.windows(2) {
+                // Each entry holds `n` 4-byte integers
+                assert_eq!(w[1] - w[0], 4 * counter[entry] as usize);
+                entry += 1;
+            }
+        }
+        assert_eq!(entry, counter.len());
+    }
+
+    #[tokio::test]
+    async fn external_basket() {
+        use super::Container;
+        use crate::core::Source;
+        use std::path::Path;
+
+        let referring = Source::new(Path::new("./src/test_data/foriter.root"));
+        let external = Container::External(referring.clone(), "simple.root".to_string(), 218, 86);
+        // The referenced file is only opened once
+        let other = Container::External(referring, "simple.root".to_string(), 100, 10);
+        let source = |c: &Container| c.location().unwrap().unwrap().0;
+        assert!(source(&external).same_as(&source(&other)));
+        let on_disk = Container::OnDisk(
+            Source::new(Path::new("./src/test_data/simple.root")),
+            218,
+            86,
+        );
+        assert_eq!(
+            external.raw_data().await.unwrap(),
+            on_disk.raw_data().await.unwrap()
+        );
+
+        let missing = Container::External(Source::new(vec![]), "simple.root".to_string(), 218, 86);
+        assert!(missing.raw_data().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn coalesced_baskets_of_several_files() {
+        use super::{raw_data_coalesced, Container};
+        use crate::core::Source;
+        use std::path::Path;
+
+        let simple = Source::new(Path::new("./src/test_data/simple.root"));
+        let referring = Source::new(Path::new("./src/test_data/foriter.root"));
+        let containers = vec![
+            Container::OnDisk(simple.clone(), 218, 86),
+            Container::External(referring, "simple.root".to_string(), 218, 86),
+            Container::External(Source::new(vec![]), "simple.root".to_string(), 218, 86),
+            Container::OnDisk(simple, 218, 86),
+        ];
+        let expected = containers[0].clone().raw_data().await.unwrap();
+        let baskets = raw_data_coalesced(containers).await.unwrap();
+        assert_eq!(baskets.len(), 4);
+        assert!(baskets[2].is_err());
+        for i in &[0, 1, 3] {
+            assert_eq!(baskets[*i].as_ref().unwrap(), &expected);
+        }
     }
 
     // /// Test the first basket of the "Tracks.fP[5]" branch
@@ -0,0 +1,204 @@
+use std::convert::TryFrom;
+
+use futures::stream;
+use nom::multi::count;
+use nom::number::complete::*;
+use nom::IResult;
+
+use crate::core::parsers::string;
+use crate::core::SourceFuture;
+use crate::tree_reader::Tree;
+use crate::Error;
+
+/// Types which can be parsed from the data of a single element of a
+/// branch. This provides the default parsers used by
+/// `#[derive(FromTree)]`.
+pub trait FromBranch: Sized {
+    /// Parse one element from the beginning of `input`
+    fn parse(input: &[u8]) -> IResult<&[u8], Self>;
+}
+
+/// The stream of entries returned by `FromTree::stream_from_tree`.
+/// Like a `SourceFuture`, it is `Send` on all targets but `wasm32`.
+#[cfg(not(target_arch = "wasm32"))]
+pub type EntryStream<'a, T> = stream::BoxStream<'a, Result<T, Error>>;
+/// The stream of entries returned by `FromTree::stream_from_tree`
+#[cfg(target_arch = "wasm32")]
+pub type EntryStream<'a, T> = stream::LocalBoxStream<'a, Result<T, Error>>;
+
+/// Types whose fields are read from the branches of a `Tree`. This
+/// trait is usually implemented with `#[derive(FromTree)]`; see
+/// `root_io_derive` for the available attributes.
+pub trait FromTree: Sized {
+    /// Stream over all entries of `tree`. Counter branches are read
+    /// eagerly. As with `TBranch::try_as_fixed_size_iterator`, an
+    /// error is yielded for each entry which cannot be read, e.g.
+    /// because its basket is corrupt, and the stream continues with
+    /// the next basket.
+    fn stream_from_tree(tree: &Tree)
+        -> SourceFuture<'_, Result<EntryStream<'static, Self>, Error>>;
+}
+
+macro_rules! from_branch_number {
+    ($($t:ty => $p:ident),*) => {
+        $(
+            impl FromBranch for $t {
+                fn parse(input: &[u8]) -> IResult<&[u8], Self> {
+                    $p(input)
+                }
+            }
+        )*
+    };
+}
+
+from_branch_number!(
+    i8 => be_i8, i16 => be_i16, i32 => be_i32, i64 => be_i64,
+    u8 => be_u8, u16 => be_u16, u32 => be_u32, u64 => be_u64,
+    f32 => be_f32, f64 => be_f64
+);
+
+impl FromBranch for bool {
+    fn parse(input: &[u8]) -> IResult<&[u8], Self> {
+        be_u8(input).map(|(i, b)| (i, b != 0))
+    }
+}
+
+impl FromBranch for String {
+    fn parse(input: &[u8]) -> IResult<&[u8], Self> {
+        string(input)
+    }
+}
+
+impl<T: FromBranch, const N: usize> FromBranch for [T; N] {
+    fn parse(input: &[u8]) -> IResult<&[u8], Self> {
+        let (input, v) = count(T::parse, N)(input)?;
+        let arr = <[T; N]>::try_from(v).unwrap_or_else(|_| unreachable!("Parsed {} elements", N));
+        Ok((input, arr))
+    }
+}
+
+macro_rules! from_branch_tuple {
+    ($($t:ident),*) => {
+        impl<$($t: FromBranch),*> FromBranch for ($($t,)*) {
+            #[allow(non_snake_case)]
+            fn parse(input: &[u8]) -> IResult<&[u8], Self> {
+                $(let (input, $t) = $t::parse(input)?;)*
+                Ok((input, ($($t,)*)))
+            }
+        }
+    };
+}
+
+from_branch_tuple!(A, B);
+from_branch_tuple!(A, B, C);
+from_branch_tuple!(A, B, C, D);
+from_branch_tuple!(A, B, C, D, E);
+from_branch_tuple!(A, B, C, D, E, F);
+
+/// Helpers used by the code generated by `#[derive(FromTree)]`
+#[doc(hidden)]
+pub mod __private {
+    use futures::prelude::*;
+    use nom::number::complete::be_u32;
+    use nom::IResult;
+
+    pub use super::EntryStream;
+    pub use crate::core::SourceFuture;
+
+    use crate::tree_reader::Tree;
+    use crate::Error;
+
+    /// `Send` on all targets but `wasm32`, like `EntryStream`
+    #[cfg(not(target_arch = "wasm32"))]
+    pub trait MaybeSend: Send {}
+    #[cfg(not(target_arch = "wasm32"))]
+    impl<T: Send> MaybeSend for T {}
+    /// `Send` on all targets but `wasm32`, like `EntryStream`
+    #[cfg(target_arch = "wasm32")]
+    pub trait MaybeSend {}
+    #[cfg(target_arch = "wasm32")]
+    impl<T> MaybeSend for T {}
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn boxed<T, S>(s: S) -> EntryStream<'static, T>
+    where
+        S: Stream<Item = Result<T, Error>> + MaybeSend + 'static,
+    {
+        s.boxed()
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn boxed<T, S>(s: S) -> EntryStream<'static, T>
+    where
+        S: Stream<Item = Result<T, Error>> + MaybeSend + 'static,
+    {
+        s.boxed_local()
+    }
+
+    pub fn fixed<T, P>(tree: &Tree, name: &str, p: P) -> Result<EntryStream<'static, T>, Error>
+    where
+        T: MaybeSend + 'static,
+        P: Fn(&[u8]) -> IResult<&[u8], T> + MaybeSend + 'static,
+    {
+        Ok(boxed(
+            tree.branch_by_name(name)?.try_as_fixed_size_iterator(p),
+        ))
+    }
+
+    pub fn jagged<T, P>(
+        tree: &Tree,
+        name: &str,
+        p: P,
+    ) -> Result<EntryStream<'static, Vec<T>>, Error>
+    where
+        T: MaybeSend + 'static,
+        P: Fn(&[u8]) -> IResult<&[u8], T> + MaybeSend + 'static,
+    {
+        Ok(boxed(tree.branch_by_name(name)?.try_as_jagged_iterator(p)))
+    }
+
+    pub fn counted<T, P>(
+        tree: &Tree,
+        name: &str,
+        p: P,
+        counter: &[u32],
+    ) -> Result<EntryStream<'static, Vec<T>>, Error>
+    where
+        T: MaybeSend + 'static,
+        P: Fn(&[u8]) -> IResult<&[u8], T> + MaybeSend + 'static,
+    {
+        Ok(boxed(
+            tree.branch_by_name(name)?
+                .try_as_var_size_iterator(p, counter),
+        ))
+    }
+
+    pub async fn counter(tree: &Tree, name: &str) -> Result<Vec<u32>, Error> {
+        tree.branch_by_name(name)?
+            .try_as_fixed_size_iterator(|i| be_u32(i))
+            .try_collect()
+            .await
+    }
+
+    pub fn zip<A, B>(
+        a: EntryStream<'static, A>,
+        b: EntryStream<'static, B>,
+    ) -> EntryStream<'static, (A, B)>
+    where
+        A: MaybeSend + 'static,
+        B: MaybeSend + 'static,
+    {
+        // Both columns yield an item for every entry, even if it
+        // cannot be read, so they stay aligned
+        boxed(a.zip(b).map(|(a, b)| Ok((a?, b?))))
+    }
+
+    pub fn map<A, T, F>(s: EntryStream<'static, A>, mut f: F) -> EntryStream<'static, T>
+    where
+        A: MaybeSend + 'static,
+        T: MaybeSend + 'static,
+        F: FnMut(A) -> T + MaybeSend + 'static,
+    {
+        boxed(s.map(move |entry| entry.map(&mut f)))
+    }
+}
@@ -0,0 +1,164 @@
+use nom::multi::count;
+use nom::number::complete::*;
+use nom::*;
+
+use crate::core::parsers::*;
+
+/// An index of the entries of a tree by a major and a minor value
+/// (ROOT's `TTreeIndex`, created with `TTree::BuildIndex`), usually
+/// a run and an event number; see `Tree::entry_for_index`
+#[derive(Debug, Clone)]
+pub struct TreeIndex {
+    /// Expression of the major values
+    major_name: String,
+    /// Expression of the minor values
+    minor_name: String,
+    /// Major and minor value of each indexed entry, sorted
+    values: Vec<(i64, i64)>,
+    /// Entry number for each element of `values`
+    entries: Vec<i64>,
+}
+
+impl TreeIndex {
+    /// The expression of the major values, e.g. a run number
+    pub fn major_name(&self) -> &str {
+        &self.major_name
+    }
+
+    /// The expression of the minor values, e.g. an event number
+    pub fn minor_name(&self) -> &str {
+        &self.minor_name
+    }
+
+    /// The number of indexed entries
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// True if no entry is indexed
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The entry with the given major and minor value. If several
+    /// entries have these values, the first one in the index is
+    /// returned, as done by ROOT.
+    pub fn entry(&self, major: i64, minor: i64) -> Option<u64> {
+        let i = self.values.partition_point(|v| *v < (major, minor));
+        if self.values.get(i) == Some(&(major, minor)) {
+            // Indices written without entry numbers have no `fIndex`
+            self.entries.get(i).map(|entry| *entry as u64)
+        } else {
+            None
+        }
+    }
+}
+
+/// Parse an array of `n` `i64`s stored behind a pointer, which is
+/// preceded by a flag telling if the pointer was null
+fn pointer_to_i64s(input: &[u8], n: usize) -> IResult<&[u8], Vec<i64>> {
+    let (input, is_array) = be_u8(input)?;
+    if is_array == 0 {
+        return Ok((input, vec![]));
+    }
+    count(be_i64, n)(input)
+}
+
+/// Parse a `TTreeIndex`
+pub(crate) fn ttreeindex(input: &[u8]) -> IResult<&[u8], TreeIndex> {
+    let tvirtualindex = |i| preceded!(i, be_u16, length_value!(checked_byte_count, tnamed));
+    let (input, (ver, major_name, minor_name, n)) = do_parse!(
+        input,
+        ver: verify!(be_u16, |v| *v == 1 || *v == 2)
+            >> _tvirtualindex: length_value!(checked_byte_count, tvirtualindex)
+            >> major_name: string
+            >> minor_name: string
+            >> n: be_i64
+            >> ((ver, major_name, minor_name, n.max(0) as usize))
+    )?;
+    let (input, majors) = pointer_to_i64s(input, n)?;
+    let (input, values) = if ver >= 2 {
+        let (input, minors) = pointer_to_i64s(input, n)?;
+        (input, majors.into_iter().zip(minors).collect())
+    } else {
+        // Version 1 stores both values in one as `major << 31 + minor`
+        let values = majors
+            .into_iter()
+            .map(|v| (v >> 31, v & 0x7fff_ffff))
+            .collect();
+        (input, values)
+    };
+    let (input, entries) = pointer_to_i64s(input, n)?;
+    Ok((
+        input,
+        TreeIndex {
+            major_name,
+            minor_name,
+            values,
+            entries,
+        },
+    ))
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+    use crate::test_utils::{tnamed, tstring, with_byte_count};
+
+    fn i64s(values: &[i64]) -> Vec<u8> {
+        let mut out = vec![1];
+        out.extend(values.iter().flat_map(|v| v.to_be_bytes().to_vec()));
+        out
+    }
+
+    /// A serialized `TTreeIndex` of the given version and arrays
+    fn tree_index(ver: u16, arrays: &[&[i64]]) -> Vec<u8> {
+        let mut tvirtualindex = vec![0, 1];
+        tvirtualindex.extend(tnamed("", ""));
+        let mut buf = ver.to_be_bytes().to_vec();
+        buf.extend(with_byte_count(tvirtualindex));
+        buf.extend(tstring("fRunNumber"));
+        buf.extend(tstring("fEventNumber"));
+        buf.extend(&(arrays[0].len() as i64).to_be_bytes());
+        for array in arrays {
+            buf.extend(i64s(array));
+        }
+        buf
+    }
+
+    #[test]
+    fn lookup() {
+        let buf = tree_index(2, &[&[100, 100, 100, 101], &[3, 7, 7, 1], &[2, 0, 3, 1]]);
+        let (rest, index) = ttreeindex(&buf).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(index.major_name(), "fRunNumber");
+        assert_eq!(index.minor_name(), "fEventNumber");
+        assert_eq!(index.len(), 4);
+        assert_eq!(index.entry(100, 3), Some(2));
+        assert_eq!(index.entry(100, 7), Some(0));
+        assert_eq!(index.entry(101, 1), Some(1));
+        assert_eq!(index.entry(100, 5), None);
+        assert_eq!(index.entry(102, 0), None);
+    }
+
+    #[test]
+    fn without_entry_numbers() {
+        let mut buf = tree_index(2, &[&[100, 101], &[3, 1]]);
+        // Null pointer to `fIndex`
+        buf.push(0);
+        let (rest, index) = ttreeindex(&buf).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.entry(100, 3), None);
+    }
+
+    #[test]
+    fn combined_values_of_version_1() {
+        let buf = tree_index(1, &[&[(5 << 31) + 1, (6 << 31) + 4], &[1, 0]]);
+        let (rest, index) = ttreeindex(&buf).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(index.entry(5, 1), Some(1));
+        assert_eq!(index.entry(6, 4), Some(0));
+        assert_eq!(index.entry(5, 4), None);
+    }
+}
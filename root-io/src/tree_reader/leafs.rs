@@ -4,7 +4,12 @@ use nom::number::complete::*;
 use nom::*;
 use quote::{Ident, Tokens};
 
-use crate::{code_gen::rust::ToRustType, core::*};
+use crate::{
+    code_gen::rust::ToRustType,
+    core::*,
+    tree_reader::value::{Double32, Scalar, ValueLayout},
+    Error,
+};
 
 #[derive(Debug, Clone)]
 pub struct TLeafBase {
@@ -69,78 +74,22 @@ impl ToRustType for TLeaf {
     fn type_name(&self) -> Tokens {
         match *self {
             TLeaf::Primitive(ref leaf_name, ref leaf) => {
-                if leaf.flen != 1 || leaf.foffset != 0 || leaf.fleafcount.is_some() {
-                    panic!("Unexpected TLeaf: \n{:#?}", leaf);
-                }
-                let t = match leaf_name.as_str() {
-                    "TLeafO" => {
-                        if leaf.flentype != 1 {
-                            panic!("Unexpected type length")
-                        }
-                        "bool"
-                    }
-                    "TLeafB" => {
-                        if leaf.flentype != 1 {
-                            panic!("Unexpected type length")
-                        };
-                        if leaf.fisunsigned == 1 {
-                            "u8"
-                        } else {
-                            "i8"
-                        }
-                    }
-                    "TLeafS" => {
-                        if leaf.flentype != 2 {
-                            panic!("Unexpected type length")
-                        };
-                        if leaf.fisunsigned == 1 {
-                            "u16"
-                        } else {
-                            "i16"
-                        }
-                    }
-                    "TLeafI" => {
-                        if leaf.flentype != 4 {
-                            panic!("Unexpected type length")
-                        };
-                        if leaf.fisunsigned == 1 {
-                            "u32"
-                        } else {
-                            "i32"
-                        }
-                    }
-                    "TLeafL" => {
-                        if leaf.flentype != 8 {
-                            panic!("Unexpected type length")
-                        };
-                        if leaf.fisunsigned == 1 {
-                            "u64"
-                        } else {
-                            "i64"
-                        }
-                    }
-                    "TLeafF" => {
-                        if leaf.flentype != 4 || leaf.fisunsigned != 0 {
-                            panic!("Unexpected type length or sign: {:#?}", leaf);
-                        };
-                        "f32"
-                    }
-                    "TLeafD" => {
-                        if leaf.flentype != 8 || leaf.fisunsigned != 0 {
-                            panic!("Unexpected type length or sign: {:#?}", leaf);
-                        };
-                        "f64"
-                    }
-                    name => panic!("Unexpected TLeaf type name {}", name),
+                let t = match primitive_scalar(leaf_name, leaf) {
+                    Some(scalar) => scalar.type_name(),
+                    // Treat unexpected leaves as blobs of bytes
+                    None => return quote! {Vec<u8>},
                 };
-                // not an array
-                let t = Ident::new(t);
-                if leaf.flen == 1 {
+                if leaf.fleafcount.is_some() {
+                    // variable number of elements per entry
+                    let t = Ident::new(format!("Vec<{}>", t));
+                    quote! {#t}
+                } else if leaf.flen == 1 {
+                    let t = Ident::new(t);
                     quote! {#t}
                 } else {
                     // array
-                    let s = format!("[{}; {}]", t, leaf.flen);
-                    quote! {#s}
+                    let t = Ident::new(format!("[{}; {}]", t, leaf.flen));
+                    quote! {#t}
                 }
             }
             TLeaf::String(_) => quote!(String),
@@ -174,6 +123,114 @@ impl ToRustType for TLeaf {
     }
 }
 
+impl TLeaf {
+    /// The `TLeaf` part common to all leaves
+    fn base(&self) -> &TLeafBase {
+        match self {
+            TLeaf::Primitive(_, base) | TLeaf::String(base) | TLeaf::Object(_, base) => base,
+            TLeaf::Element(el) => &el.base,
+        }
+    }
+
+    /// Does this leaf hold whole objects, i.e. is it the leaf of a
+    /// `TBranchObject` or of a `TBranchElement` which is not split?
+    pub(crate) fn holds_objects(&self) -> bool {
+        match self {
+            TLeaf::Object(..) => true,
+            TLeaf::Element(el) => el.id < 0 && matches!(el.type_id, TypeID::InvalidOrCounter(-1)),
+            _ => false,
+        }
+    }
+
+    /// The name of the data member stored in this leaf, i.e. without
+    /// the names of its parents and array dimensions
+    fn member_name(&self) -> &str {
+        let name = &self.base().tnamed.name;
+        let name = name.split('[').next().unwrap_or(name);
+        name.rsplit('.').next().unwrap_or(name)
+    }
+
+    /// Describe how the values of this leaf are stored. `double32`
+    /// provides the encoding of `Double32_t` members given their name.
+    pub(crate) fn value_layout<F>(&self, double32: F) -> Result<ValueLayout, Error>
+    where
+        F: FnOnce(&str) -> Result<Double32, Error>,
+    {
+        let base = self.base();
+        let scalar = match self {
+            TLeaf::Primitive(leaf_name, leaf) => primitive_scalar(leaf_name, leaf),
+            TLeaf::String(_) => Some(Scalar::String),
+            TLeaf::Element(el) => match &el.type_id {
+                TypeID::Primitive(id) | TypeID::Offset(id) => match id.0 {
+                    9 => Some(Scalar::Double32(double32(self.member_name())?)),
+                    _ => element_scalar(id),
+                },
+                TypeID::String | TypeID::STLString => Some(Scalar::String),
+                // Counters of `TClonesArray`s; see `type_name`
+                TypeID::InvalidOrCounter(_) if el.id == 0 => Some(Scalar::U32),
+                _ => None,
+            },
+            TLeaf::Object(_, _) => None,
+        };
+        let scalar = scalar.ok_or_else(|| {
+            let type_name = match self {
+                TLeaf::Element(el) => format!("{:?}", el.type_id),
+                _ => self.type_name().to_string(),
+            };
+            Error::Unsupported(format!(
+                "Reading leaf `{}` of type `{}` as `Value`",
+                base.tnamed.name, type_name
+            ))
+        })?;
+        Ok(ValueLayout {
+            scalar,
+            len: match scalar {
+                Scalar::String => 1,
+                _ => base.flen.max(1) as usize,
+            },
+            jagged: base.fleafcount.is_some(),
+        })
+    }
+}
+
+/// The type of the elements of a `TLeafX` with the given class name
+fn primitive_scalar(leaf_name: &str, leaf: &TLeafBase) -> Option<Scalar> {
+    Some(match (leaf_name, leaf.flentype, leaf.fisunsigned == 1) {
+        ("TLeafO", 1, _) => Scalar::Bool,
+        ("TLeafB", 1, true) => Scalar::U8,
+        ("TLeafB", 1, false) => Scalar::I8,
+        ("TLeafS", 2, true) => Scalar::U16,
+        ("TLeafS", 2, false) => Scalar::I16,
+        ("TLeafI", 4, true) => Scalar::U32,
+        ("TLeafI", 4, false) => Scalar::I32,
+        ("TLeafL", 8, true) => Scalar::U64,
+        ("TLeafL", 8, false) => Scalar::I64,
+        ("TLeafF", 4, false) => Scalar::F32,
+        ("TLeafD", 8, false) => Scalar::F64,
+        _ => return None,
+    })
+}
+
+/// The type of the elements of a `TLeafElement` with the given
+/// basic type. `Double32_t` needs extra information and is not
+/// handled here.
+fn element_scalar(id: &PrimitiveID) -> Option<Scalar> {
+    Some(match id.0 {
+        1 => Scalar::I8,
+        2 => Scalar::I16,
+        3 | 6 => Scalar::I32,
+        4 | 16 => Scalar::I64,
+        5 => Scalar::F32,
+        8 => Scalar::F64,
+        11 => Scalar::U8,
+        12 => Scalar::U16,
+        13 | 15 => Scalar::U32,
+        14 | 17 => Scalar::U64,
+        18 => Scalar::Bool,
+        _ => return None,
+    })
+}
+
 /// Helper function to parse the header of a `TLeaf`; Note that each
 /// `TLeaf` also has a type specific part which is ingnored here!
 pub(crate) fn tleaf<'s>(
@@ -184,22 +241,40 @@ pub(crate) fn tleaf<'s>(
     // let c_name = c_name.as_bytes();
     match c_name {
         // Variable length string; has same layout as `Primitive`
-        "TLeafC" => map!(i, call!(tleafprimitive, context), TLeaf::String),
+        "TLeafC" => map!(i, call!(tleafprimitive, context, c_name), TLeaf::String),
         "TLeafElement" => map!(i, call!(tleafelement, context), TLeaf::Element),
         "TLeafObject" => map!(i, call!(tleafobject, context), |v| TLeaf::Object(
             c_name.to_string(),
             v
         )),
-        _ => map!(i, call!(tleafprimitive, context), |v| TLeaf::Primitive(
+        _ => map!(i, call!(tleafprimitive, context, c_name), |v| TLeaf::Primitive(
             c_name.to_string(),
             v
         )),
     }
 }
 
+/// Parse a `TObjArray` of `TLeaf`s
+pub(crate) fn tleaves<'s, 'c>(input: &'s [u8], context: &'c Context) -> IResult<&'s [u8], Vec<TLeaf>>
+where
+    's: 'c,
+{
+    let (i, raws) = length_value!(input, checked_byte_count, call!(tobjarray, context))?;
+    let leaves = raws
+        .iter()
+        .map(|r| tleaf(r.obj, context, &r.classinfo).map(|(_, l)| l))
+        .collect::<Result<_, _>>()
+        .map_err(|e| reborrow_err(input, e))?;
+    Ok((i, leaves))
+}
+
 #[allow(unused_variables)]
 fn tleafbase<'s>(input: &'s [u8], context: &'s Context<'s>) -> IResult<&'s [u8], TLeafBase> {
-    let _curried_raw = |i| raw(i, context);
+    let leaf_count = |i| {
+        let (i, r) = raw(i, context)?;
+        let (_, leaf) = tleaf(r.obj, context, &r.classinfo)?;
+        Ok((i, leaf))
+    };
     do_parse!(input,
               ver: be_u16 >>
               tnamed: length_value!(checked_byte_count,
@@ -212,9 +287,7 @@ fn tleafbase<'s>(input: &'s [u8], context: &'s Context<'s>) -> IResult<&'s [u8],
               fleafcount:
               switch!(peek!(be_u32),
                       0 => map!(call!(be_u32), | _ | None) |
-                      _ => map!(map!(call!(_curried_raw),
-                                     |r| tleaf(r.obj, context, &r.classinfo).unwrap().1),
-                                |i| Some(Box::new(i)))) >>
+                      _ => map!(call!(leaf_count), |i| Some(Box::new(i)))) >>
               ({
                   TLeafBase {
                       ver,
@@ -238,13 +311,20 @@ fn tleafelement<'s>(input: &'s [u8], context: &'s Context<'s>)
               (TLeafElement {base, id, type_id})
     )
 }
-fn tleafprimitive<'s>(input: &'s [u8], context: &'s Context<'s>)
+fn tleafprimitive<'s>(input: &'s [u8], context: &'s Context<'s>, c_name: &str)
                     -> IResult<&'s [u8], TLeafBase> {
+    // `fMinimum` and `fMaximum` have the type of the leaf itself
+    let min_max_size: usize = match c_name {
+        "TLeafO" | "TLeafB" => 1,
+        "TLeafS" => 2,
+        "TLeafL" | "TLeafD" => 8,
+        _ => 4,
+    };
     do_parse!(input,
               _ver: be_u16 >>
               base: length_value!(checked_byte_count, call!(tleafbase, context)) >>
-              _fmaximum: be_f32 >>
-              _fminimum: be_f32 >>
+              _fmaximum: take!(min_max_size) >>
+              _fminimum: take!(min_max_size) >>
               (base)
     )
 }
@@ -257,3 +337,53 @@ fn tleafobject<'s>(input: &'s [u8], context: &'s Context<'s>)
               (base)
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn element(type_id: TypeID) -> TLeaf {
+        let base = TLeafBase {
+            ver: 2,
+            tnamed: TNamed {
+                name: "fX".to_string(),
+                title: String::new(),
+            },
+            flen: 1,
+            flentype: 0,
+            foffset: 0,
+            fisrange: 0,
+            fisunsigned: 0,
+            fleafcount: None,
+        };
+        TLeaf::Element(TLeafElement {
+            base,
+            id: 1,
+            type_id,
+        })
+    }
+
+    #[test]
+    fn element_type_names() {
+        let type_name = |id| element(TypeID::new(id).unwrap()).type_name().to_string();
+        assert_eq!(type_name(3), "i32");
+        // Base classes, `TObject`, `TNamed`, `char*` and unknown types
+        for id in &[0, 66, 67, 7, 1000] {
+            assert_eq!(type_name(*id), "Vec<u8>");
+        }
+        assert!(format!("{:?}", element(TypeID::Base)).contains("Vec<u8>"));
+    }
+
+    #[test]
+    fn unsupported_value_layout() {
+        for (type_id, name) in [
+            (TypeID::Base, "Base"),
+            (TypeID::Unknown(1000), "Unknown(1000)"),
+        ] {
+            match element(type_id).value_layout(|_| Ok(Double32::Float)) {
+                Err(Error::Unsupported(msg)) => assert!(msg.contains(name), "{}", msg),
+                other => panic!("Unexpected layout {:?}", other),
+            }
+        }
+    }
+}
@@ -6,19 +6,43 @@
 //! ROOT lingo).
 
 mod branch;
+mod chain;
+mod cluster;
+mod column;
 mod container;
+mod from_tree;
+mod index;
 mod leafs;
 mod tree;
+mod value;
 
-pub use self::tree::{ttree, Tree};
+pub use self::branch::ReadOptions;
+pub use self::chain::Chain;
+pub use self::cluster::Cluster;
+pub use self::column::{BasketColumn, Primitive};
+#[doc(hidden)]
+pub use self::from_tree::__private;
+pub use self::from_tree::{EntryStream, FromBranch, FromTree};
+pub use self::index::TreeIndex;
+pub(crate) use self::tree::ttree;
+pub use self::tree::{Friend, Tree};
+pub use self::value::Value;
+pub(crate) use self::value::{Double32, Scalar};
+pub use root_io_derive::FromTree;
 
 #[cfg(all(test, not(target_arch = "wasm32")))]
 mod tests {
     use std::path::PathBuf;
+
+    use futures::{StreamExt, TryStreamExt};
+    use nom::number::complete::{be_i32, be_u32};
     use tokio;
 
-    use super::ttree;
-    use crate::core::RootFile;
+    use super::branch::in_memory_baskets;
+    use super::container::{BasketData, Container};
+    use super::{BasketColumn, ReadOptions, Value};
+    use crate::core::{Buffer, Raw, RootFile, Source};
+    use crate::Error;
 
     #[tokio::test]
     async fn simple_tree() {
@@ -26,6 +50,542 @@ mod tests {
         let f = RootFile::new(path.as_path())
             .await
             .expect("Failed to open file");
-        f.items()[0].parse_with(ttree).await.unwrap();
+        f.items()[0].as_tree().await.unwrap();
+    }
+
+    /// The raw content of every basket of every branch in the
+    /// first tree of the given file
+    async fn all_baskets(path: &str) -> Vec<(String, Vec<BasketData>)> {
+        let f = RootFile::new(PathBuf::from(path).as_path())
+            .await
+            .expect("Failed to open file");
+        baskets_of(f).await
+    }
+
+    async fn baskets_of(f: RootFile) -> Vec<(String, Vec<BasketData>)> {
+        let tree = f.items()[0].as_tree().await.unwrap();
+        let mut out = vec![];
+        for branch in tree.branches() {
+            let mut baskets = vec![];
+            for c in branch.containers() {
+                baskets.push(c.to_owned().raw_data().await.unwrap());
+            }
+            out.push((branch.name(), baskets));
+        }
+        out
+    }
+
+    #[tokio::test]
+    async fn lz4_same_as_zlib() {
+        let pairs = [
+            ("HZZ-lz4.root", "HZZ-zlib.root"),
+            ("Zmumu-lz4.root", "Zmumu-zlib.root"),
+            ("sample-6.10.05-lz4.root", "sample-6.10.05-zlib.root"),
+        ];
+        for (lz4, zlib) in pairs.iter() {
+            let lz4 = all_baskets(&format!("./src/test_data/{}", lz4)).await;
+            let zlib = all_baskets(&format!("./src/test_data/{}", zlib)).await;
+            assert!(!lz4.is_empty());
+            assert_eq!(lz4, zlib);
+        }
+    }
+
+    #[tokio::test]
+    async fn mapped_file() {
+        for name in &["sample-6.10.05-uncompressed.root", "HZZ-zlib.root"] {
+            let path = format!("./src/test_data/{}", name);
+            // The test data is not modified while it is mapped
+            let source = unsafe { Source::mmap(&path) }.unwrap();
+            let f = RootFile::new(source).await.unwrap();
+            let mapped = baskets_of(f).await;
+            assert_eq!(mapped, all_baskets(&path).await);
+            let borrowed = mapped
+                .iter()
+                .flat_map(|(_, baskets)| baskets)
+                .all(|b| matches!(b.data, Buffer::Borrowed(..)));
+            // Uncompressed baskets are not copied
+            assert_eq!(borrowed, name.contains("uncompressed"));
+        }
+    }
+
+    /// Copy a test file to a temporary location and overwrite 16
+    /// bytes starting at `pos`
+    fn corrupted_copy(name: &str, pos: usize) -> PathBuf {
+        let mut bytes = std::fs::read(format!("./src/test_data/{}", name)).unwrap();
+        for b in &mut bytes[pos..pos + 16] {
+            *b = 0xff;
+        }
+        let path = std::env::temp_dir().join(format!("corrupted-{}-{}", pos, name));
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    /// Corrupt the middle of the payload of the first item of the given file
+    async fn corrupted_first_item(name: &str) -> RootFile {
+        let orig = PathBuf::from(format!("./src/test_data/{}", name));
+        let f = RootFile::new(orig.as_path()).await.unwrap();
+        let hdr = &f.items()[0].tkey_hdr;
+        let start = (hdr.seek_key + hdr.key_len as u64) as usize;
+        let mid = start + (hdr.total_size as usize - hdr.key_len as usize) / 2;
+        RootFile::new(corrupted_copy(name, mid).as_path())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn corrupted_tree_is_err() {
+        let f = corrupted_first_item("simple.root").await;
+        match f.items()[0].as_tree().await {
+            Err(Error::Decompression(_)) => {}
+            other => panic!("Expected decompression error, got {:?}", other),
+        }
+
+        let f = corrupted_first_item("HZZ-uncompressed.root").await;
+        match f.items()[0].as_tree().await {
+            Err(Error::Parse { class, offset, .. }) => {
+                assert_eq!(class, "TTree");
+                assert_eq!(offset, f.items()[0].tkey_hdr.seek_key);
+            }
+            other => panic!("Expected parse error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn corrupted_basket_keeps_entries_aligned() {
+        let name = "sample-5.30.00-zlib.root";
+        let path = PathBuf::from(format!("./src/test_data/{}", name));
+        let f = RootFile::new(path.as_path()).await.unwrap();
+        let tree = f.items()[0].as_tree().await.unwrap();
+        let branch = tree.branch_by_name("Ai4").unwrap();
+        let n_entries = branch.fentries as usize;
+        let first_entries = branch.fbasketentry.clone();
+        assert!(first_entries.len() > 2);
+        let pos = branch.containers()[1].offset() as usize;
+        let orig_i4 = tree
+            .branch_by_name("i4")
+            .unwrap()
+            .as_fixed_size_iterator(|i| be_i32(i))
+            .collect::<Vec<_>>()
+            .await;
+        let orig_ai4 = branch
+            .as_jagged_iterator(|i| be_i32(i))
+            .collect::<Vec<_>>()
+            .await;
+
+        let f = RootFile::new(corrupted_copy(name, pos).as_path())
+            .await
+            .unwrap();
+        let tree = f.items()[0].as_tree().await.unwrap();
+        let entries = tree
+            .branch_by_name("n")
+            .unwrap()
+            .as_fixed_size_iterator(|i| be_u32(i))
+            .collect::<Vec<_>>()
+            .await;
+        let res = tree
+            .branch_by_name("Ai4")
+            .unwrap()
+            .try_as_var_size_iterator(|i| be_i32(i), &entries)
+            .collect::<Vec<_>>()
+            .await;
+        // One error for each entry of the corrupted basket
+        assert_eq!(res.len(), n_entries);
+        let lost = first_entries[1] as usize..first_entries[2] as usize;
+        for (idx, r) in res.iter().enumerate() {
+            match r {
+                Err(Error::Branch {
+                    branch,
+                    basket,
+                    entry,
+                    ..
+                }) => {
+                    assert!(lost.contains(&idx));
+                    assert_eq!(branch, "Ai4");
+                    assert_eq!(*basket, 1);
+                    assert_eq!(*entry as usize, idx);
+                }
+                Err(e) => panic!("Expected branch error, got {:?}", e),
+                Ok(v) => {
+                    assert!(!lost.contains(&idx));
+                    assert_eq!(v, &orig_ai4[idx]);
+                }
+            }
+        }
+
+        // Zipping with a healthy branch keeps the entries aligned
+        let zipped = tree
+            .branch_by_name("i4")
+            .unwrap()
+            .try_as_fixed_size_iterator(|i| be_i32(i))
+            .zip(
+                tree.branch_by_name("Ai4")
+                    .unwrap()
+                    .try_as_var_size_iterator(|i| be_i32(i), &entries),
+            )
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(zipped.len(), n_entries);
+        for (idx, (i4, ai4)) in zipped.into_iter().enumerate() {
+            assert_eq!(i4.unwrap(), orig_i4[idx]);
+            match ai4 {
+                Ok(ai4) => assert_eq!(ai4, orig_ai4[idx]),
+                Err(_) => assert!(lost.contains(&idx)),
+            }
+        }
+    }
+
+    #[test]
+    fn in_memory_baskets_follow_written_ones() {
+        let raw = |obj: &'static [u8]| Raw {
+            classinfo: "TBasket".to_string(),
+            obj,
+        };
+        // Two baskets were written; the first slot still holds a copy
+        // of one of them and the third one was not written yet
+        let fbaskets = vec![raw(&[1]), raw(&[]), raw(&[3]), raw(&[])];
+        let baskets = in_memory_baskets(&fbaskets, 2);
+        assert_eq!(baskets.len(), 1);
+        match &baskets[0] {
+            Container::InMemory(buf) => assert_eq!(buf, &[3]),
+            other => panic!("Expected in-memory basket, got {:?}", other),
+        }
+        assert!(in_memory_baskets(&fbaskets, 4).is_empty());
+    }
+
+    #[tokio::test]
+    async fn random_access() {
+        let path = PathBuf::from("./src/test_data/sample-5.30.00-zlib.root");
+        let f = RootFile::new(path.as_path()).await.unwrap();
+        let tree = f.items()[0].as_tree().await.unwrap();
+        let fixed = tree.branch_by_name("i4").unwrap();
+        let all_fixed = fixed
+            .as_fixed_size_iterator(|i| be_i32(i))
+            .collect::<Vec<_>>()
+            .await;
+        let counter = tree
+            .branch_by_name("n")
+            .unwrap()
+            .as_fixed_size_iterator(|i| be_u32(i))
+            .collect::<Vec<_>>()
+            .await;
+        let var = tree.branch_by_name("Ai4").unwrap();
+        let all_var = var
+            .as_var_size_iterator(|i| be_i32(i), &counter)
+            .collect::<Vec<_>>()
+            .await;
+        let n = all_fixed.len() as u64;
+        assert_eq!(n, 30);
+        assert_eq!(all_var.len() as u64, n);
+
+        for i in 0..n {
+            assert_eq!(
+                fixed.entry(i, |i| be_i32(i)).await.unwrap(),
+                all_fixed[i as usize]
+            );
+            assert_eq!(
+                var.var_size_entry(i, |i| be_i32(i), counter[i as usize])
+                    .await
+                    .unwrap(),
+                all_var[i as usize]
+            );
+        }
+        // Ranges within one basket, across baskets and up to the end
+        for (start, end) in &[(0, 0), (1, 3), (5, 16), (0, 30), (28, 30)] {
+            let range = *start as usize..*end as usize;
+            assert_eq!(
+                fixed.entries(*start..*end, |i| be_i32(i)).await.unwrap(),
+                &all_fixed[range.clone()]
+            );
+            assert_eq!(
+                var.var_size_entries(*start..*end, |i| be_i32(i), &counter[range.clone()])
+                    .await
+                    .unwrap(),
+                &all_var[range]
+            );
+        }
+        assert!(fixed.entry(n, |i| be_i32(i)).await.is_err());
+        assert!(fixed.entries(20..n + 1, |i| be_i32(i)).await.is_err());
+        match var
+            .var_size_entries(0..3, |i| be_i32(i), &counter[..2])
+            .await
+        {
+            Err(Error::Incompatible(_)) => {}
+            other => panic!("Expected incompatible counter, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn jagged_by_entry_offsets() {
+        let path = PathBuf::from("./src/test_data/sample-5.30.00-zlib.root");
+        let f = RootFile::new(path.as_path()).await.unwrap();
+        let tree = f.items()[0].as_tree().await.unwrap();
+        let n: Vec<u32> = tree
+            .branch_by_name("n")
+            .unwrap()
+            .as_fixed_size_iterator(|i| be_u32(i))
+            .collect()
+            .await;
+        let branch = tree.branch_by_name("Ai4").unwrap();
+        let by_counter: Vec<Vec<i32>> = branch
+            .as_var_size_iterator(|i| be_i32(i), &n)
+            .collect()
+            .await;
+        let by_offsets: Vec<Vec<i32>> = branch.as_jagged_iterator(|i| be_i32(i)).collect().await;
+        assert_eq!(by_counter.len(), 30);
+        assert!(by_counter.iter().any(|e| e.is_empty()));
+        assert_eq!(by_offsets, by_counter);
+
+        // Baskets of fixed size branches have no entry offsets
+        let fixed: Vec<_> = tree
+            .branch_by_name("i4")
+            .unwrap()
+            .try_as_jagged_iterator(|i| be_i32(i))
+            .collect()
+            .await;
+        assert!(!fixed.is_empty());
+        assert!(fixed.iter().all(|res| match res {
+            Err(Error::Branch { error, .. }) => matches!(**error, Error::Unsupported(_)),
+            _ => false,
+        }));
+    }
+
+    #[tokio::test]
+    async fn value_stream() {
+        let path = PathBuf::from("./src/test_data/simple.root");
+        let f = RootFile::new(path.as_path()).await.unwrap();
+        let tree = f.items()[0].as_tree().await.unwrap();
+        let streamers = f.streamers().await.unwrap();
+        let values = |name| {
+            tree.branch_by_name(name)
+                .unwrap()
+                .as_value_stream(&streamers)
+                .map(Result::unwrap)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            values("one").await,
+            (1..=4).map(Value::I32).collect::<Vec<_>>()
+        );
+        assert_eq!(values("two").await[1], Value::F32(2.2));
+        assert_eq!(values("three").await[0], Value::String("uno".to_string()));
+
+        let path = PathBuf::from("./src/test_data/sample-5.30.00-zlib.root");
+        let f = RootFile::new(path.as_path()).await.unwrap();
+        let tree = f.items()[0].as_tree().await.unwrap();
+        let streamers = f.streamers().await.unwrap();
+        let values = |name| {
+            tree.branch_by_name(name)
+                .unwrap()
+                .as_value_stream(&streamers)
+                .map(Result::unwrap)
+                .collect::<Vec<_>>()
+        };
+        let ab = values("ab").await;
+        assert_eq!(ab.len(), 30);
+        assert_eq!(
+            ab[0],
+            Value::Array(vec![
+                Value::Bool(false),
+                Value::Bool(true),
+                Value::Bool(false)
+            ])
+        );
+        let jagged: Vec<Value> = tree
+            .branch_by_name("Ai4")
+            .unwrap()
+            .as_jagged_iterator(|i| be_i32(i))
+            .map(|entry| Value::Vec(entry.into_iter().map(Value::I32).collect()))
+            .collect()
+            .await;
+        assert_eq!(values("Ai4").await, jagged);
+
+        // Leaves which cannot be read dynamically yield a single error
+        let path = PathBuf::from("./src/test_data/small-evnt-tree-fullsplit.root");
+        let f = RootFile::new(path.as_path()).await.unwrap();
+        let tree = f.items()[0].as_tree().await.unwrap();
+        let values: Vec<_> = tree
+            .branch_by_name("StlVecI16")
+            .unwrap()
+            .as_value_stream(&[])
+            .collect()
+            .await;
+        match values.as_slice() {
+            [Err(Error::Unsupported(_))] => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn object_stream_of_split_branch() {
+        let path = PathBuf::from("./src/test_data/small-evnt-tree-fullsplit.root");
+        let f = RootFile::new(path.as_path()).await.unwrap();
+        let tree = f.items()[0].as_tree().await.unwrap();
+        let streamers = f.streamers().await.unwrap();
+        // The members of split objects are stored in separate branches
+        for name in &["evt", "I16"] {
+            let values: Vec<_> = tree
+                .branch_by_name(name)
+                .unwrap()
+                .as_object_stream(&streamers)
+                .collect()
+                .await;
+            match values.as_slice() {
+                [Err(Error::Unsupported(_))] => {}
+                other => panic!("Unexpected result {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn basket_columns() {
+        let path = PathBuf::from("./src/test_data/sample-5.30.00-zlib.root");
+        let f = RootFile::new(path.as_path()).await.unwrap();
+        let tree = f.items()[0].as_tree().await.unwrap();
+        let i4: Vec<_> = tree
+            .branch_by_name("i4")
+            .unwrap()
+            .as_fixed_size_iterator(|i| be_i32(i))
+            .collect()
+            .await;
+        let columns: Vec<BasketColumn<i32>> = tree
+            .branch_by_name("i4")
+            .unwrap()
+            .read_basket_columns()
+            .try_collect()
+            .await
+            .unwrap();
+        assert!(columns.len() > 1);
+        assert!(columns.iter().all(|c| c.offsets.is_none()));
+        assert_eq!(columns[1].first_entry, columns[0].n_entries as u64);
+        let values: Vec<_> = columns.into_iter().flat_map(|c| c.values).collect();
+        assert_eq!(values, i4);
+
+        // Fixed size arrays are flattened
+        let ai4: Vec<BasketColumn<i32>> = tree
+            .branch_by_name("ai4")
+            .unwrap()
+            .read_basket_columns()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(
+            ai4[0].entry(1),
+            Some(&[i4[1] + 1, i4[1] + 2, i4[1] + 3][..])
+        );
+
+        let jagged: Vec<Vec<i32>> = tree
+            .branch_by_name("Ai4")
+            .unwrap()
+            .as_jagged_iterator(|i| be_i32(i))
+            .collect()
+            .await;
+        let columns: Vec<BasketColumn<i32>> = tree
+            .branch_by_name("Ai4")
+            .unwrap()
+            .read_basket_columns()
+            .try_collect()
+            .await
+            .unwrap();
+        let entries: Vec<Vec<i32>> = columns
+            .iter()
+            .flat_map(|c| (0..c.n_entries).map(move |i| c.entry(i).unwrap().to_vec()))
+            .collect();
+        assert_eq!(entries, jagged);
+
+        // The requested type has to match the leaf
+        let wrong: Vec<Result<BasketColumn<f64>, _>> = tree
+            .branch_by_name("i4")
+            .unwrap()
+            .read_basket_columns()
+            .collect()
+            .await;
+        match wrong.as_slice() {
+            [Err(Error::Unsupported(_))] => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn prefetched_stream() {
+        let path = PathBuf::from("./src/test_data/sample-5.30.00-zlib.root");
+        let f = RootFile::new(path.as_path()).await.unwrap();
+        let tree = f.items()[0].as_tree().await.unwrap();
+        let branch = tree.branch_by_name("i4").unwrap();
+        assert!(branch.containers().len() > 2);
+        let sequential: Vec<_> = branch.as_fixed_size_iterator(|i| be_i32(i)).collect().await;
+        for (prefetch, decompress_threads) in &[(0, 2), (1, 0), (5, 0), (3, 2)] {
+            let options = ReadOptions {
+                prefetch: *prefetch,
+                decompress_threads: *decompress_threads,
+            };
+            let prefetched: Vec<_> = branch
+                .stream_with(|i| be_i32(i), options)
+                .try_collect()
+                .await
+                .unwrap();
+            assert_eq!(prefetched, sequential, "{:?}", options);
+        }
+    }
+
+    #[tokio::test]
+    async fn read_clusters() {
+        let path = PathBuf::from("./src/test_data/foriter2.root");
+        let f = RootFile::new(path.as_path()).await.unwrap();
+        let tree = f.items()[0].as_tree().await.unwrap();
+        let clusters = tree.clusters();
+        assert_eq!(
+            clusters,
+            vec![0..6, 6..12, 12..17, 17..22, 22..26, 26..30, 30..31]
+        );
+        let data: Vec<i32> = tree
+            .branch_by_name("data")
+            .unwrap()
+            .as_fixed_size_iterator(|i| be_i32(i))
+            .collect()
+            .await;
+        let read: Vec<_> = tree
+            .read_clusters(&["data"])
+            .unwrap()
+            .map(|cluster| {
+                let cluster = cluster.unwrap();
+                let entries = cluster.fixed_size("data", |i| be_i32(i)).unwrap();
+                assert_eq!(
+                    entries.len() as u64,
+                    cluster.entries().end - cluster.entries().start
+                );
+                entries
+            })
+            .collect()
+            .await;
+        assert_eq!(read.concat(), data);
+
+        // Baskets of different branches are not aligned in this file
+        let path = PathBuf::from("./src/test_data/sample-5.30.00-zlib.root");
+        let f = RootFile::new(path.as_path()).await.unwrap();
+        let tree = f.items()[0].as_tree().await.unwrap();
+        assert_eq!(tree.clusters(), vec![0..30]);
+        let clusters: Vec<_> = tree
+            .read_clusters(&["i4", "Ai4"])
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(clusters.len(), 1);
+        let i4: Vec<i32> = tree
+            .branch_by_name("i4")
+            .unwrap()
+            .as_fixed_size_iterator(|i| be_i32(i))
+            .collect()
+            .await;
+        let ai4: Vec<Vec<i32>> = tree
+            .branch_by_name("Ai4")
+            .unwrap()
+            .as_jagged_iterator(|i| be_i32(i))
+            .collect()
+            .await;
+        assert_eq!(clusters[0].fixed_size("i4", |i| be_i32(i)).unwrap(), i4);
+        assert_eq!(clusters[0].jagged("Ai4", |i| be_i32(i)).unwrap(), ai4);
+        assert!(clusters[0].fixed_size("f8", |i| be_i32(i)).is_err());
+        assert!(tree.read_clusters(&["does-not-exist"]).is_err());
     }
 }
@@ -1,13 +1,23 @@
-use failure::Error;
+use futures::prelude::*;
 use nom::number::complete::*;
 use nom::*;
 
 use std::fmt;
-use std::ops::Deref;
+use std::ops::{Deref, Range};
+
+use once_cell::sync::OnceCell;
 
 use crate::{
-    core::parsers::*, core::types::*, tree_reader::branch::tbranch_hdr,
-    tree_reader::branch::TBranch, tree_reader::leafs::tleaf, tree_reader::leafs::TLeaf,
+    core::parsers::*,
+    core::types::*,
+    core::{RootFile, Source},
+    tree_reader::branch::tbranches,
+    tree_reader::branch::TBranch,
+    tree_reader::cluster::{cluster_ranges, read_cluster, Cluster},
+    tree_reader::index::{ttreeindex, TreeIndex},
+    tree_reader::leafs::tleaves,
+    tree_reader::leafs::TLeaf,
+    Error,
 };
 
 /// `TTree` potentially has members with very large `Vec<u8>` buffers
@@ -28,6 +38,57 @@ impl fmt::Debug for Pointer {
     }
 }
 
+/// A friend of a tree (ROOT's `TFriendElement`): another tree whose
+/// branches are read as if they belonged to the tree itself. The
+/// friend tree may be stored in the same or in a different file.
+#[derive(Debug)]
+pub struct Friend {
+    /// Alias of the friend; the name of its tree if none was given
+    name: String,
+    /// Name of the friend tree in its file
+    tree_name: String,
+    /// Name of the file holding the friend tree; empty if it is the
+    /// same file as the one of the tree
+    file_name: String,
+    /// The friend tree, or the reason why it could not be opened;
+    /// empty until it is first needed
+    tree: OnceCell<Result<Tree, Error>>,
+}
+
+impl Friend {
+    /// The name under which the branches of this friend are found,
+    /// as in `name.branch`
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The name of the friend tree in its file
+    pub fn tree_name(&self) -> &str {
+        &self.tree_name
+    }
+
+    /// The file of the friend tree, if it is not stored in the same
+    /// file as the tree it belongs to
+    pub fn file_name(&self) -> Option<&str> {
+        Some(self.file_name.as_str()).filter(|f| !f.is_empty())
+    }
+
+    /// The friend tree, if it was opened; see `Tree::open_branch`
+    pub fn tree(&self) -> Result<&Tree, Error> {
+        match self.tree.get() {
+            Some(Ok(tree)) => Ok(tree),
+            Some(Err(e)) => Err(Error::NotFound(format!(
+                "Friend tree `{}`: {}",
+                self.name, e
+            ))),
+            None => Err(Error::NotFound(format!(
+                "Friend tree `{}`: Not opened yet",
+                self.name
+            ))),
+        }
+    }
+}
+
 /// A `Tree` is the default "container" for datasets in Root files The
 /// data is oranized in so-called branches. This type is exposed only
 /// for the purpose of creating `ColumnFixedIntoIter` and
@@ -62,24 +123,34 @@ pub struct Tree {
     fmaxentryloop: i64,
     /// Number of entries to estimate histogram limits
     festimate: i64,
+    /// Number of entries per cluster if positive; bytes per cluster if negative
+    fautoflush: i64,
+    /// Last entry of each range of clusters
+    fclusterrangeend: Vec<i64>,
+    /// Number of entries in each cluster of the corresponding range
+    fclustersize: Vec<i64>,
     /// List of Branches
     pub(crate) fbranches: Vec<TBranch>,
     /// Direct pointers to individual branch leaves
     pub(crate) fleaves: Vec<TLeaf>,
     /// List of aliases for expressions based on the tree branches.
-    faliases: Option<Vec<u8>>,
+    /// The name of each is the alias, the title the expression.
+    faliases: Result<Vec<TNamed>, Error>,
     /// Sorted index values
     findexvalues: Vec<f64>,
     /// Index of sorted values
     findex: Vec<i32>,
-    /// Pointer to the tree Index (if any)
-    ftreeindex: Option<Pointer>,
-    /// pointer to list of friend elements
-    ffriends: Option<Pointer>,
+    /// The tree index (if any), or the reason why it could not be
+    /// read
+    ftreeindex: Result<Option<TreeIndex>, Error>,
+    /// List of friend elements
+    ffriends: Result<Vec<Friend>, Error>,
     /// pointer to a list of user objects associated to this Tree
     fuserinfo: Option<Pointer>,
     /// Branch supporting the TRefTable (if any)
     fbranchref: Option<Pointer>,
+    /// The file this tree was read from
+    source: Source,
 }
 
 impl<'s> Tree {
@@ -100,26 +171,313 @@ impl<'s> Tree {
             .collect()
     }
 
-    pub fn branch_by_name(&self, name: &str) -> Result<&TBranch, Error> {
-        self.branches()
+    /// The number of entries in this tree
+    pub fn n_entries(&self) -> u64 {
+        self.fentries.max(0) as u64
+    }
+
+    /// The clusters of this tree as ranges of entries. The entries of
+    /// a cluster are stored in the same baskets for all branches, so
+    /// they can be read together (see `read_clusters`). The clusters
+    /// are computed as in ROOT's `TTree::TClusterIterator`. Trees
+    /// written without cluster information are split wherever all
+    /// branches start a new basket.
+    pub fn clusters(&self) -> Vec<Range<u64>> {
+        let ranges: Vec<_> = self
+            .fclusterrangeend
+            .iter()
+            .zip(self.fclustersize.iter())
+            .map(|(end, size)| (*end, *size))
+            .collect();
+        cluster_ranges(
+            self.n_entries(),
+            self.fautoflush,
+            &ranges,
+            &self.common_basket_starts(),
+        )
+    }
+
+    /// Entries at which all branches start a new basket
+    fn common_basket_starts(&self) -> Vec<u64> {
+        let mut common: Option<Vec<u64>> = None;
+        for branch in self.branches() {
+            if branch.fbasketentry.is_empty() {
+                continue;
+            }
+            let starts: Vec<_> = branch.fbasketentry.iter().map(|e| *e as u64).collect();
+            common = Some(match common {
+                None => starts,
+                Some(common) => common.into_iter().filter(|e| starts.contains(e)).collect(),
+            });
+        }
+        common.unwrap_or_default()
+    }
+
+    /// Stream over the `clusters` of this tree, reading the given
+    /// branches. For each cluster, the needed baskets of all branches
+    /// are fetched together, merging the requests for baskets which
+    /// are next to each other in the file. This greatly reduces the
+    /// number of requests for remote files.
+    ///
+    /// # Example
+    /// ```
+    /// use futures::TryStreamExt;
+    /// use nom::number::complete::{be_i32, be_f64};
+    /// use std::path::Path;
+    /// use root_io::{Error, RootFile};
+    ///
+    /// # async fn f() -> Result<(), Error> {
+    /// let path = Path::new("./src/test_data/sample-5.30.00-zlib.root");
+    /// let f = RootFile::new(path).await?;
+    /// let tree = f.items()[0].as_tree().await?;
+    /// tree.read_clusters(&["i4", "f8"])?
+    ///     .try_for_each(|cluster| async move {
+    ///         let ints = cluster.fixed_size("i4", |i| be_i32(i))?;
+    ///         let floats = cluster.fixed_size("f8", |i| be_f64(i))?;
+    ///         assert_eq!(ints.len(), floats.len());
+    ///         Ok(())
+    ///     })
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read_clusters(
+        &self,
+        branches: &[&str],
+    ) -> Result<impl Stream<Item = Result<Cluster, Error>>, Error> {
+        let branches = branches
+            .iter()
+            .map(|name| self.branch_by_name(name).map(|b| b.to_owned()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(stream::iter(self.clusters()).then(move |entries| {
+            let branches = branches.clone();
+            async move { read_cluster(&branches, entries).await }
+        }))
+    }
+
+    /// The index of this tree, if it has one (see `TreeIndex`),
+    /// or the reason why it could not be read
+    pub fn index(&self) -> Result<Option<&TreeIndex>, Error> {
+        match &self.ftreeindex {
+            Ok(index) => Ok(index.as_ref()),
+            Err(e) => Err(e.duplicate()),
+        }
+    }
+
+    /// The entry with the given major and minor value of the tree's
+    /// index, e.g. a run and an event number. Together with
+    /// `TBranch::entry`, this allows to read the data of a particular
+    /// event.
+    pub fn entry_for_index(&self, major: i64, minor: i64) -> Result<u64, Error> {
+        let index = self
+            .index()?
+            .ok_or_else(|| Error::NotFound(format!("Tree `{}` has no index", self.tnamed.name)))?;
+        index.entry(major, minor).ok_or_else(|| {
+            Error::NotFound(format!(
+                "Entry with {} = {} and {} = {} in tree `{}`",
+                index.major_name(),
+                major,
+                index.minor_name(),
+                minor,
+                self.tnamed.name
+            ))
+        })
+    }
+
+    /// The friends of this tree, or the reason why the list of
+    /// friends could not be read
+    pub fn friends(&self) -> Result<&[Friend], Error> {
+        match &self.ffriends {
+            Ok(friends) => Ok(friends),
+            Err(e) => Err(e.duplicate()),
+        }
+    }
+
+    /// The aliases of this tree as pairs of the alias and the
+    /// expression it stands for, or the reason why the list of
+    /// aliases could not be read
+    pub fn aliases(&self) -> Result<impl Iterator<Item = (&str, &str)>, Error> {
+        match &self.faliases {
+            Ok(aliases) => Ok(aliases.iter().map(|a| (a.name.as_str(), a.title.as_str()))),
+            Err(e) => Err(e.duplicate()),
+        }
+    }
+
+    /// Set the position of the key of this tree in the errors of the
+    /// lists and the index which could not be read, as it is not
+    /// known while the tree is parsed
+    pub(crate) fn at_key(mut self, seek_key: u64) -> Self {
+        let errors = self
+            .faliases
+            .as_mut()
+            .err()
             .into_iter()
-            .find(|b| b.name == name)
-            .ok_or_else(|| {
-                format_err!(
+            .chain(self.ffriends.as_mut().err())
+            .chain(self.ftreeindex.as_mut().err());
+        for error in errors {
+            if let Error::Parse { offset, .. } = error {
+                *offset = seek_key;
+            }
+        }
+        self
+    }
+
+    /// Open the tree of the given friend
+    async fn open_friend(&self, friend: &Friend) -> Result<Tree, Error> {
+        let source = match friend.file_name() {
+            Some(file_name) => self.source.sibling(file_name)?,
+            None => self.source.clone(),
+        };
+        let file = RootFile::new(source).await?;
+        file.get(&friend.tree_name).await?.as_tree().await
+    }
+
+    /// Find a branch of this tree by its name
+    fn own_branch(&self, name: &str) -> Option<&TBranch> {
+        self.branches().into_iter().find(|b| b.name == name)
+    }
+
+    /// Find a branch of a friend, either as `friend.branch` or by the
+    /// name of the branch alone
+    fn friend_branch(&self, name: &str) -> Option<&TBranch> {
+        let trees = || {
+            self.ffriends
+                .iter()
+                .flatten()
+                .filter_map(|f| f.tree().ok().map(|t| (f.name.as_str(), t)))
+        };
+        trees()
+            .filter_map(|(friend, tree)| {
+                let name = name.strip_prefix(friend)?.strip_prefix('.')?;
+                tree.own_branch(name)
+            })
+            .chain(trees().filter_map(|(_, tree)| tree.own_branch(name)))
+            .next()
+    }
+
+    /// Get a branch by its name. Names are resolved in this order: the
+    /// branches of this tree, aliases naming a branch, and branches of
+    /// friends, given as `friend.branch` or by the branch name alone.
+    /// Aliases for other expressions are not evaluated. Only friends
+    /// which were already opened are searched; use `open_branch` to
+    /// open them if needed. If the branch is not found and the list
+    /// of aliases or friends could not be read, the reason is
+    /// returned.
+    pub fn branch_by_name(&self, name: &str) -> Result<&TBranch, Error> {
+        let alias = self
+            .faliases
+            .iter()
+            .flatten()
+            .find(|alias| alias.name == name);
+        self.own_branch(name)
+            .or_else(|| {
+                let expr = &alias?.title;
+                self.own_branch(expr).or_else(|| self.friend_branch(expr))
+            })
+            .or_else(|| self.friend_branch(name))
+            .ok_or_else(|| match (&self.faliases, &self.ffriends) {
+                (Err(e), _) | (_, Err(e)) => e.duplicate(),
+                _ => Error::NotFound(format!(
                     "Branch {} not found in tree: \n {:#?}",
                     name,
                     self.branches()
                         .iter()
                         .map(|b| b.name.to_owned())
                         .collect::<Vec<_>>()
-                )
+                )),
             })
     }
+
+    /// Same as `branch_by_name`, but if the branch is not found, the
+    /// friends which were not opened yet are opened and searched as
+    /// well. A friend which cannot be opened is skipped; the reason
+    /// is kept in `Friend::tree`.
+    pub async fn open_branch(&self, name: &str) -> Result<&TBranch, Error> {
+        if let Ok(branch) = self.branch_by_name(name) {
+            return Ok(branch);
+        }
+        for friend in self.ffriends.iter().flatten() {
+            if friend.tree.get().is_none() {
+                let tree = self.open_friend(friend).await;
+                // Another task may have opened it in the meantime
+                let _ = friend.tree.set(tree);
+            }
+        }
+        self.branch_by_name(name)
+    }
+}
+
+/// Parse a `TFriendElement`
+fn tfriendelement(input: &[u8]) -> IResult<&[u8], Friend> {
+    do_parse!(input,
+    _ver: be_u16 >>
+    tnamed: length_value!(checked_byte_count, tnamed) >>
+    tree_name: string >>
+    ({
+        Friend {
+            name: tnamed.name,
+            tree_name,
+            file_name: tnamed.title,
+            tree: OnceCell::new(),
+        }
+    }))
+}
+
+/// Parse a pointer to a `TList`, which may be null, and each element
+/// of the list with `p`. A list which cannot be parsed is returned as
+/// an error, rather than failing the whole tree, as it is not needed
+/// to read the branches of the tree.
+fn list_of<'s, 'c, T, P>(
+    input: &'s [u8],
+    context: &'c Context,
+    p: P,
+) -> IResult<&'s [u8], Result<Vec<T>, Error>>
+where
+    's: 'c,
+    P: Fn(&[u8]) -> IResult<&[u8], T>,
+{
+    if let (i, 0) = be_u32(input)? {
+        return Ok((i, Ok(vec![])));
+    }
+    let (i, list) = raw(input, context)?;
+    let elements = tlist(list.obj, context)
+        .and_then(|(_, list)| list.objs.iter().map(|o| p(o.obj).map(|(_, e)| e)).collect())
+        .map_err(|e| Error::parse("TList", 0, context.s, e));
+    Ok((i, elements))
+}
+
+/// Parse a pointer to the index of a tree, which may be null.
+/// Indices other than `TTreeIndex` are skipped. An index which cannot
+/// be parsed is returned as an error, rather than failing the whole
+/// tree, as it is not needed to read the branches of the tree.
+fn tree_index<'s, 'c>(
+    input: &'s [u8],
+    context: &'c Context,
+) -> IResult<&'s [u8], Result<Option<TreeIndex>, Error>>
+where
+    's: 'c,
+{
+    if let (i, 0) = be_u32(input)? {
+        return Ok((i, Ok(None)));
+    }
+    let (i, index) = raw(input, context)?;
+    if index.classinfo != "TTreeIndex" {
+        return Ok((i, Ok(None)));
+    }
+    let parsed = ttreeindex(index.obj)
+        .map(|(_, index)| Some(index))
+        .map_err(|e| Error::parse("TTreeIndex", 0, context.s, e));
+    Ok((i, parsed))
 }
 
-/// Parse a `Tree` from the given buffer. Usually used through `FileItem::parse_with`.
+/// Parse a `Tree` from the given buffer; used by `FileItem::as_tree`.
+/// The output is an `Error::Unsupported` if the tree contains
+/// branches of an unsupported class.
 #[allow(unused_variables, clippy::unnecessary_unwrap)]
-pub fn ttree<'s>(input: &'s [u8], context: &Context) -> IResult<&'s [u8], Tree> {
+pub(crate) fn ttree<'s>(
+    input: &'s [u8],
+    context: &Context,
+) -> IResult<&'s [u8], Result<Tree, Error>> {
     let _curried_raw = |i| raw(i, context);
     let none_or_u8_buf = |i: &'s [u8]| {
         switch!(i, peek!(be_u32),
@@ -129,7 +487,6 @@ pub fn ttree<'s>(input: &'s [u8], context: &Context) -> IResult<&'s [u8], Tree>
                                                 Some))
     };
     let grab_checked_byte_count = |i| length_data!(i, checked_byte_count);
-    let wrapped_tobjarray = |i: &'s[u8]| length_value!(i, checked_byte_count, call!(tobjarray, context));
     do_parse!(input,
               ver: be_u16 >>
               tnamed: length_value!(checked_byte_count, tnamed) >>
@@ -151,32 +508,24 @@ pub fn ttree<'s>(input: &'s [u8], context: &Context) -> IResult<&'s [u8], Tree>
               fmaxentryloop: be_i64 >>
               _fmaxvirtualsize: be_i64 >>
               _fautosave: be_i64 >>
-              _fautoflush: be_i64 >>
+              fautoflush: be_i64 >>
               festimate: be_i64 >>
-              _fclusterrangeend: cond!(fnclusterrange.is_some(),
+              fclusterrangeend: cond!(fnclusterrange.is_some(),
                                       preceded!(be_u8,
                                                 count!(be_i64, fnclusterrange.unwrap() as usize))) >>
-              _fclustersize: cond!(fnclusterrange.is_some(),
+              fclustersize: cond!(fnclusterrange.is_some(),
                                   preceded!(be_u8,
                                             count!(be_i64, fnclusterrange.unwrap() as usize))) >>
-              fbranches: wrapped_tobjarray >>
-              fleaves: wrapped_tobjarray >>
-              faliases: none_or_u8_buf >>
+              fbranches: call!(tbranches, context) >>
+              fleaves: call!(tleaves, context) >>
+              faliases: call!(list_of, context, crate::core::parsers::tnamed) >>
               findexvalues: tarrayd >>
               findex: tarrayi >>
-              ftreeindex: none_or_u8_buf >>
-              ffriends: none_or_u8_buf >>
+              ftreeindex: call!(tree_index, context) >>
+              ffriends: call!(list_of, context, tfriendelement) >>
               fuserinfo: none_or_u8_buf >>
               fbranchref: none_or_u8_buf >>
-              ({
-                  let fbranches = fbranches.iter()
-                      .map(|s| tbranch_hdr(s, context).unwrap().1)
-                      .collect();
-                  let fleaves = fleaves.into_iter()
-                      .map(|raw| tleaf(raw.obj, context, &raw.classinfo).unwrap().1)
-                      .collect();
-                  let ftreeindex = ftreeindex.map(Pointer);
-                  let ffriends = ffriends.map(Pointer);
+              (fbranches.map(|fbranches| {
                   let fuserinfo = fuserinfo.map(Pointer);
                   let fbranchref = fbranchref.map(Pointer);
                   Tree {ver,
@@ -193,6 +542,9 @@ pub fn ttree<'s>(input: &'s [u8], context: &Context) -> IResult<&'s [u8], Tree>
                         fmaxentries,
                         fmaxentryloop,
                         festimate,
+                        fautoflush,
+                        fclusterrangeend: fclusterrangeend.unwrap_or_default(),
+                        fclustersize: fclustersize.unwrap_or_default(),
                         fbranches,
                         fleaves,
                         faliases,
@@ -201,5 +553,159 @@ pub fn ttree<'s>(input: &'s [u8], context: &Context) -> IResult<&'s [u8], Tree>
                         ftreeindex,
                         ffriends,
                         fuserinfo,
-                        fbranchref } }))
+                        fbranchref,
+                        source: context.source.clone() } })))
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::path::Path;
+
+    use nom::number::complete::be_i32;
+
+    use super::*;
+    use crate::test_utils::{tnamed, tstring, with_byte_count};
+
+    #[test]
+    fn friend_element() {
+        let mut buf = vec![0, 2];
+        buf.extend(tnamed("calib", "calib.root"));
+        buf.extend(tstring("T"));
+        buf.push(0);
+        let (rest, friend) = tfriendelement(&buf).unwrap();
+        assert_eq!(rest, &[0]);
+        assert_eq!(friend.name(), "calib");
+        assert_eq!(friend.tree_name(), "T");
+        assert_eq!(friend.file_name(), Some("calib.root"));
+        assert!(friend.tree().is_err());
+    }
+
+    #[test]
+    fn unreadable_list() {
+        // A pointer to a `TList` of a future version
+        let mut list = vec![0xFF, 0xFF, 0xFF, 0xFF];
+        list.extend(b"TList\0");
+        list.extend(with_byte_count(vec![0, 99, 1, 2, 3]));
+        let mut buf = with_byte_count(list);
+        buf.push(7);
+        let context = Context {
+            source: Source::new(buf.clone()),
+            offset: 0,
+            s: &buf,
+        };
+        let (rest, friends) = list_of(&buf, &context, tfriendelement).unwrap();
+        assert_eq!(rest, &[7]);
+        match friends {
+            Err(Error::Parse { class, .. }) => assert_eq!(class, "TList"),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unreadable_index() {
+        // A `TTreeIndex` of a future version
+        let mut index = vec![0xFF, 0xFF, 0xFF, 0xFF];
+        index.extend(b"TTreeIndex\0");
+        index.extend(with_byte_count(vec![0, 3, 1, 2, 3]));
+        let mut buf = with_byte_count(index);
+        buf.push(7);
+        let context = Context {
+            source: Source::new(buf.clone()),
+            offset: 0,
+            s: &buf,
+        };
+        let (rest, index) = tree_index(&buf, &context).unwrap();
+        assert_eq!(rest, &[7]);
+        match index {
+            Err(Error::Parse { class, .. }) => assert_eq!(class, "TTreeIndex"),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn entry_for_unreadable_index() {
+        let path = Path::new("./src/test_data/sample-5.30.00-zlib.root");
+        let f = RootFile::new(path).await.unwrap();
+        let mut tree = f.get("sample").await.unwrap().as_tree().await.unwrap();
+        match tree.entry_for_index(1, 2) {
+            Err(Error::NotFound(_)) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        tree.ftreeindex = Err(Error::Parse {
+            class: "TTreeIndex".to_string(),
+            offset: 0,
+            msg: "Unexpected end of buffer".to_string(),
+        });
+        let tree = tree.at_key(42);
+        match tree.entry_for_index(1, 2) {
+            Err(Error::Parse { class, offset, .. }) => {
+                assert_eq!((class.as_str(), offset), ("TTreeIndex", 42))
+            }
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn friends_and_aliases() {
+        let dir = Path::new("./src/test_data");
+        let f = RootFile::new(dir.join("sample-5.30.00-zlib.root"))
+            .await
+            .unwrap();
+        let mut tree = f.get("sample").await.unwrap().as_tree().await.unwrap();
+        assert!(tree.friends().unwrap().is_empty());
+        let alias = |name: &str, title: &str| TNamed {
+            name: name.to_string(),
+            title: title.to_string(),
+        };
+        tree.faliases = Ok(vec![alias("int", "i4"), alias("other_int", "other.i4")]);
+        let friend = |name: &str, file_name: &str| Friend {
+            name: name.to_string(),
+            tree_name: "sample".to_string(),
+            file_name: file_name.to_string(),
+            tree: OnceCell::new(),
+        };
+        tree.ffriends = Ok(vec![
+            friend("other", "sample-6.10.05-zlib.root"),
+            friend("missing", "missing.root"),
+        ]);
+        // Friends are only opened once a branch is not found otherwise
+        let own = tree.open_branch("i4").await.unwrap();
+        assert!(tree.friends().unwrap()[0].tree().is_err());
+        assert!(tree.branch_by_name("other.i4").is_err());
+        assert!(tree.open_branch("other.nope").await.is_err());
+        match tree.friends().unwrap()[1].tree.get() {
+            Some(Err(Error::Io(_))) => {}
+            other => panic!("Unexpected result {:?}", other),
+        }
+        let other = tree.friends().unwrap()[0].tree().unwrap();
+
+        assert!(std::ptr::eq(own, tree.branch_by_name("int").unwrap()));
+        let friends = other.branch_by_name("i4").unwrap();
+        assert!(!std::ptr::eq(own, friends));
+        assert!(std::ptr::eq(
+            friends,
+            tree.branch_by_name("other.i4").unwrap()
+        ));
+        assert!(std::ptr::eq(
+            friends,
+            tree.branch_by_name("other_int").unwrap()
+        ));
+        assert_eq!(
+            tree.branch_by_name("other.i4")
+                .unwrap()
+                .entry(3, |i| be_i32(i))
+                .await
+                .unwrap(),
+            -12
+        );
+
+        // The reason why the friends could not be read is reported
+        // for branches which are not found otherwise
+        tree.ffriends = Err(Error::Unsupported("Friends".to_string()));
+        assert!(tree.branch_by_name("i4").is_ok());
+        match tree.branch_by_name("other.i4") {
+            Err(Error::Unsupported(what)) => assert_eq!(what, "Friends"),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
 }
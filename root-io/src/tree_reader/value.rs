@@ -0,0 +1,319 @@
+use nom::multi::count;
+use nom::number::complete::*;
+use nom::IResult;
+
+use crate::core::parsers::{parse_custom_mantissa, scaled_float, string};
+use crate::Error;
+
+/// A dynamically typed element of a branch or object. This allows to
+/// read branches and objects whose type is only known at runtime,
+/// e.g. in generic tools such as dumpers or converters.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    String(String),
+    /// Array with a fixed number of elements (e.g. `Int_t a[3]`)
+    Array(Vec<Value>),
+    /// Variable number of elements per entry (jagged array)
+    Vec(Vec<Value>),
+    /// An object with its data members in the order in which they
+    /// are stored; the members of base classes come first
+    Struct(Vec<(String, Value)>),
+    /// A null pointer to an object
+    Null,
+    /// A pointer to an object which was already read elsewhere in
+    /// the same buffer; it is not read again
+    Reference,
+}
+
+impl Value {
+    /// The data member `name` of a `Value::Struct`
+    pub fn member(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Struct(members) => members.iter().find(|(n, _)| n == name).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+}
+
+/// The on-disk encoding of a `Double32_t`. It is defined by the
+/// comment of the data member, e.g. `//[xmin,xmax,nbits]`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Double32 {
+    /// No range given; stored as a regular `f32`
+    Float,
+    /// Stored as exponent and a mantissa truncated to `nbits`
+    Mantissa(usize),
+    /// Stored as an `u32` scaled to the given range
+    Range { xmin: f64, factor: f64 },
+}
+
+impl Double32 {
+    /// Figure out the encoding from the comment (title) of a
+    /// `Double32_t` data member, following `TStreamerElement::GetRange`.
+    /// The range is the first pair of brackets containing a comma, as
+    /// the first pair may give the length of an array instead (e.g.
+    /// `//[fN][0,pi,12]`). A range which cannot be parsed is an
+    /// `Error::Unsupported` rather than silently read as `f32`.
+    pub(crate) fn from_title(title: &str) -> Result<Self, Error> {
+        let range = match range_of(title) {
+            Some(range) => range,
+            None => return Ok(Double32::Float),
+        };
+        let invalid = || {
+            Error::Unsupported(format!(
+                "Range `[{}]` of a `Double32_t` or `Float16_t` in comment `{}`",
+                range, title
+            ))
+        };
+        let mut parts = range.split(',');
+        let xmin = parts.next().and_then(range_value).ok_or_else(invalid)?;
+        let xmax = parts.next().and_then(range_value).ok_or_else(invalid)?;
+        let nbits = match parts.next() {
+            Some(nbits) => nbits.trim().parse::<u32>().map_err(|_| invalid())?,
+            None => 32,
+        };
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        let nbits = if (2..=32).contains(&nbits) { nbits } else { 32 };
+        if xmin < xmax {
+            let bigint = if nbits < 32 {
+                1u64 << nbits
+            } else {
+                0xffff_ffff
+            };
+            Ok(Double32::Range {
+                xmin,
+                factor: bigint as f64 / (xmax - xmin),
+            })
+        } else if nbits < 15 {
+            Ok(Double32::Mantissa(nbits as usize))
+        } else {
+            Ok(Double32::Float)
+        }
+    }
+
+    /// The encoding of a `Float16_t` data member. Without a range,
+    /// its mantissa is truncated to 12 bits.
+    pub(crate) fn float16_from_title(title: &str) -> Result<Self, Error> {
+        Ok(match Self::from_title(title)? {
+            Double32::Float => Double32::Mantissa(12),
+            encoding => encoding,
+        })
+    }
+
+    fn parse<'s>(&self, input: &'s [u8]) -> IResult<&'s [u8], f32> {
+        match *self {
+            Double32::Float => be_f32(input),
+            Double32::Mantissa(nbits) => parse_custom_mantissa(input, nbits),
+            Double32::Range { xmin, factor } => scaled_float(input, xmin, factor),
+        }
+    }
+}
+
+/// The content of the brackets giving the range in the comment of a
+/// `Double32_t` member, if any
+fn range_of(title: &str) -> Option<&str> {
+    let mut rest = title;
+    // ROOT looks at the first two pairs of brackets only
+    for _ in 0..2 {
+        let start = rest.find('[')?;
+        let len = rest[start + 1..].find(']')?;
+        let range = &rest[start + 1..start + 1 + len];
+        if range.contains(',') {
+            return Some(range);
+        }
+        rest = &rest[start + 1 + len..];
+    }
+    None
+}
+
+/// A bound of the range of a `Double32_t`; either a number or one of
+/// the multiples of pi understood by ROOT (`pi`, `2pi`, `pi/2`, ...)
+fn range_value(value: &str) -> Option<f64> {
+    use std::f64::consts::PI;
+    let value = value.trim().to_lowercase().replace(' ', "");
+    let (sign, abs) = match value.strip_prefix('-') {
+        Some(abs) => (-1.0, abs),
+        None => (1.0, value.as_str()),
+    };
+    let pi = match abs {
+        "pi" => PI,
+        "2pi" | "2*pi" | "twopi" => 2.0 * PI,
+        "pi/2" => PI / 2.0,
+        "pi/4" => PI / 4.0,
+        _ => return value.parse().ok(),
+    };
+    Some(sign * pi)
+}
+
+/// Type of a single element of a leaf
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Scalar {
+    Bool,
+    I8,
+    I16,
+    I32,
+    I64,
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+    Double32(Double32),
+    String,
+}
+
+impl Scalar {
+    /// The Rust type used for this scalar in generated code
+    pub(crate) fn type_name(&self) -> &'static str {
+        use self::Scalar::*;
+        match self {
+            Bool => "bool",
+            I8 => "i8",
+            I16 => "i16",
+            I32 => "i32",
+            I64 => "i64",
+            U8 => "u8",
+            U16 => "u16",
+            U32 => "u32",
+            U64 => "u64",
+            F32 | Double32(_) => "f32",
+            F64 => "f64",
+            String => "String",
+        }
+    }
+
+    pub(crate) fn parse<'s>(&self, input: &'s [u8]) -> IResult<&'s [u8], Value> {
+        use self::Scalar::*;
+        match self {
+            Bool => be_u8(input).map(|(i, v)| (i, Value::Bool(v != 0))),
+            I8 => be_i8(input).map(|(i, v)| (i, Value::I8(v))),
+            I16 => be_i16(input).map(|(i, v)| (i, Value::I16(v))),
+            I32 => be_i32(input).map(|(i, v)| (i, Value::I32(v))),
+            I64 => be_i64(input).map(|(i, v)| (i, Value::I64(v))),
+            U8 => be_u8(input).map(|(i, v)| (i, Value::U8(v))),
+            U16 => be_u16(input).map(|(i, v)| (i, Value::U16(v))),
+            U32 => be_u32(input).map(|(i, v)| (i, Value::U32(v))),
+            U64 => be_u64(input).map(|(i, v)| (i, Value::U64(v))),
+            F32 => be_f32(input).map(|(i, v)| (i, Value::F32(v))),
+            F64 => be_f64(input).map(|(i, v)| (i, Value::F64(v))),
+            Double32(d) => d.parse(input).map(|(i, v)| (i, Value::F32(v))),
+            String => string(input).map(|(i, v)| (i, Value::String(v))),
+        }
+    }
+}
+
+/// Layout of the elements of a leaf in each entry
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct ValueLayout {
+    pub(crate) scalar: Scalar,
+    /// Number of scalars per element; more than one for fixed size arrays
+    pub(crate) len: usize,
+    /// Does each entry contain a variable number of elements?
+    pub(crate) jagged: bool,
+}
+
+impl ValueLayout {
+    /// Parse a single element; for jagged layouts, an entry
+    /// consists of several such elements
+    pub(crate) fn parse_element<'s>(&self, input: &'s [u8]) -> IResult<&'s [u8], Value> {
+        if self.len == 1 {
+            self.scalar.parse(input)
+        } else {
+            let (input, values) = count(|i| self.scalar.parse(i), self.len)(input)?;
+            Ok((input, Value::Array(values)))
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn double32_from_title() {
+        let from_title = |title| Double32::from_title(title).unwrap();
+        assert_eq!(from_title("some comment"), Double32::Float);
+        assert_eq!(from_title("[0, 0, 8] x"), Double32::Mantissa(8));
+        assert_eq!(from_title("[0,0,20]"), Double32::Float);
+        assert_eq!(
+            Double32::float16_from_title("x").unwrap(),
+            Double32::Mantissa(12)
+        );
+        assert_eq!(
+            Double32::float16_from_title("[0,0,10]").unwrap(),
+            Double32::Mantissa(10)
+        );
+        assert_eq!(
+            from_title("[-1,1,8]"),
+            Double32::Range {
+                xmin: -1.0,
+                factor: 128.0
+            }
+        );
+        assert_eq!(
+            from_title("[0,10]"),
+            Double32::Range {
+                xmin: 0.0,
+                factor: f64::from(0xffff_ffff_u32) / 10.0
+            }
+        );
+        // The first brackets give the length of the array
+        assert_eq!(from_title("[fN][0, 0, 8]"), Double32::Mantissa(8));
+        assert_eq!(from_title("[fN] no range"), Double32::Float);
+    }
+
+    #[test]
+    fn double32_range_of_pi() {
+        use std::f64::consts::PI;
+        let range = |title| match Double32::from_title(title).unwrap() {
+            Double32::Range { xmin, factor } => (xmin, factor),
+            other => panic!("Unexpected encoding {:?}", other),
+        };
+        assert_eq!(range("[-pi,pi,12]"), (-PI, 4096.0 / (2.0 * PI)));
+        assert_eq!(range("[0, 2pi, 12]"), (0.0, 4096.0 / (2.0 * PI)));
+        assert_eq!(range("[-Pi/2, pi/2, 8]"), (-PI / 2.0, 256.0 / PI));
+        assert_eq!(range("[0,pi/4,8]"), (0.0, 256.0 / (PI / 4.0)));
+        // Ranges which cannot be parsed are not read as `f32`
+        for title in &["[0,x,8]", "[-pi,pi,many]", "[0,1,8,9]", "[0,3pi]"] {
+            assert!(Double32::from_title(title).is_err(), "{}", title);
+        }
+    }
+
+    #[test]
+    fn double32_values() {
+        let range = Double32::from_title("[-1,1,8]").unwrap();
+        assert_eq!(range.parse(&[0, 0, 0, 192]).unwrap().1, 0.5);
+        // 1.5 with 8 bits mantissa; 0b0_01111111_1000...
+        let mantissa = Double32::Mantissa(8);
+        assert_eq!(mantissa.parse(&[127, 0, 0x80]).unwrap().1, 1.5);
+        // The sign is stored in the bit after the mantissa
+        assert_eq!(mantissa.parse(&[127, 0x02, 0x80]).unwrap().1, -1.5);
+    }
+
+    #[test]
+    fn fixed_size_array() {
+        let layout = ValueLayout {
+            scalar: Scalar::I16,
+            len: 2,
+            jagged: false,
+        };
+        assert_eq!(
+            layout.parse_element(&[0, 1, 255, 254, 7]).unwrap(),
+            (&[7][..], Value::Array(vec![Value::I16(1), Value::I16(-2)]))
+        );
+    }
+}
@@ -0,0 +1,153 @@
+#![cfg(all(test, not(target_arch = "wasm32")))]
+use std::path::{Path, PathBuf};
+
+use futures::{StreamExt, TryStreamExt};
+use nom::number::complete::{be_i32, be_u32};
+
+use root_io::tree_reader::FromTree;
+use root_io::{Chain, Error, RootFile};
+
+fn test_file(name: &str) -> PathBuf {
+    Path::new("./src/test_data").join(name)
+}
+
+async fn read_i4(name: &str) -> Vec<i32> {
+    let f = RootFile::new(test_file(name)).await.unwrap();
+    let tree = f.item_by_name("sample").unwrap().as_tree().await.unwrap();
+    tree.branch_by_name("i4")
+        .unwrap()
+        .as_fixed_size_iterator(|i| be_i32(i))
+        .collect()
+        .await
+}
+
+#[tokio::test]
+async fn stream_across_files() {
+    let files = [
+        "sample-5.30.00-zlib.root",
+        "sample-6.10.05-uncompressed.root",
+        "sample-5.30.00-zlib.root",
+    ];
+    let first = read_i4(files[0]).await;
+    let second = read_i4(files[1]).await;
+    let chain = Chain::new("sample", files.iter().map(|f| test_file(f)));
+    assert_eq!(chain.n_files(), 3);
+
+    let data: Vec<i32> = chain
+        .stream("i4", |i| be_i32(i))
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(data, [&first[..], &second[..], &first[..]].concat());
+
+    let n = (first.len() + second.len()) as u64;
+    assert_eq!(
+        chain.first_entries().await.unwrap(),
+        vec![0, first.len() as u64, n, n + first.len() as u64]
+    );
+    assert_eq!(chain.n_entries().await.unwrap(), data.len() as u64);
+    assert_eq!(chain.locate(0).await.unwrap(), (0, 0));
+    assert_eq!(chain.locate(first.len() as u64 + 3).await.unwrap(), (1, 3));
+    assert!(chain.locate(data.len() as u64).await.is_err());
+    for entry in &[0, first.len() as u64, n + 2] {
+        let value = chain.entry("i4", *entry, |i| be_i32(i)).await.unwrap();
+        assert_eq!(value, data[*entry as usize]);
+    }
+}
+
+#[derive(Debug, PartialEq, FromTree)]
+struct Entry {
+    #[branch("i4")]
+    int: i32,
+    #[branch("Ai4", counter = "n")]
+    array: Vec<i32>,
+}
+
+#[tokio::test]
+async fn var_size_and_jagged_streams() {
+    let files = [
+        "sample-5.30.00-zlib.root",
+        "sample-6.10.05-uncompressed.root",
+    ];
+    let chain = Chain::new("sample", files.iter().map(|f| test_file(f)));
+    let counter: Vec<u32> = chain
+        .stream("n", |i| be_u32(i))
+        .try_collect()
+        .await
+        .unwrap();
+    let var_size: Vec<Vec<i32>> = chain
+        .var_size_stream("Ai4", |i| be_i32(i), &counter)
+        .try_collect()
+        .await
+        .unwrap();
+    let jagged: Vec<Vec<i32>> = chain
+        .jagged_stream("Ai4", |i| be_i32(i))
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(var_size.len(), counter.len());
+    assert_eq!(var_size, jagged);
+    for (array, n) in var_size.iter().zip(&counter) {
+        assert_eq!(array.len(), *n as usize);
+    }
+    // The first entry of the second file
+    let entry = chain.first_entries().await.unwrap()[1];
+    let value = chain
+        .var_size_entry("Ai4", entry, |i| be_i32(i), counter[entry as usize])
+        .await
+        .unwrap();
+    assert_eq!(value, var_size[entry as usize]);
+
+    let entries: Vec<Entry> = chain.stream_from_tree().try_collect().await.unwrap();
+    let ints: Vec<i32> = chain
+        .stream("i4", |i| be_i32(i))
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(entries.len(), ints.len());
+    for ((entry, int), array) in entries.iter().zip(ints).zip(var_size) {
+        assert_eq!((entry.int, &entry.array), (int, &array));
+    }
+}
+
+#[tokio::test]
+async fn files_are_opened_lazily() {
+    let files = vec![
+        test_file("sample-5.30.00-zlib.root"),
+        test_file("does-not-exist.root"),
+    ];
+    let chain = Chain::new("sample", files);
+    // Only the first file is needed
+    assert_eq!(chain.locate(1).await.unwrap(), (0, 1));
+    let results: Vec<_> = chain.stream("i4", |i| be_i32(i)).collect().await;
+    assert!(results[..results.len() - 1].iter().all(|r| r.is_ok()));
+    assert!(results.last().unwrap().is_err());
+}
+
+#[tokio::test]
+async fn unreadable_entries() {
+    #[derive(Debug, FromTree)]
+    struct Unreadable {
+        #[branch("i4", parser = |i| Err(nom::Err::Failure((i, nom::error::ErrorKind::Verify))))]
+        _int: i32,
+    }
+    let files = ["sample-5.30.00-zlib.root", "sample-6.10.05-zlib.root"];
+    let chain = Chain::new("sample", files.iter().map(|f| test_file(f)));
+    let entries: Vec<_> = chain.stream_from_tree::<Unreadable>().collect().await;
+    // An error for every entry of every file instead of a panic
+    assert_eq!(entries.len() as u64, chain.n_entries().await.unwrap());
+    assert!(entries.iter().all(|e| e.is_err()));
+}
+
+#[tokio::test]
+async fn incompatible_trees() {
+    let chain = Chain::new(
+        "events",
+        vec![test_file("HZZ.root"), test_file("Zmumu.root")],
+    );
+    assert!(chain.tree(0).await.is_ok());
+    match chain.tree(1).await {
+        Err(Error::Incompatible(_)) => {}
+        other => panic!("Unexpected result {:?}", other.map(|_| ())),
+    }
+}
@@ -0,0 +1,114 @@
+#![cfg(all(test, not(target_arch = "wasm32")))]
+use std::path::Path;
+
+use futures::{StreamExt, TryStreamExt};
+use nom::number::complete::*;
+
+use root_io::{
+    tree_reader::{FromTree, Tree},
+    RootFile,
+};
+
+#[derive(Debug, PartialEq, FromTree)]
+struct Sample {
+    n: i32,
+    #[branch("i4")]
+    int: i32,
+    b: bool,
+    #[branch("ai4")]
+    array: [i32; 3],
+    #[branch("af4")]
+    tuple: (f32, f32, f32),
+    #[branch("str")]
+    string: String,
+    #[branch("u8", parser = |i| be_u64(i).map(|(i, v)| (i, v * 2)))]
+    doubled: u64,
+    #[branch("Ai4", counter = "n")]
+    counted: Vec<i32>,
+    #[branch("Af8", counter = "n", parser = |i| be_f64(i))]
+    counted_with_parser: Vec<f64>,
+    #[branch("Ai4", jagged)]
+    jagged: Vec<i32>,
+}
+
+async fn sample_tree() -> Tree {
+    let path = Path::new("./src/test_data/sample-5.30.00-zlib.root");
+    let f = RootFile::new(path).await.expect("Failed to open file");
+    f.items()[0].as_tree().await.unwrap()
+}
+
+#[tokio::test]
+async fn derive_from_tree() {
+    let tree = sample_tree().await;
+    let rows: Vec<Sample> = Sample::stream_from_tree(&tree)
+        .await
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 30);
+
+    let column = |name: &str| {
+        tree.branch_by_name(name)
+            .unwrap()
+            .as_fixed_size_iterator(|i| be_i32(i))
+            .collect::<Vec<_>>()
+    };
+    let n = column("n").await;
+    let int = column("i4").await;
+    let counter: Vec<_> = n.iter().map(|n| *n as u32).collect();
+    let counted: Vec<_> = tree
+        .branch_by_name("Ai4")
+        .unwrap()
+        .as_var_size_iterator(|i| be_i32(i), &counter)
+        .collect()
+        .await;
+    let u8s: Vec<_> = tree
+        .branch_by_name("u8")
+        .unwrap()
+        .as_fixed_size_iterator(|i| be_u64(i))
+        .collect()
+        .await;
+    for (i, row) in rows.iter().enumerate() {
+        assert_eq!(row.n, n[i]);
+        assert_eq!(row.int, int[i]);
+        assert_eq!(row.array, [int[i] + 1, int[i] + 2, int[i] + 3]);
+        assert_eq!(row.string, format!("hey-{}", i));
+        assert_eq!(row.doubled, 2 * u8s[i]);
+        assert_eq!(row.counted, counted[i]);
+        assert_eq!(row.counted.len(), n[i] as usize);
+        assert_eq!(row.counted_with_parser.len(), n[i] as usize);
+        assert_eq!(row.jagged, row.counted);
+    }
+}
+
+#[tokio::test]
+async fn derive_from_tree_missing_branch() {
+    #[derive(FromTree)]
+    struct Missing {
+        #[branch("does-not-exist")]
+        _x: i32,
+    }
+    let tree = sample_tree().await;
+    assert!(Missing::stream_from_tree(&tree).await.is_err());
+}
+
+#[tokio::test]
+async fn derive_from_tree_unreadable_entries() {
+    #[derive(Debug, FromTree)]
+    struct Unreadable {
+        #[branch("n")]
+        _n: i32,
+        #[branch("i4", parser = |i| Err(nom::Err::Failure((i, nom::error::ErrorKind::Verify))))]
+        _int: i32,
+    }
+    let tree = sample_tree().await;
+    let rows: Vec<_> = Unreadable::stream_from_tree(&tree)
+        .await
+        .unwrap()
+        .collect()
+        .await;
+    // Every entry yields an error instead of panicking
+    assert_eq!(rows.len(), 30);
+    assert!(rows.iter().all(|row| row.is_err()));
+}
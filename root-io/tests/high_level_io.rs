@@ -2,8 +2,9 @@
 use std::path::PathBuf;
 
 use root_io::*;
+use root_io::tree_reader::Value;
 
-const TEST_FILES: &[&str]= &[
+const TEST_FILES: &[&str] = &[
     "./src/test_data/simple.root",
     "./src/test_data/HZZ.root",
     "./src/test_data/HZZ-lz4.root",
@@ -59,6 +60,10 @@ fn local_paths() -> Vec<PathBuf> {
 #[cfg(not(target_arch="wasm32"))]
 mod local {
     use super::*;
+    use std::collections::HashMap;
+    use std::ffi::OsStr;
+    use std::path::Path;
+    use std::process::Command;
     use tokio;
 
     #[tokio::test]
@@ -77,6 +82,188 @@ mod local {
         }
     }
 
+    /// The `.rlib`s of this crate and its dependencies by their crate
+    /// names, as reported by cargo when building this crate
+    fn rlibs() -> HashMap<String, PathBuf> {
+        let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+        let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+        let output = Command::new(cargo)
+            .args(["build", "--lib", "--message-format=json", "--manifest-path"])
+            .arg(manifest)
+            .output()
+            .expect("Failed to run cargo");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let mut rlibs = HashMap::new();
+        for line in output.stdout.split(|b| *b == b'\n') {
+            let msg: serde_json::Value = match serde_json::from_slice(line) {
+                Ok(msg) => msg,
+                Err(_) => continue,
+            };
+            if msg["reason"] != "compiler-artifact" {
+                continue;
+            }
+            let name = msg["target"]["name"].as_str().unwrap().replace('-', "_");
+            let rlib = msg["filenames"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter_map(|f| f.as_str())
+                .find(|f| f.ends_with(".rlib"));
+            if let Some(rlib) = rlib {
+                rlibs.insert(name, PathBuf::from(rlib));
+            }
+        }
+        rlibs
+    }
+
+    /// Compile the Rust code generated from the streamer info of each
+    /// file against this crate
+    #[tokio::test]
+    async fn generated_rust_compiles() {
+        let rlibs = rlibs();
+        let rlib = |name| {
+            rlibs
+                .get(name)
+                .unwrap_or_else(|| panic!("Cargo did not build `{}`", name))
+        };
+        // Unlike this crate, dependencies are not copied out of `deps`
+        let deps = rlib("nom").parent().unwrap();
+        let out_dir = deps.join("generated-parsers");
+        std::fs::create_dir_all(&out_dir).unwrap();
+        let mut paths: Vec<_> = std::fs::read_dir("./src/test_data")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|p| p.extension() == Some(OsStr::new("root")))
+            .collect();
+        paths.sort();
+        let mut lib = String::new();
+        for (i, p) in paths.iter().enumerate() {
+            let f = RootFile::new(p.as_path())
+                .await
+                .expect("Failed to open file");
+            let mut s = String::new();
+            f.streamer_info_as_rust(&mut s).await.unwrap();
+            std::fs::write(out_dir.join(format!("file_{}.rs", i)), s).unwrap();
+            lib += &format!("// {}\nmod file_{};\n", p.display(), i);
+        }
+        std::fs::write(out_dir.join("lib.rs"), lib).unwrap();
+
+        let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+        let output = Command::new(rustc)
+            .args(["--edition=2018", "--crate-type=lib", "--emit=metadata"])
+            .args(["--crate-name", "generated_parsers"])
+            .arg("-L")
+            .arg(format!("dependency={}", deps.display()))
+            .arg("--extern")
+            .arg(format!("root_io={}", rlib("root_io").display()))
+            .arg("--extern")
+            .arg(format!("nom={}", rlib("nom").display()))
+            .arg("--out-dir")
+            .arg(&out_dir)
+            .arg(out_dir.join("lib.rs"))
+            .output()
+            .expect("Failed to run rustc");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    #[tokio::test]
+    async fn root_file_from_memory() {
+        for p in local_paths() {
+            println!("{:?}", p);
+            let on_disk = RootFile::new(p.as_path()).await.expect("Failed to open file");
+            let buf = std::fs::read(&p).unwrap();
+            let in_memory = RootFile::new(buf).await.expect("Failed to open buffer");
+            let (mut expected, mut s) = (String::new(), String::new());
+            on_disk.streamer_info_as_yaml(&mut expected).await.unwrap();
+            in_memory.streamer_info_as_yaml(&mut s).await.unwrap();
+            assert_eq!(s, expected);
+            let names = |f: &RootFile| f.items().iter().map(|i| i.name()).collect::<Vec<_>>();
+            assert_eq!(names(&in_memory), names(&on_disk));
+            // The test data is not modified while it is mapped
+            let source = unsafe { Source::mmap(&p) }.expect("Failed to map file");
+            let mapped = RootFile::new(source).await.expect("Failed to map file");
+            assert_eq!(names(&mapped), names(&on_disk));
+        }
+    }
+
+    #[tokio::test]
+    async fn trees_as_values() {
+        for p in local_paths() {
+            println!("{:?}", p);
+            let f = RootFile::new(p.as_path()).await.expect("Failed to open file");
+            let streamers = f.streamers().await.unwrap();
+            for item in f.items().iter().filter(|i| i.class_name() == "TTree") {
+                let value = item.as_value(&streamers).await.unwrap();
+                assert_eq!(value.member("fName"), Some(&Value::String(item.object_name().to_string())));
+                let branches = match value.member("fBranches") {
+                    Some(Value::Vec(branches)) => branches,
+                    other => panic!("Unexpected branches {:?}", other),
+                };
+                // Trees of some older files are not supported by `as_tree`
+                let tree = match item.as_tree().await {
+                    Ok(tree) => tree,
+                    Err(_) => continue,
+                };
+                assert_eq!(value.member("fEntries"), Some(&Value::I64(tree.n_entries() as i64)));
+                for branch in branches {
+                    match branch.member("fName") {
+                        Some(Value::String(name)) => assert!(tree.branch_by_name(name).is_ok()),
+                        other => panic!("Unexpected branch name {:?}", other),
+                    }
+                }
+            }
+        }
+    }
+
+    /// A source which counts the requests made to it
+    #[derive(Debug, Default)]
+    struct CountingSource {
+        data: Vec<u8>,
+        requests: std::sync::atomic::AtomicUsize,
+    }
+
+    impl DataSource for CountingSource {
+        fn fetch(&self, start: u64, len: u64) -> SourceFuture<'_, Result<Vec<u8>, Error>> {
+            self.requests.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            self.data.fetch(start, len)
+        }
+    }
+
+    #[tokio::test]
+    async fn root_file_from_custom_source() {
+        let source = std::sync::Arc::new(CountingSource {
+            data: std::fs::read(TEST_FILES[0]).unwrap(),
+            ..Default::default()
+        });
+        let f = RootFile::new(source.clone()).await.expect("Failed to open file");
+        assert!(!f.items().is_empty());
+        assert!(source.requests.load(std::sync::atomic::Ordering::Relaxed) > 0);
+    }
+
+    fn assert_send<T: Send>(_: T) {}
+
+    /// Files can be read from a task spawned on a multi-threaded
+    /// executor; this only needs to compile
+    #[allow(dead_code)]
+    async fn futures_and_streams_are_send() {
+        assert_send(RootFile::new(std::path::Path::new(TEST_FILES[0])));
+        let f = RootFile::new(std::path::Path::new(TEST_FILES[0])).await.unwrap();
+        let tree = f.items()[0].as_tree().await.unwrap();
+        assert_send(f.items()[0].as_tree());
+        assert_send(tree.open_branch("one"));
+        assert_send(tree.branch_by_name("one").unwrap().as_fixed_size_iterator(
+            |i| nom::number::complete::be_i32::<(&[u8], nom::error::ErrorKind)>(i),
+        ));
+    }
+
     #[cfg(not(target_os="macos"))]
     #[tokio::test]
     async fn root_file_methods_esd() {
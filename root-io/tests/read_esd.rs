@@ -1,114 +1,46 @@
 #![cfg(test)]
 
-use failure::Error;
 use futures::prelude::*;
-use nom::number::complete::*;
-use nom::sequence::tuple;
 
 use root_io::{
     core::parsers::{parse_custom_mantissa, parse_tobjarray_of_tnameds},
-    stream_zip,
-    tree_reader::Tree,
+    tree_reader::{FromTree, Tree},
     RootFile,
 };
 
 /// A model for the / a subset of the ESD data
-#[derive(Debug)]
+#[derive(Debug, FromTree)]
 struct Model {
+    #[branch("PrimaryVertex.AliVertex.fPosition[3]")]
     primaryvertex_alivertex_fposition: (f32, f32, f32),
+    #[branch("PrimaryVertex.AliVertex.fNContributors")]
     primaryvertex_alivertex_fncontributors: i32,
+    #[branch("AliESDRun.fRunNumber")]
     aliesdrun_frunnumber: i32,
+    #[branch("AliESDRun.fTriggerClasses", parser = parse_tobjarray_of_tnameds)]
     aliesdrun_ftriggerclasses: Vec<String>,
+    #[branch("AliESDHeader.fTriggerMask")]
     aliesdheader_ftriggermask: u64,
+    #[branch("Tracks.fX", counter = "Tracks")]
     tracks_fx: Vec<f32>,
+    #[branch("Tracks.fP[5]", counter = "Tracks")]
     tracks_fp: Vec<(f32, f32, f32, f32, f32)>,
+    #[branch("Tracks.fAlpha", counter = "Tracks")]
     tracks_falpha: Vec<f32>,
+    #[branch("Tracks.fFlags", counter = "Tracks")]
     tracks_fflags: Vec<u64>,
+    #[branch("Tracks.fITSchi2", counter = "Tracks", parser = |i| parse_custom_mantissa(i, 8))]
     tracks_fitschi2: Vec<f32>,
+    #[branch("Tracks.fITSncls", counter = "Tracks")]
     tracks_fitsncls: Vec<i8>,
+    #[branch("Tracks.fITSClusterMap", counter = "Tracks")]
     tracks_fitsclustermap: Vec<u8>,
+    #[branch("Tracks.fTPCncls", counter = "Tracks")]
     tracks_ftpcncls: Vec<u16>,
+    #[branch("Tracks.fTPCchi2", counter = "Tracks", parser = |i| parse_custom_mantissa(i, 8))]
     tracks_ftpcchi2: Vec<f32>,
 }
 
-impl Model {
-    async fn stream_from_tree(t: &Tree) -> Result<impl Stream<Item = Self>, Error> {
-        let track_counter: Vec<_> = t
-            .branch_by_name("Tracks")?
-            .as_fixed_size_iterator(|i| be_u32(i))
-            .collect::<Vec<_>>()
-            .await;
-        let s = stream_zip!(
-            t.branch_by_name("AliESDRun.fRunNumber")?
-                .as_fixed_size_iterator(|i| be_i32(i)),
-            t.branch_by_name("AliESDRun.fTriggerClasses")?
-                .as_fixed_size_iterator(parse_tobjarray_of_tnameds),
-            t.branch_by_name("AliESDHeader.fTriggerMask")?
-                .as_fixed_size_iterator(|i| be_u64(i)),
-            t.branch_by_name("PrimaryVertex.AliVertex.fPosition[3]")?
-                .as_fixed_size_iterator(|i| tuple((be_f32, be_f32, be_f32))(i)),
-            t.branch_by_name("PrimaryVertex.AliVertex.fNContributors")?
-                .as_fixed_size_iterator(|i| be_i32(i)),
-            t.branch_by_name("Tracks.fX")?
-                .as_var_size_iterator(|i| be_f32(i), &track_counter),
-            t.branch_by_name("Tracks.fP[5]")?.as_var_size_iterator(
-                |i| tuple((be_f32, be_f32, be_f32, be_f32, be_f32))(i),
-                &track_counter
-            ),
-            t.branch_by_name("Tracks.fAlpha")?
-                .as_var_size_iterator(|i| be_f32(i), &track_counter),
-            t.branch_by_name("Tracks.fFlags")?
-                .as_var_size_iterator(|i| be_u64(i), &track_counter),
-            t.branch_by_name("Tracks.fITSchi2")?
-                .as_var_size_iterator(|i| parse_custom_mantissa(i, 8), &track_counter),
-            t.branch_by_name("Tracks.fITSncls")?
-                .as_var_size_iterator(|i| be_i8(i), &track_counter),
-            t.branch_by_name("Tracks.fITSClusterMap")?
-                .as_var_size_iterator(|i| be_u8(i), &track_counter),
-            t.branch_by_name("Tracks.fTPCncls")?
-                .as_var_size_iterator(|i| be_u16(i), &track_counter),
-            t.branch_by_name("Tracks.fTPCchi2")?
-                .as_var_size_iterator(|i| parse_custom_mantissa(i, 8), &track_counter),
-        )
-        .map(
-            |(
-                aliesdrun_frunnumber,
-                aliesdrun_ftriggerclasses,
-                aliesdheader_ftriggermask,
-                primaryvertex_alivertex_fposition,
-                primaryvertex_alivertex_fncontributors,
-                tracks_fx,
-                tracks_fp,
-                tracks_falpha,
-                tracks_fflags,
-                tracks_fitschi2,
-                tracks_fitsncls,
-                tracks_fitsclustermap,
-                tracks_ftpcncls,
-                tracks_ftpcchi2,
-            )| {
-                Self {
-                    aliesdrun_frunnumber,
-                    aliesdrun_ftriggerclasses,
-                    aliesdheader_ftriggermask,
-                    primaryvertex_alivertex_fposition,
-                    primaryvertex_alivertex_fncontributors,
-                    tracks_fx,
-                    tracks_fp,
-                    tracks_falpha,
-                    tracks_fflags,
-                    tracks_fitschi2,
-                    tracks_fitsncls,
-                    tracks_fitsclustermap,
-                    tracks_ftpcchi2,
-                    tracks_ftpcncls,
-                }
-            },
-        );
-        Ok(s)
-    }
-}
-
 #[cfg(target_arch = "wasm32")]
 mod wasm {
     use super::*;
@@ -161,7 +93,7 @@ mod x64 {
 }
 
 async fn test_branch_iterators(tree: &Tree) {
-    let mut schema_iter = Box::pin(Model::stream_from_tree(tree).await.unwrap());
+    let mut schema_iter = Model::stream_from_tree(tree).await.unwrap();
 
     let mut cnt = 0;
     let mut aliesdrun_frunnumber = 0;
@@ -179,7 +111,7 @@ async fn test_branch_iterators(tree: &Tree) {
     let mut tracks_ftpcchi2: Vec<f32> = vec![];
     let mut tracks_ftpcncls: Vec<u16> = vec![];
 
-    while let Some(event) = schema_iter.next().await {
+    while let Some(event) = schema_iter.try_next().await.unwrap() {
         cnt += 1;
         aliesdrun_frunnumber += event.aliesdrun_frunnumber;
         aliesdheader_ftriggermask += event.aliesdheader_ftriggermask;
@@ -0,0 +1,357 @@
+#![cfg(all(test, not(target_arch = "wasm32")))]
+//! Reading remote files from a local stand-in for an HTTP server
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures::StreamExt;
+use nom::number::complete::be_i32;
+use reqwest::Url;
+
+use root_io::{CachedSource, Error, HttpOptions, RootFile, Source};
+
+const FILE: &str = "./src/test_data/sample-5.30.00-zlib.root";
+
+/// How the server answers a request for several ranges
+#[derive(Clone, Copy)]
+enum Ranges {
+    /// With a `multipart/byteranges` response
+    Multi,
+    /// With only the first range
+    First,
+    /// With the whole file
+    Ignored,
+    /// With the first range but without its last byte
+    Short,
+}
+
+/// The file served by `serve_file`. Tests may change it while it is
+/// being served.
+struct Served {
+    data: Vec<u8>,
+    etag: String,
+    /// If false, connections are closed without a response
+    online: bool,
+    /// Number of further requests answered with `503 Service Unavailable`
+    failures: usize,
+    /// If set, requests are not answered until after this time
+    delay: Option<Duration>,
+}
+
+/// Serve the file at `path` in a background thread. Returns its url
+/// and the log of the `Range` headers of all requests.
+fn serve(path: &str, ranges: Ranges) -> (Url, Arc<Mutex<Vec<String>>>) {
+    let served = Served {
+        data: std::fs::read(path).unwrap(),
+        etag: "\"1\"".to_string(),
+        online: true,
+        failures: 0,
+        delay: None,
+    };
+    serve_file(Arc::new(Mutex::new(served)), ranges)
+}
+
+/// Serve a file in a background thread. Returns its url and the log
+/// of all requests; `HEAD` requests are logged as "HEAD", others by
+/// their `Range` header.
+fn serve_file(served: Arc<Mutex<Served>>, ranges: Ranges) -> (Url, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/file.root", listener.local_addr().unwrap());
+    let log = Arc::new(Mutex::new(vec![]));
+    let requests = log.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let (method, range) = read_request(&stream);
+            let mut served = served.lock().unwrap();
+            if !served.online {
+                continue;
+            }
+            if let Some(delay) = served.delay {
+                requests.lock().unwrap().push(range);
+                thread::sleep(delay);
+                continue;
+            }
+            if served.failures > 0 {
+                served.failures -= 1;
+                requests.lock().unwrap().push(range);
+                let rsp = "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+                stream.write_all(rsp.as_bytes()).unwrap();
+            } else if method == "HEAD" {
+                requests.lock().unwrap().push(method);
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nETag: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    served.etag,
+                    served.data.len()
+                )
+                .unwrap();
+            } else {
+                requests.lock().unwrap().push(range.clone());
+                respond(&mut stream, &served.data, &range, ranges);
+            }
+        }
+    });
+    (Url::parse(&url).unwrap(), log)
+}
+
+/// Read the request and return its method and the value of its
+/// `Range` header
+fn read_request(stream: &TcpStream) -> (String, String) {
+    let mut method = String::new();
+    let mut range = String::new();
+    for line in BufReader::new(stream).lines() {
+        let line = line.unwrap();
+        if line.is_empty() {
+            break;
+        }
+        if method.is_empty() {
+            method = line.split(' ').next().unwrap_or_default().to_string();
+        }
+        if let Some(value) = line.to_lowercase().strip_prefix("range: bytes=") {
+            range = value.to_string();
+        }
+    }
+    (method, range)
+}
+
+fn respond(stream: &mut TcpStream, data: &[u8], range: &str, ranges: Ranges) {
+    let requested: Vec<(usize, usize)> = range
+        .split(',')
+        .filter_map(|r| {
+            let mut bounds = r.trim().splitn(2, '-').map(|b| b.parse().ok());
+            Some((bounds.next()??, bounds.next()??))
+        })
+        .collect();
+    let content_range = |(first, last): (usize, usize)| {
+        format!("Content-Range: bytes {}-{}/{}\r\n", first, last, data.len())
+    };
+    let (status, headers, body) = match (ranges, requested.as_slice()) {
+        (Ranges::Ignored, _) | (_, []) => ("200 OK", String::new(), data.to_vec()),
+        (Ranges::Multi, [_, _, ..]) => {
+            let mut body = vec![];
+            for r in &requested {
+                body.extend(b"\r\n--BOUNDARY\r\n");
+                body.extend(content_range(*r).as_bytes());
+                body.extend(b"\r\n");
+                body.extend(&data[r.0..=r.1]);
+            }
+            body.extend(b"\r\n--BOUNDARY--\r\n");
+            let headers = "Content-Type: multipart/byteranges; boundary=BOUNDARY\r\n";
+            ("206 Partial Content", headers.to_string(), body)
+        }
+        (Ranges::Short, [first, ..]) => (
+            "206 Partial Content",
+            content_range(*first),
+            data[first.0..first.1].to_vec(),
+        ),
+        (_, [first, ..]) => (
+            "206 Partial Content",
+            content_range(*first),
+            data[first.0..=first.1].to_vec(),
+        ),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        headers,
+        body.len()
+    )
+    .unwrap();
+    stream.write_all(&body).unwrap();
+}
+
+#[tokio::test]
+async fn fetch_many_multi_range() {
+    let data = std::fs::read(FILE).unwrap();
+    let ranges = [(1000, 10), (0, 4), (1010, 5), (2000, 100)];
+    for mode in &[Ranges::Multi, Ranges::First] {
+        let (url, log) = serve(FILE, *mode);
+        let source = Source::new(url);
+        let bufs = source.fetch_many(&ranges).await.unwrap();
+        for ((start, len), buf) in ranges.iter().zip(bufs) {
+            assert_eq!(buf, &data[*start as usize..(start + len) as usize]);
+        }
+        let requests = log.lock().unwrap().clone();
+        match mode {
+            // Adjacent ranges are merged
+            Ranges::Multi => assert_eq!(requests, vec!["0-3,1000-1014,2000-2099"]),
+            // After a failed multi-range request, ranges are requested one by one
+            _ => assert_eq!(requests.len(), 4),
+        }
+    }
+
+    // Further requests avoid multi-range requests if they failed once
+    let (url, log) = serve(FILE, Ranges::First);
+    let source = Source::new(url);
+    source.fetch_many(&ranges).await.unwrap();
+    source.fetch_many(&ranges).await.unwrap();
+    assert_eq!(log.lock().unwrap().len(), 4 + 3);
+}
+
+#[tokio::test]
+async fn invalid_responses() {
+    let options = HttpOptions {
+        retries: 2,
+        backoff: Duration::from_millis(1),
+        ..Default::default()
+    };
+    // The whole file instead of the requested range is an error and
+    // not retried
+    let (url, log) = serve(FILE, Ranges::Ignored);
+    let source = Source::remote(url, options.clone()).unwrap();
+    match source.fetch(0, 4).await {
+        Err(Error::Response { range, .. }) => assert_eq!(range, "0-3"),
+        other => panic!("Unexpected result {:?}", other),
+    }
+    assert_eq!(log.lock().unwrap().len(), 1);
+
+    // Incomplete responses are retried
+    let (url, log) = serve(FILE, Ranges::Short);
+    let source = Source::remote(url, options).unwrap();
+    assert!(source.fetch(0, 4).await.is_err());
+    assert_eq!(log.lock().unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn retries_and_timeouts() {
+    let served = Arc::new(Mutex::new(Served {
+        data: std::fs::read(FILE).unwrap(),
+        etag: "\"1\"".to_string(),
+        online: true,
+        failures: 2,
+        delay: None,
+    }));
+    let (url, log) = serve_file(served.clone(), Ranges::Multi);
+    let options = HttpOptions {
+        timeout: Some(Duration::from_millis(200)),
+        retries: 3,
+        backoff: Duration::from_millis(10),
+        ..Default::default()
+    };
+    let source = Source::remote(url, options).unwrap();
+    // Two failures and a success
+    assert_eq!(source.fetch(0, 4).await.unwrap(), b"root");
+    assert_eq!(log.lock().unwrap().len(), 3);
+
+    // Giving up after three retries, with a delay of 10, 20, and 40ms
+    served.lock().unwrap().failures = 5;
+    let start = Instant::now();
+    assert!(source.fetch(0, 4).await.is_err());
+    assert!(start.elapsed() >= Duration::from_millis(70));
+    assert_eq!(log.lock().unwrap().len(), 3 + 4);
+
+    // Requests time out
+    {
+        let mut served = served.lock().unwrap();
+        served.failures = 0;
+        served.delay = Some(Duration::from_secs(5));
+    }
+    let start = Instant::now();
+    match source.fetch(0, 4).await {
+        Err(Error::Http(e)) => assert!(e.is_timeout()),
+        other => panic!("Unexpected result {:?}", other),
+    }
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn read_remote_tree() {
+    let local = RootFile::new(std::path::Path::new(FILE)).await.unwrap();
+    let local = local.items()[0].as_tree().await.unwrap();
+    let expected: Vec<i32> = local
+        .branch_by_name("i4")
+        .unwrap()
+        .as_fixed_size_iterator(|i| be_i32(i))
+        .collect()
+        .await;
+
+    let (url, log) = serve(FILE, Ranges::Multi);
+    let f = RootFile::new(url).await.unwrap();
+    let tree = f.items()[0].as_tree().await.unwrap();
+    let n_requests = log.lock().unwrap().len();
+    let clusters: Vec<_> = tree
+        .read_clusters(&["i4", "f8", "Ai4"])
+        .unwrap()
+        .collect()
+        .await;
+    // All baskets of the only cluster are read with one request
+    assert_eq!(clusters.len(), 1);
+    assert_eq!(log.lock().unwrap().len(), n_requests + 1);
+    let cluster = clusters[0].as_ref().unwrap();
+    assert_eq!(cluster.fixed_size("i4", |i| be_i32(i)).unwrap(), expected);
+}
+
+#[tokio::test]
+async fn cached_remote_file() {
+    let served = Arc::new(Mutex::new(Served {
+        data: std::fs::read(FILE).unwrap(),
+        etag: "\"1\"".to_string(),
+        online: true,
+        failures: 0,
+        delay: None,
+    }));
+    let (url, log) = serve_file(served.clone(), Ranges::Multi);
+    let dir = std::env::temp_dir().join(format!("root-io-cache-{}", std::process::id()));
+    let options = HttpOptions {
+        retries: 2,
+        backoff: Duration::from_millis(1),
+        ..Default::default()
+    };
+    let read_i4 = || async {
+        let remote = Source::remote(url.clone(), options.clone()).unwrap();
+        let source = CachedSource::with_dir(remote, &dir).unwrap();
+        let f = RootFile::new(source).await.unwrap();
+        let tree = f.items()[0].as_tree().await.unwrap();
+        tree.branch_by_name("i4")
+            .unwrap()
+            .as_fixed_size_iterator(|i| be_i32(i))
+            .collect::<Vec<_>>()
+            .await
+    };
+    let expected: Vec<i32> = (-15..15).collect();
+    let n_requests = |log: &Arc<Mutex<Vec<String>>>| {
+        let log = log.lock().unwrap();
+        // Failed requests are logged without a range
+        log.iter().filter(|r| !r.is_empty() && *r != "HEAD").count()
+    };
+
+    assert_eq!(read_i4().await, expected);
+    let fetched = n_requests(&log);
+    assert!(fetched > 0);
+
+    // A second run only validates the cached blocks
+    assert_eq!(read_i4().await, expected);
+    assert_eq!(n_requests(&log), fetched);
+
+    // Without a connection, the cached blocks are used
+    served.lock().unwrap().online = false;
+    assert_eq!(read_i4().await, expected);
+    assert_eq!(n_requests(&log), fetched);
+
+    // A changed file is fetched again
+    {
+        let mut served = served.lock().unwrap();
+        served.online = true;
+        served.etag = "\"2\"".to_string();
+    }
+    assert_eq!(read_i4().await, expected);
+    assert_eq!(n_requests(&log), 2 * fetched);
+
+    // Validation is retried like any other request
+    served.lock().unwrap().failures = 1;
+    let n_logged = log.lock().unwrap().len();
+    assert_eq!(read_i4().await, expected);
+    assert_eq!(log.lock().unwrap()[n_logged..], ["", "HEAD"]);
+
+    // Sources without a name can't be cached
+    match CachedSource::with_dir(std::path::Path::new(FILE), &dir) {
+        Err(Error::Unsupported(_)) => {}
+        other => panic!("Unexpected result {:?}", other),
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
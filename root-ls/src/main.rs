@@ -74,7 +74,21 @@ async fn inspect_file(f: &RootFile, sub_matches: &ArgMatches<'_>) {
         println!("Items in file:");
         for (i, item) in f.items().iter().enumerate() {
             if sub_matches.is_present("v") {
-                println!("{}: {}", i, item.verbose_info());
+                let d = item.datime();
+                println!(
+                    "{}: `{};{}` of type `{}`, title: `{}`, written {}-{:02}-{:02} {:02}:{:02}:{:02}",
+                    i,
+                    item.object_name(),
+                    item.cycle(),
+                    item.class_name(),
+                    item.title(),
+                    d.year,
+                    d.month,
+                    d.day,
+                    d.hour,
+                    d.minute,
+                    d.second
+                );
             } else {
                 println!("{}: {}", i, item.name());
             }